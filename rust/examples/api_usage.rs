@@ -1,4 +1,4 @@
-use opensecret::{KeyOptions, OpenSecretClient, Result, SigningAlgorithm};
+use opensecret::{KeyOptions, OpenSecretClient, Result, SigningAlgorithm, WordCount};
 use uuid::Uuid;
 
 #[tokio::main]
@@ -89,25 +89,17 @@ async fn main() -> Result<()> {
 
     // Generate default mnemonic (12 words)
     let private_key = client.get_private_key(None).await?;
-    let word_count = private_key.mnemonic.split_whitespace().count();
+    let word_count = private_key.word_count();
     println!("   ✓ Generated {} word mnemonic", word_count);
     println!(
         "   First 3 words: {}...",
-        private_key
-            .mnemonic
-            .split_whitespace()
-            .take(3)
-            .collect::<Vec<_>>()
-            .join(" ")
+        private_key.words()[..3].join(" ")
     );
 
-    // Generate with 24 words using BIP-85 derivation path
-    // The 4th segment in the path specifies word count (12, 18, or 24)
-    let options = KeyOptions {
-        private_key_derivation_path: None,
-        seed_phrase_derivation_path: Some("m/83696968'/39'/0'/24'/0'".to_string()),
-    };
-    let _key_24 = client.get_private_key(Some(options)).await?;
+    // Generate with 24 words using a BIP-85 child mnemonic
+    let _key_24 = client
+        .get_private_key_words(WordCount::TwentyFour, None)
+        .await?;
     println!("   ✓ Generated 24 word mnemonic via BIP-85");
 
     // Get raw private key bytes
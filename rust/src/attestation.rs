@@ -1,13 +1,21 @@
 use crate::cbor::{self, Value as CborValue};
+use crate::crypto;
 use crate::error::{Error, Result};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use ring::signature;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use x509_parser::prelude::*;
 
 // AWS Nitro Root Certificate (production)
 const AWS_NITRO_ROOT_CERT: &[u8] = include_bytes!("../assets/aws_nitro_root.der");
 
+/// Oldest attestation document accepted by default, measured from its
+/// `timestamp`.
+pub const DEFAULT_MAX_ATTESTATION_AGE: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttestationDocument {
     pub module_id: String,
@@ -21,9 +29,159 @@ pub struct AttestationDocument {
     pub nonce: Option<Vec<u8>>,
 }
 
+/// The raw components of a COSE_Sign1 attestation document.
+#[derive(Debug, Clone)]
+pub struct CoseSign1Parts {
+    /// Serialized protected header (a CBOR map inside a byte string).
+    pub protected: Vec<u8>,
+    /// CBOR encoding of the unprotected header map.
+    pub unprotected: Vec<u8>,
+    /// CBOR-encoded attestation document.
+    pub payload: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// Produces the nonce sent with each attestation request. The nonce must be
+/// unique per handshake in production; override it only to make handshakes
+/// deterministic in tests.
+pub trait NonceSource: Send + Sync {
+    fn next_nonce(&self) -> String;
+}
+
+/// Random UUIDv4 nonces. The default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomNonce;
+
+impl NonceSource for RandomNonce {
+    fn next_nonce(&self) -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+}
+
+/// Always returns the same nonce. For tests only: reusing a nonce lets a
+/// recorded attestation document be replayed.
+#[derive(Debug, Clone)]
+pub struct FixedNonce(pub String);
+
+impl NonceSource for FixedNonce {
+    fn next_nonce(&self) -> String {
+        self.0.clone()
+    }
+}
+
+/// Verified attestation documents shared between clients, so a new client
+/// against the same enclave can skip certificate-chain verification. Every
+/// handshake still fetches a document for its own nonce; see
+/// [`AttestationVerifier::verify_against_trusted`] for the checks that
+/// remain.
+///
+/// Entries are keyed by base URL and expected PCRs, and expire `ttl` after
+/// verification however often they are reused. Clients using mock
+/// attestation ignore the cache. Cloning shares the cache.
+///
+/// # Security
+///
+/// A cached entry vouches for the enclave's signing certificate for the
+/// whole `ttl`, even if that certificate is revoked or expires within the
+/// window. Keep `ttl` short, e.g. minutes, and do not use a cache where every
+/// document must be verified against the AWS Nitro root.
+#[derive(Debug, Clone)]
+pub struct AttestationCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, (Instant, AttestationDocument)>>>,
+}
+
+impl AttestationCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Default::default(),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Drops every entry, forcing the next handshake of each client to verify
+    /// afresh.
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+
+    /// The document cached under `key`, if it has not expired.
+    pub(crate) fn get(&self, key: &str) -> Option<AttestationDocument> {
+        let mut entries = self.entries.lock().ok()?;
+        match entries.get(key) {
+            Some((verified_at, document)) if verified_at.elapsed() < self.ttl => {
+                Some(document.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, key: String, document: AttestationDocument) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(key, (Instant::now(), document));
+        }
+    }
+
+    pub(crate) fn remove(&self, key: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(key);
+        }
+    }
+
+    /// Cache key for a client with these verification settings.
+    pub(crate) fn key(base_url: &str, expected_pcrs: Option<&HashMap<usize, Vec<u8>>>) -> String {
+        let mut key = base_url.to_string();
+        if let Some(pcrs) = expected_pcrs {
+            let mut pcrs: Vec<_> = pcrs.iter().collect();
+            pcrs.sort_by_key(|(index, _)| **index);
+            for (index, value) in pcrs {
+                key.push_str(&format!("|pcr{}={}", index, hex::encode(value)));
+            }
+        }
+        key
+    }
+}
+
+/// Checks the `nonce` field of an attestation document against the nonce the
+/// client sent. The client sends a text nonce (a UUID by default); enclaves
+/// store it either as its UTF-8 bytes, as hex of those bytes, or, for UUID and
+/// hex nonces, as the decoded raw bytes. All three encodings are accepted.
+pub fn nonce_matches(nonce_bytes: &[u8], expected_nonce: &str) -> bool {
+    let utf8 = crypto::constant_time_eq(nonce_bytes, expected_nonce.as_bytes());
+
+    let hex_of_text = hex::encode(expected_nonce.as_bytes());
+    let hex_text = std::str::from_utf8(nonce_bytes)
+        .map(|text| {
+            crypto::constant_time_eq(text.to_ascii_lowercase().as_bytes(), hex_of_text.as_bytes())
+        })
+        .unwrap_or(false);
+
+    let expected_hex: String = expected_nonce
+        .chars()
+        .filter(|c| *c != '-')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let raw =
+        crypto::constant_time_eq(hex::encode(nonce_bytes).as_bytes(), expected_hex.as_bytes());
+
+    utf8 || hex_text || raw
+}
+
 pub struct AttestationVerifier {
     expected_pcrs: Option<std::collections::HashMap<usize, Vec<u8>>>,
     allow_debug: bool,
+    max_age: std::time::Duration,
+    root_certificate: Vec<u8>,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -32,6 +190,8 @@ impl Default for AttestationVerifier {
         Self {
             expected_pcrs: None,
             allow_debug: cfg!(feature = "mock-attestation"),
+            max_age: DEFAULT_MAX_ATTESTATION_AGE,
+            root_certificate: AWS_NITRO_ROOT_CERT.to_vec(),
         }
     }
 }
@@ -46,15 +206,32 @@ impl AttestationVerifier {
         self
     }
 
-    pub fn verify_attestation_document(
-        &self,
-        document_b64: &str,
-        expected_nonce: &str,
-    ) -> Result<AttestationDocument> {
-        let document_bytes = BASE64.decode(document_b64)?;
+    /// Rejects documents whose `timestamp` is older than `max_age`.
+    /// Defaults to [`DEFAULT_MAX_ATTESTATION_AGE`].
+    pub fn with_max_age(mut self, max_age: std::time::Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
 
+    /// Trusts `root_der` instead of the embedded AWS Nitro root, e.g. for a
+    /// staging enclave or after a root rotation. The document's certificate
+    /// bundle must start with exactly this certificate.
+    pub fn with_root_certificate(mut self, root_der: Vec<u8>) -> Self {
+        self.root_certificate = root_der;
+        self
+    }
+
+    /// Splits a base64 COSE_Sign1 document into its four components without
+    /// verifying anything. Useful for inspecting a document whose signature
+    /// fails to verify; use [`Self::verify_attestation_document`] to trust it.
+    pub fn parse_cose_sign1(document_b64: &str) -> Result<CoseSign1Parts> {
+        Self::parse_cose_sign1_bytes(&BASE64.decode(document_b64)?)
+    }
+
+    /// [`Self::parse_cose_sign1`] for a document that is already decoded.
+    pub fn parse_cose_sign1_bytes(document: &[u8]) -> Result<CoseSign1Parts> {
         // Parse COSE_Sign1 structure
-        let cbor_value: CborValue = cbor::from_slice(&document_bytes)?;
+        let cbor_value: CborValue = cbor::from_slice(document)?;
 
         let cose_sign1 = match &cbor_value {
             CborValue::Array(arr) => arr,
@@ -73,7 +250,7 @@ impl AttestationVerifier {
 
         // Extract components
         let protected = match &cose_sign1[0] {
-            CborValue::Bytes(b) => b,
+            CborValue::Bytes(b) => b.clone(),
             _ => {
                 return Err(Error::AttestationVerificationFailed(
                     "Invalid protected header".to_string(),
@@ -81,8 +258,10 @@ impl AttestationVerifier {
             }
         };
 
+        let unprotected = cbor::to_vec(&cose_sign1[1])?;
+
         let payload = match &cose_sign1[2] {
-            CborValue::Bytes(b) => b,
+            CborValue::Bytes(b) => b.clone(),
             _ => {
                 return Err(Error::AttestationVerificationFailed(
                     "Invalid payload".to_string(),
@@ -91,7 +270,7 @@ impl AttestationVerifier {
         };
 
         let signature = match &cose_sign1[3] {
-            CborValue::Bytes(b) => b,
+            CborValue::Bytes(b) => b.clone(),
             _ => {
                 return Err(Error::AttestationVerificationFailed(
                     "Invalid signature".to_string(),
@@ -99,18 +278,92 @@ impl AttestationVerifier {
             }
         };
 
+        Ok(CoseSign1Parts {
+            protected,
+            unprotected,
+            payload,
+            signature,
+        })
+    }
+
+    pub fn verify_attestation_document(
+        &self,
+        document_b64: &str,
+        expected_nonce: &str,
+    ) -> Result<AttestationDocument> {
+        self.verify_document_bytes(&BASE64.decode(document_b64)?, expected_nonce)
+    }
+
+    /// Verifies raw COSE_Sign1 bytes, e.g. a document captured earlier by a
+    /// proxy, without a live handshake. Documents are still subject to the
+    /// freshness check, so auditing old captures needs a suitable
+    /// [`Self::with_max_age`].
+    pub fn verify_document_bytes(
+        &self,
+        cbor_bytes: &[u8],
+        expected_nonce: &str,
+    ) -> Result<AttestationDocument> {
+        let (parts, doc) = self.parse_fresh_document(cbor_bytes, expected_nonce)?;
+
+        // Verify certificate chain
+        self.verify_certificate_chain(&doc)?;
+
+        // Verify signature
+        self.verify_signature(&parts.protected, &parts.payload, &parts.signature, &doc)?;
+
+        // Verify PCRs if expected
+        if let Some(expected_pcrs) = &self.expected_pcrs {
+            self.verify_pcrs(&doc, expected_pcrs)?;
+        }
+
+        Ok(doc)
+    }
+
+    /// Verifies a document against `trusted`, an earlier document from the
+    /// same enclave that passed full verification. The nonce, freshness,
+    /// signature and PCR checks run as usual, but instead of verifying the
+    /// certificate chain the document must be signed with the same leaf
+    /// certificate as `trusted`.
+    pub fn verify_against_trusted(
+        &self,
+        document_b64: &str,
+        expected_nonce: &str,
+        trusted: &AttestationDocument,
+    ) -> Result<AttestationDocument> {
+        let (parts, doc) =
+            self.parse_fresh_document(&BASE64.decode(document_b64)?, expected_nonce)?;
+
+        if doc.certificate != trusted.certificate {
+            return Err(Error::AttestationVerificationFailed(
+                "Certificate does not match the trusted attestation document".to_string(),
+            ));
+        }
+
+        self.verify_signature(&parts.protected, &parts.payload, &parts.signature, &doc)?;
+
+        if let Some(expected_pcrs) = &self.expected_pcrs {
+            self.verify_pcrs(&doc, expected_pcrs)?;
+        }
+
+        Ok(doc)
+    }
+
+    /// Parses a COSE_Sign1 document and checks its nonce and freshness.
+    fn parse_fresh_document(
+        &self,
+        cbor_bytes: &[u8],
+        expected_nonce: &str,
+    ) -> Result<(CoseSign1Parts, AttestationDocument)> {
+        let parts = Self::parse_cose_sign1_bytes(cbor_bytes)?;
+
         // Parse attestation document from payload
-        let doc_cbor: CborValue = cbor::from_slice(payload)?;
+        let doc_cbor: CborValue = cbor::from_slice(&parts.payload)?;
 
         let doc = self.parse_attestation_document(&doc_cbor)?;
 
         // Verify nonce
         if let Some(nonce_bytes) = &doc.nonce {
-            let nonce_str = String::from_utf8(nonce_bytes.to_vec()).map_err(|e| {
-                Error::AttestationVerificationFailed(format!("Invalid nonce encoding: {}", e))
-            })?;
-
-            if nonce_str != expected_nonce {
+            if !nonce_matches(nonce_bytes, expected_nonce) {
                 return Err(Error::AttestationVerificationFailed(
                     "Nonce mismatch".to_string(),
                 ));
@@ -121,18 +374,32 @@ impl AttestationVerifier {
             ));
         }
 
-        // Verify certificate chain
-        self.verify_certificate_chain(&doc)?;
+        // Reject replayed documents
+        self.verify_freshness(&doc, chrono::Utc::now().timestamp_millis())?;
 
-        // Verify signature
-        self.verify_signature(protected, payload, signature, &doc)?;
+        Ok((parts, doc))
+    }
 
-        // Verify PCRs if expected
-        if let Some(expected_pcrs) = &self.expected_pcrs {
-            self.verify_pcrs(&doc, expected_pcrs)?;
+    /// Checks `doc.timestamp` (milliseconds since the epoch) against `now_ms`.
+    /// Mock documents carry no meaningful timestamp and are exempt.
+    fn verify_freshness(&self, doc: &AttestationDocument, now_ms: i64) -> Result<()> {
+        if doc.module_id.starts_with("mock-") {
+            return Ok(());
         }
 
-        Ok(doc)
+        let age_ms = u64::try_from(now_ms)
+            .unwrap_or(0)
+            .saturating_sub(doc.timestamp);
+        let age = std::time::Duration::from_millis(age_ms);
+        if age > self.max_age {
+            return Err(Error::AttestationVerificationFailed(format!(
+                "Attestation document is {}s old, exceeding the maximum age of {}s",
+                age.as_secs(),
+                self.max_age.as_secs()
+            )));
+        }
+
+        Ok(())
     }
 
     fn parse_attestation_document(&self, cbor: &CborValue) -> Result<AttestationDocument> {
@@ -293,16 +560,16 @@ impl AttestationVerifier {
             return Ok(());
         }
 
-        // Step 1: Verify the first cert in cabundle matches AWS Nitro root
+        // Step 1: Verify the first cert in cabundle matches the trusted root
         if doc.cabundle.is_empty() {
             return Err(Error::AttestationVerificationFailed(
                 "Certificate bundle is empty".to_string(),
             ));
         }
 
-        if doc.cabundle[0] != AWS_NITRO_ROOT_CERT {
+        if doc.cabundle[0] != self.root_certificate {
             return Err(Error::AttestationVerificationFailed(
-                "First certificate does not match AWS Nitro root certificate".to_string(),
+                "First certificate does not match the trusted root certificate".to_string(),
             ));
         }
 
@@ -506,13 +773,18 @@ impl AttestationVerifier {
         doc: &AttestationDocument,
         expected: &std::collections::HashMap<usize, Vec<u8>>,
     ) -> Result<()> {
-        for (index, expected_value) in expected {
+        let mut indices: Vec<&usize> = expected.keys().collect();
+        indices.sort();
+        for index in indices {
+            let expected_value = &expected[index];
             match doc.pcrs.get(index) {
                 Some(actual_value) => {
-                    if actual_value != expected_value {
+                    if !crypto::constant_time_eq(actual_value, expected_value) {
                         return Err(Error::AttestationVerificationFailed(format!(
-                            "PCR{} mismatch",
-                            index
+                            "PCR{} mismatch: expected {}, got {}",
+                            index,
+                            hex::encode(expected_value),
+                            hex::encode(actual_value)
                         )));
                     }
                 }
@@ -659,3 +931,260 @@ pub fn create_mock_attestation_document(nonce: &str) -> Result<String> {
     let cose_bytes = cbor::to_vec(&CborValue::Array(cose_sign1))?;
     Ok(BASE64.encode(cose_bytes))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONCE: &str = "3f2504e0-4f89-11d3-9a0c-0305e82c3301";
+
+    #[test]
+    fn test_nonce_matches_utf8_and_hex_encodings() {
+        assert!(nonce_matches(NONCE.as_bytes(), NONCE));
+        assert!(nonce_matches(hex::encode(NONCE).as_bytes(), NONCE));
+        assert!(nonce_matches(
+            hex::encode(NONCE).to_uppercase().as_bytes(),
+            NONCE
+        ));
+    }
+
+    #[test]
+    fn test_nonce_matches_raw_uuid_bytes() {
+        let raw = uuid::Uuid::parse_str(NONCE).unwrap();
+        assert!(nonce_matches(raw.as_bytes(), NONCE));
+        assert!(nonce_matches(&[0xab, 0xcd], "abcd"));
+    }
+
+    #[test]
+    fn test_nonce_matches_rejects_other_nonces() {
+        let other = "3f2504e0-4f89-11d3-9a0c-0305e82c3302";
+        assert!(!nonce_matches(NONCE.as_bytes(), other));
+        assert!(!nonce_matches(hex::encode(NONCE).as_bytes(), other));
+        assert!(!nonce_matches(
+            uuid::Uuid::parse_str(NONCE).unwrap().as_bytes(),
+            other
+        ));
+        assert!(!nonce_matches(b"", NONCE));
+    }
+
+    #[test]
+    fn test_parse_cose_sign1_splits_components() {
+        let payload = cbor::to_vec(&CborValue::Map(vec![(
+            CborValue::Text("nonce".to_string()),
+            CborValue::Bytes(b"nonce".to_vec()),
+        )]))
+        .unwrap();
+        let document = BASE64.encode(
+            cbor::to_vec(&CborValue::Array(vec![
+                CborValue::Bytes(vec![0u8; 32]),
+                CborValue::Map(Vec::new()),
+                CborValue::Bytes(payload.clone()),
+                CborValue::Bytes(vec![0u8; 64]),
+            ]))
+            .unwrap(),
+        );
+        let parts = AttestationVerifier::parse_cose_sign1(&document).unwrap();
+
+        assert_eq!(parts.protected, vec![0u8; 32]);
+        assert_eq!(parts.signature, vec![0u8; 64]);
+        assert_eq!(parts.payload, payload);
+        let unprotected: CborValue = cbor::from_slice(&parts.unprotected).unwrap();
+        assert!(matches!(unprotected, CborValue::Map(_)));
+
+        let not_cose = BASE64.encode(cbor::to_vec(&CborValue::Array(vec![])).unwrap());
+        assert!(AttestationVerifier::parse_cose_sign1(&not_cose).is_err());
+    }
+
+    #[test]
+    fn test_verify_pcrs_reports_index_and_values() {
+        let doc = AttestationDocument {
+            module_id: "i-0123".to_string(),
+            timestamp: 0,
+            digest: "SHA384".to_string(),
+            pcrs: [(0, vec![0xaa; 2]), (1, vec![0xbb; 2])]
+                .into_iter()
+                .collect(),
+            certificate: vec![],
+            cabundle: vec![],
+            public_key: None,
+            user_data: None,
+            nonce: None,
+        };
+        let verifier = AttestationVerifier::new();
+
+        let matching = [(0, vec![0xaa; 2])].into_iter().collect();
+        assert!(verifier.verify_pcrs(&doc, &matching).is_ok());
+
+        let mismatched = [(0, vec![0xaa; 2]), (1, vec![0xcc; 2])]
+            .into_iter()
+            .collect();
+        let error = verifier.verify_pcrs(&doc, &mismatched).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Attestation verification failed: PCR1 mismatch: expected cccc, got bbbb"
+        );
+
+        let missing = [(2, vec![0u8; 2])].into_iter().collect();
+        assert!(verifier.verify_pcrs(&doc, &missing).is_err());
+    }
+
+    #[test]
+    fn test_verify_freshness_rejects_old_documents() {
+        let now_ms = 1_700_000_000_000;
+        let mut doc = AttestationDocument {
+            module_id: "i-0123".to_string(),
+            timestamp: now_ms as u64 - 60_000,
+            digest: "SHA384".to_string(),
+            pcrs: std::collections::HashMap::new(),
+            certificate: vec![],
+            cabundle: vec![],
+            public_key: None,
+            user_data: None,
+            nonce: None,
+        };
+        let verifier = AttestationVerifier::new();
+        assert!(verifier.verify_freshness(&doc, now_ms).is_ok());
+
+        doc.timestamp = now_ms as u64 - 3_600_000;
+        let error = verifier.verify_freshness(&doc, now_ms).unwrap_err();
+        assert!(error.to_string().contains("3600s old"));
+
+        let lenient = AttestationVerifier::new().with_max_age(std::time::Duration::from_secs(7200));
+        assert!(lenient.verify_freshness(&doc, now_ms).is_ok());
+
+        doc.module_id = "mock-module".to_string();
+        assert!(verifier.verify_freshness(&doc, now_ms).is_ok());
+    }
+
+    #[test]
+    fn test_verify_certificate_chain_with_custom_root() {
+        let root = include_bytes!("../assets/test/test_root.der").to_vec();
+        let doc = AttestationDocument {
+            module_id: "i-0123".to_string(),
+            timestamp: 0,
+            digest: "SHA384".to_string(),
+            pcrs: std::collections::HashMap::new(),
+            certificate: include_bytes!("../assets/test/test_leaf.der").to_vec(),
+            cabundle: vec![root.clone()],
+            public_key: None,
+            user_data: None,
+            nonce: None,
+        };
+
+        let error = AttestationVerifier::new()
+            .verify_certificate_chain(&doc)
+            .unwrap_err();
+        assert!(error.to_string().contains("trusted root certificate"));
+
+        let verifier = AttestationVerifier::new().with_root_certificate(root);
+        verifier.verify_certificate_chain(&doc).unwrap();
+
+        let mut forged = doc.clone();
+        forged.certificate = AWS_NITRO_ROOT_CERT.to_vec();
+        assert!(verifier.verify_certificate_chain(&forged).is_err());
+    }
+
+    #[test]
+    fn test_verify_document_bytes_matches_base64_entry_point() {
+        let payload = cbor::to_vec(&CborValue::Map(vec![
+            (
+                CborValue::Text("module_id".to_string()),
+                CborValue::Text("i-0123".to_string()),
+            ),
+            (
+                CborValue::Text("nonce".to_string()),
+                CborValue::Bytes(NONCE.as_bytes().to_vec()),
+            ),
+        ]))
+        .unwrap();
+        let document = cbor::to_vec(&CborValue::Array(vec![
+            CborValue::Bytes(vec![]),
+            CborValue::Map(Vec::new()),
+            CborValue::Bytes(payload),
+            CborValue::Bytes(vec![0u8; 64]),
+        ]))
+        .unwrap();
+        let verifier = AttestationVerifier::new().with_max_age(std::time::Duration::MAX);
+
+        let error = verifier
+            .verify_document_bytes(&document, "other-nonce")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Attestation verification failed: Nonce mismatch"
+        );
+
+        let from_bytes = verifier
+            .verify_document_bytes(&document, NONCE)
+            .unwrap_err();
+        let from_base64 = verifier
+            .verify_attestation_document(&BASE64.encode(&document), NONCE)
+            .unwrap_err();
+        assert_eq!(from_bytes.to_string(), from_base64.to_string());
+        assert!(from_bytes
+            .to_string()
+            .contains("Certificate bundle is empty"));
+    }
+
+    #[test]
+    fn test_verify_against_trusted_skips_only_the_certificate_chain() {
+        // A P-384 certificate that does not chain as a leaf.
+        let leaf = AWS_NITRO_ROOT_CERT.to_vec();
+        let payload = cbor::to_vec(&CborValue::Map(vec![
+            (
+                CborValue::Text("module_id".to_string()),
+                CborValue::Text("i-0123".to_string()),
+            ),
+            (
+                CborValue::Text("certificate".to_string()),
+                CborValue::Bytes(leaf.clone()),
+            ),
+            (
+                CborValue::Text("nonce".to_string()),
+                CborValue::Bytes(NONCE.as_bytes().to_vec()),
+            ),
+        ]))
+        .unwrap();
+        let document = BASE64.encode(
+            cbor::to_vec(&CborValue::Array(vec![
+                CborValue::Bytes(vec![]),
+                CborValue::Map(Vec::new()),
+                CborValue::Bytes(payload),
+                CborValue::Bytes(vec![0u8; 96]),
+            ]))
+            .unwrap(),
+        );
+        let mut trusted = AttestationDocument {
+            module_id: "i-0123".to_string(),
+            timestamp: 0,
+            digest: "SHA384".to_string(),
+            pcrs: std::collections::HashMap::new(),
+            certificate: leaf,
+            cabundle: vec![],
+            public_key: None,
+            user_data: None,
+            nonce: None,
+        };
+        let verifier = AttestationVerifier::new().with_max_age(std::time::Duration::MAX);
+
+        let error = verifier
+            .verify_against_trusted(&document, "other-nonce", &trusted)
+            .unwrap_err();
+        assert!(error.to_string().contains("Nonce mismatch"));
+
+        // Verification gets past the certificate chain to the (bogus)
+        // signature.
+        let error = verifier
+            .verify_against_trusted(&document, NONCE, &trusted)
+            .unwrap_err();
+        assert!(error.to_string().contains("Signature verification failed"));
+
+        trusted.certificate = include_bytes!("../assets/test/test_leaf.der").to_vec();
+        let error = verifier
+            .verify_against_trusted(&document, NONCE, &trusted)
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Certificate does not match the trusted attestation document"));
+    }
+}
@@ -0,0 +1,195 @@
+//! A blocking facade over [`crate::OpenSecretClient`] for scripts and CLI
+//! tools that do not otherwise use async.
+//!
+//! Each call runs the async client to completion on a runtime owned by the
+//! blocking client. The async client stays the primary API; methods not
+//! wrapped here are reachable through [`OpenSecretClient::call`].
+//!
+//! # Panics
+//!
+//! The blocking client must not be used, or dropped, from within an async
+//! context (a Tokio runtime): blocking on a runtime inside another panics.
+//! Use the async client there instead.
+//!
+//! ```ignore
+//! use opensecret::blocking::OpenSecretClient;
+//!
+//! let client = OpenSecretClient::new("https://api.opensecret.cloud")?;
+//! client.perform_attestation_handshake()?;
+//! client.login(email, password, client_id)?;
+//! let value = client.kv_get("theme")?;
+//! let models = client.call(|client| client.get_models())?;
+//! ```
+
+use crate::error::Result;
+use crate::types::*;
+use std::future::Future;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+pub struct OpenSecretClient {
+    inner: crate::OpenSecretClient,
+    runtime: Runtime,
+}
+
+impl OpenSecretClient {
+    pub fn new(base_url: impl Into<String>) -> Result<Self> {
+        Self::from_async(crate::OpenSecretClient::new(base_url)?)
+    }
+
+    pub fn new_with_api_key(base_url: impl Into<String>, api_key: String) -> Result<Self> {
+        Self::from_async(crate::OpenSecretClient::new_with_api_key(
+            base_url, api_key,
+        )?)
+    }
+
+    /// Wraps an async client configured with
+    /// [`crate::OpenSecretClient::builder`].
+    pub fn from_async(inner: crate::OpenSecretClient) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// The wrapped async client, for its synchronous accessors.
+    pub fn inner(&self) -> &crate::OpenSecretClient {
+        &self.inner
+    }
+
+    /// Runs any async client method to completion, e.g.
+    /// `client.call(|client| client.get_models())`.
+    pub fn call<'a, F>(&'a self, f: impl FnOnce(&'a crate::OpenSecretClient) -> F) -> F::Output
+    where
+        F: Future,
+    {
+        self.runtime.block_on(f(&self.inner))
+    }
+
+    pub fn perform_attestation_handshake(&self) -> Result<()> {
+        self.call(|client| client.perform_attestation_handshake())
+    }
+
+    pub fn login(&self, email: String, password: String, client_id: Uuid) -> Result<LoginResponse> {
+        self.call(|client| client.login(email, password, client_id))
+    }
+
+    pub fn login_with_id(
+        &self,
+        id: Uuid,
+        password: String,
+        client_id: Uuid,
+    ) -> Result<LoginResponse> {
+        self.call(|client| client.login_with_id(id, password, client_id))
+    }
+
+    pub fn register(
+        &self,
+        email: String,
+        password: String,
+        client_id: Uuid,
+        name: Option<String>,
+    ) -> Result<LoginResponse> {
+        self.call(|client| client.register(email, password, client_id, name))
+    }
+
+    pub fn register_guest(&self, password: String, client_id: Uuid) -> Result<LoginResponse> {
+        self.call(|client| client.register_guest(password, client_id))
+    }
+
+    pub fn refresh_token(&self) -> Result<()> {
+        self.call(|client| client.refresh_token())
+    }
+
+    pub fn logout(&self) -> Result<()> {
+        self.call(|client| client.logout())
+    }
+
+    pub fn get_user(&self) -> Result<UserResponse> {
+        self.call(|client| client.get_user())
+    }
+
+    pub fn kv_get(&self, key: &str) -> Result<String> {
+        self.call(|client| client.kv_get(key))
+    }
+
+    pub fn kv_put(&self, key: &str, value: String) -> Result<String> {
+        self.call(|client| client.kv_put(key, value))
+    }
+
+    pub fn kv_delete(&self, key: &str) -> Result<()> {
+        self.call(|client| client.kv_delete(key))
+    }
+
+    pub fn kv_list(&self) -> Result<Vec<KVListItem>> {
+        self.call(|client| client.kv_list())
+    }
+
+    pub fn get_models(&self) -> Result<ModelsResponse> {
+        self.call(|client| client.get_models())
+    }
+
+    pub fn create_chat_completion(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse> {
+        self.call(|client| client.create_chat_completion(request))
+    }
+
+    pub fn create_embeddings(&self, request: EmbeddingRequest) -> Result<EmbeddingResponse> {
+        self.call(|client| client.create_embeddings(request))
+    }
+
+    pub fn get_access_token(&self) -> Result<Option<String>> {
+        self.inner.get_access_token()
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>> {
+        self.inner.get_session_id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::SessionCipher;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_blocking_client_runs_calls_without_a_runtime() {
+        let server_runtime = Runtime::new().unwrap();
+        let mock_server = server_runtime.block_on(MockServer::start());
+        let session_key = [63u8; 32];
+        let sealed = SessionCipher::new(session_key)
+            .seal_response(&"dark")
+            .unwrap();
+        server_runtime.block_on(
+            Mock::given(method("GET"))
+                .and(path("/protected/kv/theme"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(serde_json::json!({ "encrypted": sealed.encrypted })),
+                )
+                .expect(1)
+                .mount(&mock_server),
+        );
+
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        client
+            .inner()
+            .import_session(SerializedSession {
+                session_id: Uuid::new_v4(),
+                session_key,
+                access_token: Some("access_token".to_string()),
+                refresh_token: None,
+            })
+            .unwrap();
+
+        assert_eq!(client.kv_get("theme").unwrap(), "dark");
+        assert_eq!(
+            client.get_access_token().unwrap().as_deref(),
+            Some("access_token")
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Capture of encrypted request/response envelopes for offline debugging.
+//!
+//! **Development only.** Every captured request record includes the session
+//! key, so anyone holding the capture file can decrypt the whole session:
+//! prompts, responses, tokens and key material. Never enable the `dev-capture`
+//! feature in a build that talks to production accounts, and delete capture
+//! files once the investigation is over.
+//!
+//! Records are written as JSON lines. Bodies of streaming (SSE) responses are
+//! not captured: their record carries the status and the body `<stream>`. On
+//! Unix the file is created readable by its owner only.
+
+use crate::error::{Error, Result};
+use crate::types::SessionState;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Serialize)]
+#[serde(tag = "direction", rename_all = "snake_case")]
+enum CaptureRecord<'a> {
+    Request {
+        timestamp: String,
+        session_id: String,
+        /// Base64 session key. This is what makes the capture decryptable.
+        session_key: String,
+        method: &'a str,
+        endpoint: &'a str,
+        envelope: Option<&'a str>,
+    },
+    Response {
+        timestamp: String,
+        method: &'a str,
+        endpoint: &'a str,
+        status: u16,
+        body: &'a str,
+    },
+}
+
+/// Append-only JSON-lines file of encrypted envelopes.
+pub struct CaptureFile {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl CaptureFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(&path).map_err(|e| {
+            Error::Configuration(format!(
+                "Failed to open capture file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        tracing::warn!(
+            "dev-capture: writing session keys and encrypted traffic to {}",
+            path.display()
+        );
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn record_request(
+        &self,
+        session: &SessionState,
+        method: &str,
+        endpoint: &str,
+        envelope: Option<&str>,
+    ) {
+        self.write(&CaptureRecord::Request {
+            timestamp: Utc::now().to_rfc3339(),
+            session_id: session.session_id.to_string(),
+            session_key: BASE64.encode(session.session_key),
+            method,
+            endpoint,
+            envelope,
+        });
+    }
+
+    pub(crate) fn record_response(&self, method: &str, endpoint: &str, status: u16, body: &str) {
+        self.write(&CaptureRecord::Response {
+            timestamp: Utc::now().to_rfc3339(),
+            method,
+            endpoint,
+            status,
+            body,
+        });
+    }
+
+    // Capture is best effort: a failed write must never fail the API call.
+    fn write(&self, record: &CaptureRecord<'_>) {
+        let result = serde_json::to_string(record)
+            .map_err(std::io::Error::other)
+            .and_then(|line| {
+                let mut file = self
+                    .file
+                    .lock()
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
+                writeln!(file, "{}", line)
+            });
+
+        if let Err(e) = result {
+            tracing::warn!(
+                "dev-capture: failed to write {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
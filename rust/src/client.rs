@@ -1,19 +1,31 @@
 use crate::{
-    attestation::{AttestationDocument, AttestationVerifier},
+    attestation::{
+        AttestationCache, AttestationDocument, AttestationVerifier, NonceSource, RandomNonce,
+    },
     cbor::{self, Value as CborValue},
     crypto::{self},
     error::{Error, Result},
+    monitor::{ConnectionState, HealthMonitor},
     session::SessionManager,
+    sse::{apply_error_policy, cancel_on, EventDecoder, JsonReassembler},
+    stream::{track_usage, StreamHandle, UsageHandle},
     types::*,
 };
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::{Stream, StreamExt};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use reqwest::{
     header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
     Client,
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, RwLock,
+};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 pub struct OpenSecretClient {
@@ -22,8 +34,56 @@ pub struct OpenSecretClient {
     session_manager: SessionManager,
     use_mock_attestation: bool,
     server_public_key: Arc<RwLock<Option<Vec<u8>>>>, // Store server's public key from attestation
+    attestation_document: Arc<RwLock<Option<AttestationDocument>>>,
+    /// Held for the duration of a handshake so that concurrent handshakes do
+    /// not interleave their writes to the server key and session.
+    handshake: Arc<tokio::sync::Mutex<()>>,
+    strict_model: bool,
+    stream_error_policy: StreamErrorPolicy,
+    credential_override: Option<Credential>,
+    max_derivation_depth: usize,
+    nonce_source: Arc<dyn NonceSource>,
+    accounts: Arc<RwLock<HashMap<String, SessionManager>>>,
+    cancel: Arc<RwLock<CancellationToken>>,
+    require_real_attestation: bool,
+    expected_pcrs: Option<HashMap<usize, Vec<u8>>>,
+    attestation_cache: Option<AttestationCache>,
+    auto_refresh: bool,
+    auto_rehandshake: bool,
+    timeout: Option<std::time::Duration>,
+    stream_timeout: Option<std::time::Duration>,
+    retry: Option<RetryConfig>,
+    upload_progress: Option<Arc<Mutex<ProgressCallback>>>,
+    #[cfg(feature = "dev-capture")]
+    capture: Option<Arc<crate::capture::CaptureFile>>,
 }
 
+/// Default limit on the number of components in a derivation path.
+pub const DEFAULT_MAX_DERIVATION_DEPTH: usize = 10;
+
+/// Maximum number of embedding sub-batches in flight in
+/// [`OpenSecretClient::create_embeddings_resumable`].
+pub const EMBEDDING_BATCH_CONCURRENCY: usize = 4;
+
+/// `(index, vector)` pairs yielded by
+/// [`OpenSecretClient::create_embeddings_resumable`].
+pub type EmbeddingStream<'a> = Pin<Box<dyn Stream<Item = Result<(usize, Vec<f32>)>> + Send + 'a>>;
+
+/// Maximum number of concurrent requests issued by
+/// [`OpenSecretClient::get_public_keys`].
+pub const PUBLIC_KEY_BATCH_SIZE: usize = 16;
+
+/// Maximum number of concurrent requests issued by
+/// [`OpenSecretClient::sign_messages`].
+pub const SIGN_MESSAGES_CONCURRENCY: usize = 8;
+
+/// Upload progress callback, called with the bytes sent so far and the total
+/// request size when known.
+pub type ProgressCallback = Box<dyn FnMut(u64, Option<u64>) + Send>;
+
+/// Size of the pieces an upload with a progress callback is sent in.
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
 fn append_query_param(query: &mut Vec<String>, key: &str, value: impl ToString) {
     let encoded = utf8_percent_encode(&value.to_string(), NON_ALPHANUMERIC).to_string();
     query.push(format!("{}={}", key, encoded));
@@ -142,6 +202,260 @@ fn build_conversation_projects_endpoint(params: Option<&ConversationProjectListP
     endpoint
 }
 
+/// The `exp` claim of a JWT, read without verifying the signature.
+fn jwt_expiry(token: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    let payload = token.split('.').nth(1)?;
+    let claims: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?)
+            .ok()?;
+    let exp = claims.get("exp")?;
+    let seconds = exp
+        .as_i64()
+        .or_else(|| exp.as_f64().map(|exp| exp as i64))?;
+    chrono::DateTime::from_timestamp(seconds, 0)
+}
+
+/// POST endpoints that only compute a result, so a request the server did
+/// not process can be sent again under [`OpenSecretClient::with_retry`].
+const RETRY_SAFE_POST_ENDPOINTS: &[&str] = &["/v1/chat/completions", "/v1/embeddings"];
+
+/// Which failures of a request [`send_with_retry`] may send again.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RetryMode {
+    /// Any transient status, connection failure or timeout.
+    Idempotent,
+    /// Only failures where the server cannot have processed (and billed)
+    /// the request: connection failures and 502/503 responses.
+    Unprocessed,
+}
+
+fn retry_mode(method: &str, endpoint: &str) -> Option<RetryMode> {
+    match method {
+        "GET" => Some(RetryMode::Idempotent),
+        "POST" if RETRY_SAFE_POST_ENDPOINTS.contains(&endpoint) => Some(RetryMode::Unprocessed),
+        _ => None,
+    }
+}
+
+/// The JSON request body for `data`, which is encrypted as-is.
+fn to_json_body<T: Serialize>(data: Option<T>) -> Result<Option<Vec<u8>>> {
+    data.map(|data| serde_json::to_vec(&data))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// MIME type for an audio file, from its extension.
+fn audio_content_type(filename: &str) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp3" | "mpga" | "mpeg" => "audio/mpeg",
+        "m4a" | "mp4" => "audio/mp4",
+        "wav" => "audio/wav",
+        "webm" => "audio/webm",
+        "ogg" | "oga" => "audio/ogg",
+        "flac" => "audio/flac",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Streams `body` in [`UPLOAD_CHUNK_SIZE`] pieces, reporting each piece to
+/// `progress` as it is handed to the connection.
+fn progress_body(body: Vec<u8>, progress: Arc<Mutex<ProgressCallback>>) -> reqwest::Body {
+    let total = body.len() as u64;
+    let body = bytes::Bytes::from(body);
+    let mut sent = 0u64;
+    let chunks = (0..body.len())
+        .step_by(UPLOAD_CHUNK_SIZE)
+        .map(move |start| body.slice(start..(start + UPLOAD_CHUNK_SIZE).min(body.len())));
+    let stream = futures::stream::iter(chunks).map(move |chunk| {
+        sent += chunk.len() as u64;
+        if let Ok(mut progress) = progress.lock() {
+            (*progress)(sent, Some(total));
+        }
+        Ok::<_, std::io::Error>(chunk)
+    });
+    reqwest::Body::wrap_stream(stream)
+}
+
+/// Whether an error response says the server does not know the session,
+/// rather than rejecting the request itself.
+fn is_session_expired_response(status: u16, body: &str) -> bool {
+    let body = body.to_ascii_lowercase();
+    matches!(status, 400 | 401)
+        && body.contains("session")
+        && ["expired", "not found", "invalid", "unknown"]
+            .iter()
+            .any(|reason| body.contains(reason))
+}
+
+fn is_transient_status(status: reqwest::StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// Parses a `Retry-After` value, either delay seconds or an HTTP-date.
+fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Exponential backoff for retry number `attempt` (starting at 0), with up
+/// to half of the delay randomized away.
+fn retry_delay(retry: &RetryConfig, attempt: u32) -> std::time::Duration {
+    use p256::elliptic_curve::rand_core::{OsRng, RngCore};
+
+    let delay = retry
+        .base_delay
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(retry.max_delay);
+    let jitter = f64::from(OsRng.next_u32()) / f64::from(u32::MAX);
+    delay.mul_f64(1.0 - jitter / 2.0)
+}
+
+/// Sends `request`, retrying the failures `mode` allows as configured by
+/// `retry`.
+async fn send_with_retry(
+    request: reqwest::RequestBuilder,
+    retry: &RetryConfig,
+    mode: RetryMode,
+) -> Result<reqwest::Response> {
+    let retry_status = |status: reqwest::StatusCode| match mode {
+        RetryMode::Idempotent => is_transient_status(status),
+        RetryMode::Unprocessed => matches!(status.as_u16(), 502 | 503),
+    };
+    let retry_error =
+        |e: &reqwest::Error| e.is_connect() || (mode == RetryMode::Idempotent && e.is_timeout());
+    let mut attempt = 0;
+    loop {
+        let Some(this_attempt) = request.try_clone() else {
+            return Ok(request.send().await?);
+        };
+        let delay = match this_attempt.send().await {
+            Ok(response) if attempt < retry.max_retries && retry_status(response.status()) => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after);
+                match retry_after {
+                    Some(delay) if delay > retry.max_delay => return Ok(response),
+                    Some(delay) => delay,
+                    None => retry_delay(retry, attempt),
+                }
+            }
+            Err(e) if attempt < retry.max_retries && retry_error(&e) => retry_delay(retry, attempt),
+            result => return Ok(result?),
+        };
+        attempt += 1;
+        tracing::debug!("Retrying request (attempt {}) in {:?}", attempt, delay);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Whether the host of `base_url` is a loopback address, `localhost`, the
+/// unspecified address or the Android emulator's alias for the host machine.
+fn is_local_host(base_url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(base_url) else {
+        return false;
+    };
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    if host == "localhost" {
+        return true;
+    }
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<std::net::IpAddr>()
+        .is_ok_and(|ip| {
+            ip.is_loopback() || ip.is_unspecified() || ip == std::net::IpAddr::from([10, 0, 2, 2])
+        })
+}
+
+/// Validates `base_url` and strips trailing slashes. A path prefix such as
+/// `https://host/opensecret` is kept, so endpoints resolve beneath it.
+fn normalize_base_url(base_url: &str) -> Result<String> {
+    let url = reqwest::Url::parse(base_url.trim())
+        .map_err(|e| Error::Configuration(format!("Invalid base URL '{}': {}", base_url, e)))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::Configuration(format!(
+            "Base URL must use http or https, got '{}'",
+            url.scheme()
+        )));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(Error::Configuration(
+            "Base URL must not contain a query string or fragment".to_string(),
+        ));
+    }
+
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+fn parse_agent_payload<T: DeserializeOwned>(json_str: &str, kind: &str) -> Result<T> {
+    serde_json::from_str(json_str).map_err(|e| Error::Api {
+        status: 0,
+        message: format!("Failed to parse agent {}: {}", kind, e),
+    })
+}
+
+/// Maps a decrypted agent SSE payload to its typed event. Unknown event types
+/// are skipped.
+fn parse_agent_event(event_type: &str, json_str: &str) -> Option<Result<AgentSseEvent>> {
+    let event = match event_type {
+        "agent.message" => parse_agent_payload(json_str, "message").map(AgentSseEvent::Message),
+        "agent.reaction" => parse_agent_payload(json_str, "reaction").map(AgentSseEvent::Reaction),
+        "agent.typing" => parse_agent_payload(json_str, "typing").map(AgentSseEvent::Typing),
+        "agent.done" => parse_agent_payload(json_str, "done").map(AgentSseEvent::Done),
+        "agent.error" => parse_agent_payload(json_str, "error").map(AgentSseEvent::Error),
+        _ => return None,
+    };
+    Some(event)
+}
+
+fn normalize_model_id(model: &str) -> String {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    let model = match model.len().checked_sub(11) {
+        Some(split) if is_date_suffix(&model.as_bytes()[split..]) => &model[..split],
+        _ => model,
+    };
+    model
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Whether `suffix` is a `-YYYY-MM-DD` snapshot date.
+fn is_date_suffix(suffix: &[u8]) -> bool {
+    suffix.len() == 11
+        && suffix.iter().enumerate().all(|(i, b)| match i {
+            0 | 5 | 8 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+/// Whether a model id returned by the server is the one that was requested,
+/// ignoring vendor prefixes (`meta-llama/`), punctuation (`llama-3.3` vs
+/// `llama3-3`) and dated snapshot suffixes (`gpt-4o-2024-08-06`).
+fn models_match(requested: &str, returned: &str) -> bool {
+    let requested = normalize_model_id(requested);
+    !requested.is_empty() && requested == normalize_model_id(returned)
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum AuthHeaderMode {
     None,
@@ -149,39 +463,468 @@ enum AuthHeaderMode {
     ApiKeyOrJwt,
 }
 
+/// Configures an [`OpenSecretClient`]; see [`OpenSecretClient::builder`].
+///
+/// ```ignore
+/// let client = OpenSecretClient::builder()
+///     .base_url("https://enclave.example.com")
+///     .api_key(api_key)
+///     .timeout(Duration::from_secs(30))
+///     .build()?;
+/// ```
+#[derive(Default)]
+pub struct OpenSecretClientBuilder {
+    base_url: Option<String>,
+    api_key: Option<String>,
+    timeout: Option<std::time::Duration>,
+    expected_pcrs: Option<HashMap<usize, Vec<u8>>>,
+    mock_attestation: Option<bool>,
+    http_client: Option<Client>,
+    pinned_tls_spki: Option<Vec<Vec<u8>>>,
+    auto_refresh: Option<bool>,
+    auto_rehandshake: Option<bool>,
+}
+
+impl OpenSecretClientBuilder {
+    /// The enclave's base URL. Required.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// See [`OpenSecretClient::with_timeout`].
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// See [`OpenSecretClient::with_expected_pcrs`].
+    pub fn expected_pcrs(mut self, pcrs: HashMap<usize, Vec<u8>>) -> Self {
+        self.expected_pcrs = Some(pcrs);
+        self
+    }
+
+    /// See [`OpenSecretClient::with_mock_attestation`].
+    pub fn mock_attestation(mut self, mock_attestation: bool) -> Self {
+        self.mock_attestation = Some(mock_attestation);
+        self
+    }
+
+    /// See [`OpenSecretClient::with_http_client`].
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Requires the server's TLS certificate to carry one of `pins`, each the
+    /// SHA-256 of a DER `SubjectPublicKeyInfo` (see [`crate::tls::spki_sha256`]).
+    /// The usual WebPKI chain and hostname checks still apply; connections to a
+    /// server with any other key fail during the TLS handshake.
+    ///
+    /// Pinning is part of the HTTP client this builder creates, so it cannot
+    /// be combined with [`Self::http_client`]; `build` rejects that.
+    pub fn pinned_tls_spki(mut self, pins: Vec<Vec<u8>>) -> Self {
+        self.pinned_tls_spki = Some(pins);
+        self
+    }
+
+    /// See [`OpenSecretClient::with_auto_refresh`].
+    pub fn auto_refresh(mut self, auto_refresh: bool) -> Self {
+        self.auto_refresh = Some(auto_refresh);
+        self
+    }
+
+    /// See [`OpenSecretClient::with_auto_rehandshake`].
+    pub fn auto_rehandshake(mut self, auto_rehandshake: bool) -> Self {
+        self.auto_rehandshake = Some(auto_rehandshake);
+        self
+    }
+
+    pub fn build(self) -> Result<OpenSecretClient> {
+        let base_url = self
+            .base_url
+            .ok_or_else(|| Error::Configuration("A base URL is required".to_string()))?;
+        let session_manager = match self.api_key {
+            Some(api_key) => SessionManager::new_with_api_key(api_key),
+            None => SessionManager::new(),
+        };
+        let http_client = match (self.http_client, self.pinned_tls_spki) {
+            (Some(_), Some(_)) => {
+                return Err(Error::Configuration(
+                    "TLS pinning cannot be applied to a custom HTTP client".to_string(),
+                ))
+            }
+            (Some(client), None) => client,
+            (None, Some(pins)) => {
+                let config = crate::tls::PinnedSpkiVerifier::new(pins)?.into_client_config();
+                Client::builder()
+                    .use_preconfigured_tls(config)
+                    .build()
+                    .map_err(|e| {
+                        Error::Configuration(format!("Failed to build HTTP client: {}", e))
+                    })?
+            }
+            (None, None) => Client::default(),
+        };
+        let mut client = OpenSecretClient::with_session_manager(
+            base_url,
+            session_manager,
+            http_client,
+            self.mock_attestation,
+        )?;
+        client.timeout = self.timeout;
+        client.expected_pcrs = self.expected_pcrs;
+        if let Some(auto_refresh) = self.auto_refresh {
+            client.auto_refresh = auto_refresh;
+        }
+        if let Some(auto_rehandshake) = self.auto_rehandshake {
+            client.auto_rehandshake = auto_rehandshake;
+        }
+        Ok(client)
+    }
+}
+
 impl OpenSecretClient {
     pub fn new(base_url: impl Into<String>) -> Result<Self> {
-        let base_url = base_url.into();
-        let use_mock = base_url.contains("localhost")
-            || base_url.contains("127.0.0.1")
-            || base_url.contains("0.0.0.0")
-            || base_url.contains("10.0.2.2");
-
-        Ok(Self {
-            client: Client::new(),
-            base_url: base_url.trim_end_matches('/').to_string(),
-            session_manager: SessionManager::new(),
-            use_mock_attestation: use_mock,
-            server_public_key: Arc::new(RwLock::new(None)),
-        })
+        Self::builder().base_url(base_url).build()
     }
 
     pub fn new_with_api_key(base_url: impl Into<String>, api_key: String) -> Result<Self> {
-        let base_url = base_url.into();
-        let use_mock = base_url.contains("localhost")
-            || base_url.contains("127.0.0.1")
-            || base_url.contains("0.0.0.0")
-            || base_url.contains("10.0.2.2");
+        Self::builder().base_url(base_url).api_key(api_key).build()
+    }
+
+    /// Like [`Self::new`], but sends every request, streams included, through
+    /// `client`, e.g. to share a connection pool, add TLS roots or go through
+    /// a proxy. Timeouts configured on `client` apply in addition to
+    /// [`Self::with_timeout`].
+    pub fn with_http_client(base_url: impl Into<String>, client: Client) -> Result<Self> {
+        Self::builder()
+            .base_url(base_url)
+            .http_client(client)
+            .build()
+    }
+
+    pub fn builder() -> OpenSecretClientBuilder {
+        OpenSecretClientBuilder::default()
+    }
+
+    /// `mock_attestation` overrides the default, which mocks attestation for
+    /// local development URLs.
+    fn with_session_manager(
+        base_url: String,
+        session_manager: SessionManager,
+        client: Client,
+        mock_attestation: Option<bool>,
+    ) -> Result<Self> {
+        let base_url = normalize_base_url(&base_url)?;
+        let use_mock = mock_attestation.unwrap_or_else(|| is_local_host(&base_url));
+        if use_mock && !is_local_host(&base_url) {
+            tracing::warn!(
+                "Attestation is mocked for {} although its host is not local; \
+                 the server will not be verified",
+                base_url
+            );
+        }
 
         Ok(Self {
-            client: Client::new(),
-            base_url: base_url.trim_end_matches('/').to_string(),
-            session_manager: SessionManager::new_with_api_key(api_key),
+            client,
+            base_url,
+            session_manager,
             use_mock_attestation: use_mock,
             server_public_key: Arc::new(RwLock::new(None)),
+            attestation_document: Arc::new(RwLock::new(None)),
+            handshake: Arc::new(tokio::sync::Mutex::new(())),
+            strict_model: false,
+            stream_error_policy: StreamErrorPolicy::default(),
+            credential_override: None,
+            max_derivation_depth: DEFAULT_MAX_DERIVATION_DEPTH,
+            nonce_source: Arc::new(RandomNonce),
+            accounts: Arc::new(RwLock::new(HashMap::new())),
+            cancel: Arc::new(RwLock::new(CancellationToken::new())),
+            require_real_attestation: false,
+            expected_pcrs: None,
+            attestation_cache: None,
+            auto_refresh: true,
+            auto_rehandshake: true,
+            timeout: None,
+            stream_timeout: None,
+            retry: None,
+            upload_progress: None,
+            #[cfg(feature = "dev-capture")]
+            capture: None,
         })
     }
 
+    /// When enabled, streaming chat completions fail if the server answers with a
+    /// different model than the one requested (e.g. a silent fallback route).
+    /// Dated snapshots and punctuation differences of the same model are accepted.
+    pub fn with_strict_model(mut self, strict_model: bool) -> Self {
+        self.strict_model = strict_model;
+        self
+    }
+
+    /// Controls whether a 401 from a protected call refreshes the access token
+    /// with the stored refresh token and retries the request once. Enabled by
+    /// default; disable it to handle expiry yourself. A failed refresh is
+    /// returned as-is and never retried.
+    pub fn with_auto_refresh(mut self, auto_refresh: bool) -> Self {
+        self.auto_refresh = auto_refresh;
+        self
+    }
+
+    /// Controls whether an encrypted call that fails because the session is
+    /// gone ([`Error::SessionExpired`], or another session or decryption
+    /// failure) re-runs the attestation handshake and retries the request
+    /// once. Enabled by default; when disabled those errors are returned as-is.
+    pub fn with_auto_rehandshake(mut self, auto_rehandshake: bool) -> Self {
+        self.auto_rehandshake = auto_rehandshake;
+        self
+    }
+
+    /// Limits each non-streaming request, from connecting until the whole
+    /// response body has arrived, to `timeout`. By default requests never
+    /// time out. An expired request fails with [`Error::Timeout`].
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Like [`Self::with_timeout`] for streaming calls (chat completion and
+    /// agent streams). The limit covers the whole stream, so it is usually
+    /// much longer than the request timeout; by default streams never time
+    /// out.
+    pub fn with_stream_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.stream_timeout = Some(timeout);
+        self
+    }
+
+    /// Retries GET requests that fail with a transient status such as
+    /// 502/503, a 429, a timeout or a connection error, using jittered
+    /// exponential backoff and honoring `Retry-After`. Compute-only POSTs
+    /// (chat completions and embeddings) are billed once processed, so they
+    /// are retried only after a connection error or a 502/503. Other
+    /// statuses, e.g. 400/401/403/404, fail immediately. Streams are not
+    /// retried. Off by default.
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Applies the configured request or stream timeout to `request`.
+    fn apply_timeout(
+        &self,
+        request: reqwest::RequestBuilder,
+        streaming: bool,
+    ) -> reqwest::RequestBuilder {
+        let timeout = if streaming {
+            self.stream_timeout
+        } else {
+            self.timeout
+        };
+        match timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
+    }
+
+    /// Whether handshakes skip attestation verification. Mock attestation is
+    /// chosen when the base URL's host is local (loopback, the unspecified
+    /// address or the Android emulator host).
+    pub fn is_attestation_mocked(&self) -> bool {
+        self.use_mock_attestation
+    }
+
+    /// Overrides the URL-based choice of mock attestation, e.g. to verify a
+    /// real enclave reached through a local tunnel.
+    ///
+    /// **Security**: in mock mode the attestation document's signature,
+    /// certificate chain and PCRs are not checked, so the client encrypts to
+    /// whatever key the server presents. Only enable it against development
+    /// servers.
+    pub fn with_mock_attestation(mut self, mock_attestation: bool) -> Self {
+        self.use_mock_attestation = mock_attestation;
+        self
+    }
+
+    /// Makes handshakes fail instead of using mock attestation unless the base
+    /// URL's host really is local (loopback or the Android emulator host), even
+    /// if mock attestation was enabled with [`Self::with_mock_attestation`].
+    pub fn require_real_attestation(mut self) -> Self {
+        self.require_real_attestation = true;
+        self
+    }
+
+    /// Pins the enclave image: handshakes fail with
+    /// [`Error::AttestationVerificationFailed`] unless the attested PCRs equal
+    /// `pcrs` at every given index. Mock attestation (local URLs) has no PCRs
+    /// and is not checked.
+    pub fn with_expected_pcrs(mut self, pcrs: HashMap<usize, Vec<u8>>) -> Self {
+        self.expected_pcrs = Some(pcrs);
+        self
+    }
+
+    /// [`Self::with_expected_pcrs`] with hex-encoded values, as published for
+    /// release builds.
+    pub fn with_expected_pcrs_hex(self, pcrs: HashMap<usize, String>) -> Result<Self> {
+        let pcrs = pcrs
+            .into_iter()
+            .map(|(index, value)| {
+                hex::decode(value.trim())
+                    .map(|bytes| (index, bytes))
+                    .map_err(|e| Error::Configuration(format!("Invalid PCR{} hex: {}", index, e)))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        Ok(self.with_expected_pcrs(pcrs))
+    }
+
+    /// Sets what streaming calls do after a chunk fails to decrypt or parse.
+    /// Defaults to [`StreamErrorPolicy::AbortOnError`].
+    pub fn with_stream_error_policy(mut self, policy: StreamErrorPolicy) -> Self {
+        self.stream_error_policy = policy;
+        self
+    }
+
+    /// Sets the deepest derivation path accepted in [`KeyOptions`] before a
+    /// request is sent. Defaults to [`DEFAULT_MAX_DERIVATION_DEPTH`].
+    pub fn with_max_derivation_depth(mut self, max_depth: usize) -> Self {
+        self.max_derivation_depth = max_depth;
+        self
+    }
+
+    /// Shares verified attestation documents with other clients through
+    /// `cache`, so handshakes skip certificate-chain verification while an
+    /// entry is fresh. Each handshake still fetches and checks a document
+    /// for its own nonce. Read the security notes on [`AttestationCache`]
+    /// before enabling this.
+    pub fn with_attestation_cache(mut self, cache: AttestationCache) -> Self {
+        self.attestation_cache = Some(cache);
+        self
+    }
+
+    /// Replaces the source of attestation nonces, e.g. with
+    /// [`FixedNonce`](crate::attestation::FixedNonce) so a mocked attestation
+    /// endpoint can match a known path. Defaults to random nonces.
+    pub fn with_nonce_source(mut self, source: impl NonceSource + 'static) -> Self {
+        self.nonce_source = Arc::new(source);
+        self
+    }
+
+    fn check_key_options(&self, options: Option<&KeyOptions>) -> Result<()> {
+        match options {
+            Some(options) => options.validate_depth(self.max_derivation_depth),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a handle that sends OpenAI-compatible calls (chat, models,
+    /// embeddings, ...) with `credential` instead of the default API-key-first
+    /// choice. The handle shares this client's session and tokens, so it is
+    /// cheap to create for a single call:
+    ///
+    /// ```ignore
+    /// let response = client
+    ///     .with_credential(Credential::Jwt)
+    ///     .create_chat_completion(request)
+    ///     .await?;
+    /// ```
+    pub fn with_credential(&self, credential: Credential) -> Self {
+        let mut handle = self.handle(self.session_manager.clone());
+        handle.credential_override = Some(credential);
+        handle
+    }
+
+    /// Returns a handle scoped to the account `id`. All account handles share
+    /// this client's attested session, so signing in N accounts on one
+    /// endpoint costs a single handshake, but each account keeps its own
+    /// tokens and API key:
+    ///
+    /// ```ignore
+    /// client.account("alice")?.login(alice_email, alice_password, client_id).await?;
+    /// client.account("bob")?.login(bob_email, bob_password, client_id).await?;
+    /// let alice = client.account("alice")?.get_user().await?;
+    /// ```
+    ///
+    /// The account is created, signed out, on first use. The client itself
+    /// acts as a separate, unnamed account.
+    pub fn account(&self, id: impl Into<String>) -> Result<Self> {
+        let mut accounts = self
+            .accounts
+            .write()
+            .map_err(|e| Error::Session(format!("Failed to acquire accounts lock: {}", e)))?;
+        let session_manager = accounts
+            .entry(id.into())
+            .or_insert_with(|| self.session_manager.with_shared_session())
+            .clone();
+        Ok(self.handle(session_manager))
+    }
+
+    /// Forgets the account `id` and its tokens. Returns whether it existed.
+    pub fn remove_account(&self, id: &str) -> Result<bool> {
+        let mut accounts = self
+            .accounts
+            .write()
+            .map_err(|e| Error::Session(format!("Failed to acquire accounts lock: {}", e)))?;
+        Ok(accounts.remove(id).is_some())
+    }
+
+    /// Ids of the accounts created with [`Self::account`].
+    pub fn account_ids(&self) -> Result<Vec<String>> {
+        let accounts = self
+            .accounts
+            .read()
+            .map_err(|e| Error::Session(format!("Failed to acquire accounts lock: {}", e)))?;
+        Ok(accounts.keys().cloned().collect())
+    }
+
+    /// A handle sharing this client's configuration and connection, with the
+    /// given session manager.
+    fn handle(&self, session_manager: SessionManager) -> Self {
+        Self {
+            client: self.client.clone(),
+            base_url: self.base_url.clone(),
+            session_manager,
+            use_mock_attestation: self.use_mock_attestation,
+            server_public_key: self.server_public_key.clone(),
+            attestation_document: self.attestation_document.clone(),
+            handshake: self.handshake.clone(),
+            strict_model: self.strict_model,
+            stream_error_policy: self.stream_error_policy,
+            credential_override: self.credential_override.clone(),
+            max_derivation_depth: self.max_derivation_depth,
+            nonce_source: self.nonce_source.clone(),
+            accounts: self.accounts.clone(),
+            cancel: self.cancel.clone(),
+            require_real_attestation: self.require_real_attestation,
+            expected_pcrs: self.expected_pcrs.clone(),
+            attestation_cache: self.attestation_cache.clone(),
+            auto_refresh: self.auto_refresh,
+            auto_rehandshake: self.auto_rehandshake,
+            timeout: self.timeout,
+            stream_timeout: self.stream_timeout,
+            retry: self.retry,
+            upload_progress: self.upload_progress.clone(),
+            #[cfg(feature = "dev-capture")]
+            capture: self.capture.clone(),
+        }
+    }
+
+    /// Appends every encrypted request and response envelope, together with the
+    /// session key needed to decrypt it, to `path` as JSON lines. Streaming
+    /// responses are recorded without their events.
+    ///
+    /// **Development only**: the file contains secret material. See
+    /// [`crate::capture`].
+    #[cfg(feature = "dev-capture")]
+    pub fn capture_to(mut self, path: impl AsRef<std::path::Path>) -> Result<Self> {
+        self.capture = Some(Arc::new(crate::capture::CaptureFile::open(path)?));
+        Ok(self)
+    }
+
     pub fn set_api_key(&self, api_key: String) -> Result<()> {
         self.session_manager.set_api_key(api_key)
     }
@@ -190,24 +933,138 @@ impl OpenSecretClient {
         self.session_manager.clear_api_key()
     }
 
+    /// Runs the attestation handshake, replacing any existing session.
+    /// Handshakes on a client and its handles run one at a time.
     pub async fn perform_attestation_handshake(&self) -> Result<()> {
-        // Generate a nonce
-        let nonce = Uuid::new_v4().to_string();
+        self.perform_handshake_detailed().await.map(|_| ())
+    }
+
+    /// [`Self::perform_attestation_handshake`], returning what the handshake
+    /// verified and negotiated, e.g. for tools that check the attested PCRs.
+    pub async fn perform_handshake_detailed(&self) -> Result<HandshakeOutcome> {
+        self.cancellable(self.attest_and_exchange(None)).await
+    }
+
+    /// [`Self::perform_attestation_handshake`] with a caller-chosen nonce,
+    /// e.g. one bound to external context, instead of one from the
+    /// configured [`NonceSource`]. The attestation document must echo it (see
+    /// [`Self::get_attestation_document`]). The nonce goes into the request
+    /// path and must be non-empty without whitespace, `/`, `?`, `#` or `%`.
+    pub async fn perform_attestation_handshake_with_nonce(&self, nonce: &str) -> Result<()> {
+        if nonce.is_empty()
+            || nonce
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '/' | '?' | '#' | '%'))
+        {
+            return Err(Error::Configuration(format!(
+                "Invalid attestation nonce {:?}",
+                nonce
+            )));
+        }
 
-        // Step 1: Get attestation document
-        let attestation_doc = self.get_attestation_document(&nonce).await?;
+        self.cancellable(self.attest_and_exchange(Some(nonce.to_string())))
+            .await
+            .map(|_| ())
+    }
 
-        // Step 2: Parse and verify attestation document
-        let doc = if !self.use_mock_attestation {
-            let verifier = AttestationVerifier::new();
-            verifier.verify_attestation_document(&attestation_doc.attestation_document, &nonce)?
-        } else {
-            // For mock mode, extract without full verification
-            self.parse_mock_attestation(&attestation_doc.attestation_document)?
+    /// Runs the attestation handshake only if there is no session yet.
+    /// Concurrent callers wait for a single handshake and share its session.
+    pub async fn ensure_session(&self) -> Result<()> {
+        if self.session_manager.get_session()?.is_some() {
+            return Ok(());
+        }
+
+        self.cancellable(async {
+            let _handshake = self.handshake.lock().await;
+            if self.session_manager.get_session()?.is_some() {
+                return Ok(());
+            }
+            self.attest_and_exchange_locked(None).await.map(|_| ())
+        })
+        .await
+    }
+
+    /// Aborts every request and stream in flight on this client and all
+    /// handles derived from it (accounts, [`Self::with_credential`]). They
+    /// return [`Error::Cancelled`] promptly. Calls started afterwards run
+    /// normally.
+    pub fn cancel_all(&self) -> Result<()> {
+        let mut cancel = self
+            .cancel
+            .write()
+            .map_err(|e| Error::Session(format!("Failed to acquire cancellation lock: {}", e)))?;
+        cancel.cancel();
+        *cancel = CancellationToken::new();
+        Ok(())
+    }
+
+    fn cancellation_token(&self) -> Result<CancellationToken> {
+        let cancel = self
+            .cancel
+            .read()
+            .map_err(|e| Error::Session(format!("Failed to acquire cancellation lock: {}", e)))?;
+        Ok(cancel.clone())
+    }
+
+    /// Runs `future` until it completes or [`Self::cancel_all`] is called.
+    async fn cancellable<T>(
+        &self,
+        future: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let cancel = self.cancellation_token()?;
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Err(Error::Cancelled),
+            result = future => result,
+        }
+    }
+
+    /// Runs the attestation handshake and returns the verified (or, against a
+    /// local server, mock) attestation document with the new session. Uses
+    /// `nonce` if given, and otherwise one from the nonce source.
+    async fn attest_and_exchange(&self, nonce: Option<String>) -> Result<HandshakeOutcome> {
+        let _handshake = self.handshake.lock().await;
+        self.attest_and_exchange_locked(nonce).await
+    }
+
+    /// [`Self::attest_and_exchange`] for callers already holding the
+    /// handshake lock.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(base_url = %self.base_url),
+        err(level = "warn")
+    )]
+    async fn attest_and_exchange_locked(&self, nonce: Option<String>) -> Result<HandshakeOutcome> {
+        let nonce = nonce.unwrap_or_else(|| self.nonce_source.next_nonce());
+
+        let cache = self
+            .attestation_cache
+            .as_ref()
+            .filter(|_| !self.use_mock_attestation);
+        let cache_key = AttestationCache::key(&self.base_url, self.expected_pcrs.as_ref());
+        let trusted = cache.and_then(|cache| cache.get(&cache_key));
+
+        // Step 1: Get attestation document
+        let attestation_doc = self.request_attestation_document(&nonce).await?;
+
+        // Step 2: Parse and verify attestation document, skipping the
+        // certificate chain if it is signed like a cached, verified one
+        let reused = trusted.and_then(|trusted| {
+            self.attestation_verifier()
+                .verify_against_trusted(&attestation_doc.attestation_document, &nonce, &trusted)
+                .map_err(|e| tracing::debug!(error = %e, "Cached attestation not reusable"))
+                .ok()
+        });
+        let cached_attestation = reused.is_some();
+        let doc = match reused {
+            Some(doc) => doc,
+            None => self.verify_attestation(&attestation_doc.attestation_document, &nonce)?,
         };
+        tracing::debug!(module_id = %doc.module_id, cached_attestation, "Attestation document verified");
 
         // Store server's public key from attestation document
-        if let Some(pub_key) = doc.public_key {
+        if let Some(pub_key) = doc.public_key.clone() {
             *self.server_public_key.write().map_err(|e| {
                 Error::KeyExchange(format!("Failed to write server public key: {}", e))
             })? = Some(pub_key);
@@ -218,15 +1075,122 @@ impl OpenSecretClient {
         }
 
         // Step 3: Perform key exchange
-        self.perform_key_exchange(&nonce).await?;
+        let exchanged = self.perform_key_exchange(&nonce).await;
+        if let Some(cache) = cache {
+            match (&exchanged, cached_attestation) {
+                // The enclave may have been replaced; verify afresh next time.
+                (Err(_), true) => cache.remove(&cache_key),
+                (Ok(_), false) => cache.insert(cache_key, doc.clone()),
+                _ => {}
+            }
+        }
+        let session_id = exchanged?;
 
-        Ok(())
+        *self.attestation_document.write().map_err(|e| {
+            Error::Session(format!("Failed to write attestation document: {}", e))
+        })? = Some(doc.clone());
+
+        Ok(HandshakeOutcome {
+            attestation_document: doc,
+            session_id,
+            mock_attestation: self.use_mock_attestation,
+            cached_attestation,
+        })
+    }
+
+    /// The attestation document verified by the most recent completed
+    /// handshake, or `None` before any handshake has completed. Useful for
+    /// audit logs of the attested `module_id`, `timestamp` and PCR values.
+    pub fn get_attestation_document(&self) -> Result<Option<AttestationDocument>> {
+        let doc = self
+            .attestation_document
+            .read()
+            .map_err(|e| Error::Session(format!("Failed to read attestation document: {}", e)))?;
+        Ok(doc.clone())
+    }
+
+    /// Checks connectivity, the attestation handshake and, when logged in,
+    /// authentication, stopping at the first failure. Step failures are
+    /// recorded in the report rather than returned as errors.
+    pub async fn diagnose(&self) -> Result<Diagnostics> {
+        let mut report = Diagnostics {
+            base_url: self.base_url.clone(),
+            mock_attestation: self.use_mock_attestation,
+            module_id: None,
+            steps: Vec::new(),
+            first_error: None,
+        };
+
+        let started = std::time::Instant::now();
+        let health = self.test_connection().await;
+        if !report.record(
+            "health",
+            started,
+            health.map(|body| body.trim().to_string()),
+        ) {
+            return Ok(report);
+        }
+
+        let started = std::time::Instant::now();
+        let attestation = self.attest_and_exchange(None).await.map(|outcome| {
+            report.module_id = Some(outcome.attestation_document.module_id);
+            if self.use_mock_attestation {
+                "mock attestation accepted".to_string()
+            } else {
+                "attestation verified".to_string()
+            }
+        });
+        if !report.record("attestation", started, attestation) {
+            return Ok(report);
+        }
+
+        if self.session_manager.get_access_token()?.is_some() {
+            let started = std::time::Instant::now();
+            let auth = self
+                .get_user()
+                .await
+                .map(|user| format!("authenticated as {}", user.user.id));
+            report.record("auth", started, auth);
+        }
+
+        Ok(report)
+    }
+
+    fn attestation_verifier(&self) -> AttestationVerifier {
+        let mut verifier = AttestationVerifier::new();
+        if let Some(pcrs) = &self.expected_pcrs {
+            verifier = verifier.with_expected_pcrs(pcrs.clone());
+        }
+        verifier
+    }
+
+    fn verify_attestation(&self, document_b64: &str, nonce: &str) -> Result<AttestationDocument> {
+        if self.use_mock_attestation
+            && self.require_real_attestation
+            && !is_local_host(&self.base_url)
+        {
+            return Err(Error::AttestationVerificationFailed(format!(
+                "Refusing mock attestation for non-local URL {}",
+                self.base_url
+            )));
+        }
+
+        if !self.use_mock_attestation {
+            self.attestation_verifier()
+                .verify_attestation_document(document_b64, nonce)
+        } else {
+            // For mock mode, extract without full verification
+            self.parse_mock_attestation(document_b64)
+        }
     }
 
-    async fn get_attestation_document(&self, nonce: &str) -> Result<AttestationResponse> {
+    async fn request_attestation_document(&self, nonce: &str) -> Result<AttestationResponse> {
         let url = format!("{}/attestation/{}", self.base_url, nonce);
 
-        let response = self.client.get(&url).send().await?;
+        let response = self
+            .apply_timeout(self.client.get(url), false)
+            .send()
+            .await?;
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
@@ -243,7 +1207,8 @@ impl OpenSecretClient {
         response.json().await.map_err(Into::into)
     }
 
-    async fn perform_key_exchange(&self, nonce: &str) -> Result<()> {
+    #[tracing::instrument(level = "debug", skip_all, err(level = "warn"))]
+    async fn perform_key_exchange(&self, nonce: &str) -> Result<Uuid> {
         // Generate ephemeral keypair
         let (secret, public_key) = crypto::generate_static_keypair();
         let public_key_bytes = public_key.as_bytes();
@@ -260,8 +1225,7 @@ impl OpenSecretClient {
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
         let response = self
-            .client
-            .post(&url)
+            .apply_timeout(self.client.post(&url), false)
             .headers(headers)
             .json(&body)
             .send()
@@ -291,13 +1255,17 @@ impl OpenSecretClient {
             .ok_or_else(|| Error::KeyExchange("Server public key not available".to_string()))?;
 
         // Convert server's public key bytes to x25519 PublicKey
-        let server_public_key = x25519_dalek::PublicKey::from(
-            <[u8; 32]>::try_from(server_public_key_bytes.as_slice())
-                .map_err(|_| Error::KeyExchange("Invalid server public key length".to_string()))?,
-        );
+        let server_public_key =
+            crypto::parse_public_key(server_public_key_bytes).map_err(|e| match e {
+                Error::KeyExchange(message) => {
+                    Error::KeyExchange(format!("Server attestation key rejected: {}", message))
+                }
+                other => other,
+            })?;
 
         // Perform ECDH to get shared secret
         let shared_secret = crypto::perform_static_key_exchange(&secret, &server_public_key);
+        crypto::ensure_contributory(&shared_secret)?;
 
         // Decrypt the session key
         let session_key = crypto::decrypt_session_key(
@@ -310,14 +1278,48 @@ impl OpenSecretClient {
             .map_err(|e| Error::Session(format!("Invalid session ID format: {}", e)))?;
 
         self.session_manager.set_session(session_id, session_key)?;
+        tracing::debug!(%session_id, "Session established");
 
-        Ok(())
+        Ok(session_id)
     }
 
     pub fn get_session_id(&self) -> Result<Option<Uuid>> {
         Ok(self.session_manager.get_session()?.map(|s| s.session_id))
     }
 
+    /// Snapshot of the session key and tokens for [`Self::import_session`].
+    /// Fails if no handshake has completed. See [`SerializedSession`] for how
+    /// to store the result.
+    pub fn export_session(&self) -> Result<SerializedSession> {
+        let session = self
+            .session_manager
+            .get_session()?
+            .ok_or_else(|| Error::Session("No active session to export".to_string()))?;
+        let tokens = self.session_manager.get_tokens()?;
+
+        Ok(SerializedSession {
+            session_id: session.session_id,
+            session_key: session.session_key,
+            access_token: tokens.as_ref().map(|t| t.access_token.clone()),
+            refresh_token: tokens.and_then(|t| t.refresh_token.clone()),
+        })
+    }
+
+    /// Restores a session saved with [`Self::export_session`], replacing the
+    /// current session and tokens. Calls then go straight to the server
+    /// without a handshake; if the server no longer knows the session, the
+    /// usual re-attestation retry takes over.
+    pub fn import_session(&self, data: SerializedSession) -> Result<()> {
+        self.session_manager
+            .set_session(data.session_id, data.session_key)?;
+        match &data.access_token {
+            Some(access_token) => self
+                .session_manager
+                .set_tokens(access_token.clone(), data.refresh_token.clone()),
+            None => self.session_manager.clear_tokens(),
+        }
+    }
+
     fn parse_mock_attestation(&self, document_b64: &str) -> Result<AttestationDocument> {
         // For mock/dev mode, just extract the essential fields without full verification
         let document_bytes = BASE64.decode(document_b64)?;
@@ -392,9 +1394,12 @@ impl OpenSecretClient {
         })
     }
 
-    pub async fn test_connection(&self) -> Result<String> {
-        let url = format!("{}/health-check", self.base_url);
-        let response = self.client.get(&url).send().await?;
+    /// Fetches the list of enclave deployments from a public endpoint
+    /// directory. The directory is plain JSON, so the listed PCRs are only as
+    /// trustworthy as the TLS connection to `directory_url`; attestation of the
+    /// chosen endpoint is still performed by the handshake.
+    pub async fn discover_endpoints(directory_url: &str) -> Result<Vec<EndpointInfo>> {
+        let response = Client::new().get(directory_url).send().await?;
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
@@ -408,38 +1413,251 @@ impl OpenSecretClient {
             });
         }
 
-        response.text().await.map_err(Into::into)
+        // Directory documents may wrap the list or return it bare.
+        let mut directory: serde_json::Value = response.json().await?;
+        if let Some(endpoints) = directory.get_mut("endpoints") {
+            directory = endpoints.take();
+        }
+        let endpoints: Vec<EndpointInfo> = serde_json::from_value(directory)?;
+
+        endpoints
+            .into_iter()
+            .map(|mut endpoint| {
+                endpoint.url = normalize_base_url(&endpoint.url)?;
+                Ok(endpoint)
+            })
+            .collect()
     }
 
-    async fn encrypted_api_call<T: Serialize + Clone, U: DeserializeOwned>(
-        &self,
-        endpoint: &str,
-        method: &str,
-        data: Option<T>,
-    ) -> Result<U> {
-        self.retry_encrypted_json_call_without_refresh(endpoint, method, data, AuthHeaderMode::None)
-            .await
+    /// Calls the health-check endpoint and parses whichever payload format the
+    /// deployment returns. Non-success statuses are still errors.
+    pub async fn health_status(&self) -> Result<HealthStatus> {
+        let body = self.test_connection().await?;
+        Ok(HealthStatus::parse(&body))
     }
 
-    async fn authenticated_api_call<T: Serialize + Clone, U: DeserializeOwned>(
-        &self,
-        endpoint: &str,
-        method: &str,
-        data: Option<T>,
-    ) -> Result<U> {
-        self.retry_encrypted_json_call(endpoint, method, data, AuthHeaderMode::Jwt, true)
-            .await
+    /// Calls the health-check endpoint and requires a JSON body, for
+    /// monitoring that acts on its fields. Unlike [`Self::health_status`], a
+    /// plain-text body is an [`Error::InvalidResponse`].
+    pub async fn health_check(&self) -> Result<HealthStatus> {
+        let body = self.test_connection().await?;
+        HealthStatus::parse_json(&body)
     }
 
-    async fn retry_encrypted_json_call_without_refresh<
-        T: Serialize + Clone,
-        U: DeserializeOwned,
-    >(
-        &self,
-        endpoint: &str,
-        method: &str,
-        data: Option<T>,
-        auth_mode: AuthHeaderMode,
+    pub async fn test_connection(&self) -> Result<String> {
+        let url = format!("{}/health-check", self.base_url);
+        let response = self
+            .apply_timeout(self.client.get(&url), false)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(Error::Api {
+                status,
+                message: text,
+            });
+        }
+
+        response.text().await.map_err(Into::into)
+    }
+
+    /// Starts a background task that checks the server every `interval`,
+    /// re-attests when no session is established and refreshes the access
+    /// token once it expires within two intervals. The returned
+    /// [`HealthMonitor`] reports state changes and stops the task when
+    /// stopped or dropped.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn start_health_monitor(&self, interval: std::time::Duration) -> Result<HealthMonitor> {
+        if interval.is_zero() {
+            return Err(Error::Configuration(
+                "Health monitor interval must be non-zero".to_string(),
+            ));
+        }
+
+        let client = self.handle(self.session_manager.clone());
+        let cancel = CancellationToken::new();
+        let (state, receiver) = tokio::sync::watch::channel(ConnectionState::Starting);
+        let refresh_threshold = interval.saturating_mul(2);
+
+        let task_cancel = cancel.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = task_cancel.cancelled() => break,
+                    _ = ticker.tick() => {}
+                }
+                let observed = tokio::select! {
+                    biased;
+                    _ = task_cancel.cancelled() => break,
+                    observed = client.check_health(refresh_threshold) => observed,
+                };
+                // `None` means the check was interrupted by `cancel_all`.
+                let Some(observed) = observed else { continue };
+                state.send_if_modified(|current| {
+                    if *current == observed {
+                        return false;
+                    }
+                    tracing::debug!("Connection state changed to {:?}", observed);
+                    *current = observed;
+                    true
+                });
+            }
+        });
+
+        Ok(HealthMonitor::new(receiver, cancel, task))
+    }
+
+    /// One health monitor pass. Returns `None` if it was cancelled.
+    async fn check_health(
+        &self,
+        refresh_threshold: std::time::Duration,
+    ) -> Option<ConnectionState> {
+        if let Err(e) = self.test_connection().await {
+            return Some(ConnectionState::Unreachable(e.to_string()));
+        }
+
+        let maintained = async {
+            self.ensure_session().await?;
+            if self.session_manager.get_access_token()?.is_some() {
+                self.refresh_if_needed(refresh_threshold).await?;
+            }
+            Ok::<_, Error>(())
+        }
+        .await;
+
+        match maintained {
+            Ok(()) => Some(ConnectionState::Healthy),
+            Err(Error::Cancelled) => None,
+            Err(e) => Some(ConnectionState::Degraded(e.to_string())),
+        }
+    }
+
+    /// Calls an endpoint the SDK does not wrap yet through the encrypted
+    /// session, e.g. from a local proxy. `method` is `GET`, `POST`, `PUT` or
+    /// `DELETE`; `endpoint` is the path, such as `/protected/user`.
+    ///
+    /// The access token is sent when signed in (and refreshed on a 401 as for
+    /// the wrapped methods), so both public and `/protected` endpoints work.
+    ///
+    /// ```ignore
+    /// let user: serde_json::Value = client
+    ///     .make_encrypted_request("GET", "/protected/user", None::<()>)
+    ///     .await?;
+    /// ```
+    pub async fn make_encrypted_request<T: Serialize + Clone, U: DeserializeOwned>(
+        &self,
+        method: &str,
+        endpoint: &str,
+        body: Option<T>,
+    ) -> Result<U> {
+        self.authenticated_api_call(endpoint, method, body).await
+    }
+
+    /// Like [`Self::make_encrypted_request`], but sends and returns raw bytes,
+    /// for binary endpoints such as file uploads and downloads. `body` is
+    /// encrypted as-is and the decrypted response is returned without
+    /// parsing; an empty response yields an empty `Vec`.
+    pub async fn encrypted_call_bytes(
+        &self,
+        method: &str,
+        endpoint: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        self.cancellable(self.retry_encrypted_bytes_call(
+            endpoint,
+            method,
+            body.as_deref(),
+            AuthHeaderMode::Jwt,
+            self.auto_refresh,
+        ))
+        .await
+        .map(Option::unwrap_or_default)
+    }
+
+    /// [`Self::make_encrypted_request`] for `/v1` endpoints, authenticating
+    /// with the API key when one is set and the access token otherwise.
+    pub async fn make_encrypted_openai_request<T: Serialize + Clone, U: DeserializeOwned>(
+        &self,
+        method: &str,
+        endpoint: &str,
+        body: Option<T>,
+    ) -> Result<U> {
+        self.encrypted_openai_call(endpoint, method, body).await
+    }
+
+    async fn encrypted_api_call<T: Serialize + Clone, U: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        method: &str,
+        data: Option<T>,
+    ) -> Result<U> {
+        self.cancellable(self.retry_encrypted_json_call_without_refresh(
+            endpoint,
+            method,
+            data,
+            AuthHeaderMode::None,
+        ))
+        .await
+    }
+
+    async fn authenticated_api_call<T: Serialize + Clone, U: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        method: &str,
+        data: Option<T>,
+    ) -> Result<U> {
+        self.cancellable(self.retry_encrypted_json_call(
+            endpoint,
+            method,
+            data,
+            AuthHeaderMode::Jwt,
+            self.auto_refresh,
+        ))
+        .await
+    }
+
+    /// [`Self::encrypted_api_call`] for endpoints whose only result is success:
+    /// an empty 2xx body (e.g. 204 No Content) or any JSON body is accepted.
+    async fn encrypted_no_content_call<T: Serialize + Clone>(
+        &self,
+        endpoint: &str,
+        method: &str,
+        data: Option<T>,
+    ) -> Result<()> {
+        let _: serde_json::Value = self.encrypted_api_call(endpoint, method, data).await?;
+        Ok(())
+    }
+
+    /// [`Self::authenticated_api_call`] for endpoints whose only result is
+    /// success, such as deletes.
+    async fn authenticated_no_content_call<T: Serialize + Clone>(
+        &self,
+        endpoint: &str,
+        method: &str,
+        data: Option<T>,
+    ) -> Result<()> {
+        let _: serde_json::Value = self.authenticated_api_call(endpoint, method, data).await?;
+        Ok(())
+    }
+
+    async fn retry_encrypted_json_call_without_refresh<
+        T: Serialize + Clone,
+        U: DeserializeOwned,
+    >(
+        &self,
+        endpoint: &str,
+        method: &str,
+        data: Option<T>,
+        auth_mode: AuthHeaderMode,
     ) -> Result<U> {
         let mut retried_attestation = false;
 
@@ -449,7 +1667,7 @@ impl OpenSecretClient {
                 .await
             {
                 Ok(result) => return Ok(result),
-                Err(error) if !retried_attestation && Self::is_attestation_retryable(&error) => {
+                Err(error) if !retried_attestation && self.should_rehandshake(&error) => {
                     self.perform_attestation_handshake().await?;
                     retried_attestation = true;
                 }
@@ -466,16 +1684,31 @@ impl OpenSecretClient {
         auth_mode: AuthHeaderMode,
         allow_refresh: bool,
     ) -> Result<U> {
+        let body = to_json_body(data)?;
+        let decrypted = self
+            .retry_encrypted_bytes_call(endpoint, method, body.as_deref(), auth_mode, allow_refresh)
+            .await?;
+        Self::decode_json_response(endpoint, decrypted)
+    }
+
+    async fn retry_encrypted_bytes_call(
+        &self,
+        endpoint: &str,
+        method: &str,
+        body: Option<&[u8]>,
+        auth_mode: AuthHeaderMode,
+        allow_refresh: bool,
+    ) -> Result<Option<Vec<u8>>> {
         let mut retried_attestation = false;
         let mut retried_refresh = false;
 
         loop {
             match self
-                .encrypted_json_call_inner(endpoint, method, data.clone(), auth_mode)
+                .encrypted_bytes_call_inner(endpoint, method, body, auth_mode)
                 .await
             {
                 Ok(result) => return Ok(result),
-                Err(error) if !retried_attestation && Self::is_attestation_retryable(&error) => {
+                Err(error) if !retried_attestation && self.should_rehandshake(&error) => {
                     self.perform_attestation_handshake().await?;
                     retried_attestation = true;
                 }
@@ -497,15 +1730,62 @@ impl OpenSecretClient {
         data: Option<T>,
         auth_mode: AuthHeaderMode,
     ) -> Result<U> {
+        let body = to_json_body(data)?;
+        let decrypted = self
+            .encrypted_bytes_call_inner(endpoint, method, body.as_deref(), auth_mode)
+            .await?;
+        Self::decode_json_response(endpoint, decrypted)
+    }
+
+    fn decode_json_response<U: DeserializeOwned>(
+        endpoint: &str,
+        decrypted: Option<Vec<u8>>,
+    ) -> Result<U> {
+        // A 2xx with no body (204 No Content) carries nothing to decrypt. It is
+        // success for callers expecting `()` or a `serde_json::Value`.
+        match decrypted {
+            Some(decrypted) => Ok(serde_json::from_slice(&decrypted)?),
+            None => serde_json::from_value(serde_json::Value::Null).map_err(|_| {
+                Error::InvalidResponse(format!("Empty response body from {}", endpoint))
+            }),
+        }
+    }
+
+    /// Sends an encrypted request and returns the decrypted response payload,
+    /// or `None` when the response has no body.
+    async fn encrypted_bytes_call_inner(
+        &self,
+        endpoint: &str,
+        method: &str,
+        body: Option<&[u8]>,
+        auth_mode: AuthHeaderMode,
+    ) -> Result<Option<Vec<u8>>> {
         let (response, session_key) = self
-            .send_encrypted_request(endpoint, method, data, auth_mode, false)
+            .send_encrypted_request(endpoint, method, body, auth_mode, false)
             .await?;
-        let encrypted_response: EncryptedResponse<U> = response.json().await?;
+        #[cfg(feature = "dev-capture")]
+        let status = response.status().as_u16();
+        let body = response.text().await?;
+        #[cfg(feature = "dev-capture")]
+        if let Some(capture) = &self.capture {
+            capture.record_response(method, endpoint, status, &body);
+        }
+
+        if body.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let encrypted_response: EncryptedResponse<Vec<u8>> = serde_json::from_str(&body)?;
         let decrypted =
-            crypto::decrypt_data(&session_key, &BASE64.decode(&encrypted_response.encrypted)?)?;
-        let result: U = serde_json::from_slice(&decrypted)?;
+            crypto::decrypt_data(&session_key, &BASE64.decode(&encrypted_response.encrypted)?)
+                .map_err(|e| {
+                    tracing::warn!(method, endpoint, "Failed to decrypt response: {}", e);
+                    // A well-formed success response that does not decrypt was
+                    // sealed under a session key this client no longer shares.
+                    Error::SessionExpired(format!("response could not be decrypted: {}", e))
+                })?;
 
-        Ok(result)
+        Ok(Some(decrypted))
     }
 
     /// Encrypted API call specifically for OpenAI endpoints (/v1/*)
@@ -516,8 +1796,34 @@ impl OpenSecretClient {
         method: &str,
         data: Option<T>,
     ) -> Result<U> {
-        self.retry_encrypted_json_call(endpoint, method, data, AuthHeaderMode::ApiKeyOrJwt, true)
-            .await
+        self.cancellable(self.retry_encrypted_json_call(
+            endpoint,
+            method,
+            data,
+            AuthHeaderMode::ApiKeyOrJwt,
+            self.auto_refresh,
+        ))
+        .await
+    }
+
+    /// Like [`Self::encrypted_openai_call`] for endpoints whose decrypted
+    /// payload is binary (audio, images) rather than JSON.
+    async fn encrypted_openai_bytes_call<T: Serialize + Clone>(
+        &self,
+        endpoint: &str,
+        method: &str,
+        data: Option<T>,
+    ) -> Result<Vec<u8>> {
+        let body = to_json_body(data)?;
+        self.cancellable(self.retry_encrypted_bytes_call(
+            endpoint,
+            method,
+            body.as_deref(),
+            AuthHeaderMode::ApiKeyOrJwt,
+            self.auto_refresh,
+        ))
+        .await?
+        .ok_or_else(|| Error::InvalidResponse(format!("Empty response body from {}", endpoint)))
     }
 
     async fn retry_encrypted_stream_call<T: Serialize + Clone>(
@@ -528,16 +1834,17 @@ impl OpenSecretClient {
         auth_mode: AuthHeaderMode,
         allow_refresh: bool,
     ) -> Result<(reqwest::Response, [u8; 32])> {
+        let body = to_json_body(data)?;
         let mut retried_attestation = false;
         let mut retried_refresh = false;
 
         loop {
             match self
-                .send_encrypted_request(endpoint, method, data.clone(), auth_mode, true)
+                .send_encrypted_request(endpoint, method, body.as_deref(), auth_mode, true)
                 .await
             {
                 Ok(response) => return Ok(response),
-                Err(error) if !retried_attestation && Self::is_attestation_retryable(&error) => {
+                Err(error) if !retried_attestation && self.should_rehandshake(&error) => {
                     self.perform_attestation_handshake().await?;
                     retried_attestation = true;
                 }
@@ -552,11 +1859,12 @@ impl OpenSecretClient {
         }
     }
 
-    async fn send_encrypted_request<T: Serialize>(
+    #[tracing::instrument(level = "debug", skip_all, fields(method = %method, endpoint = %endpoint))]
+    async fn send_encrypted_request(
         &self,
         endpoint: &str,
         method: &str,
-        data: Option<T>,
+        body: Option<&[u8]>,
         auth_mode: AuthHeaderMode,
         accept_sse: bool,
     ) -> Result<(reqwest::Response, [u8; 32])> {
@@ -568,9 +1876,8 @@ impl OpenSecretClient {
 
         let url = format!("{}{}", self.base_url, endpoint);
 
-        let encrypted_body = if let Some(data) = data {
-            let json = serde_json::to_string(&data)?;
-            let encrypted = crypto::encrypt_data(&session.session_key, json.as_bytes())?;
+        let encrypted_body = if let Some(body) = body {
+            let encrypted = crypto::encrypt_data(&session.session_key, body)?;
             Some(EncryptedRequest {
                 encrypted: BASE64.encode(&encrypted),
             })
@@ -578,6 +1885,16 @@ impl OpenSecretClient {
             None
         };
 
+        #[cfg(feature = "dev-capture")]
+        if let Some(capture) = &self.capture {
+            capture.record_request(
+                &session,
+                method,
+                endpoint,
+                encrypted_body.as_ref().map(|body| body.encrypted.as_str()),
+            );
+        }
+
         let headers = self.build_encrypted_headers(&session, auth_mode, accept_sse)?;
         let request_builder = match method {
             "GET" => self.client.get(&url),
@@ -592,25 +1909,72 @@ impl OpenSecretClient {
             }
         };
 
-        let request_builder = request_builder.headers(headers);
-        let response = if let Some(body) = encrypted_body {
-            request_builder.json(&body).send().await?
-        } else {
-            request_builder.send().await?
+        let mut request_builder = self
+            .apply_timeout(request_builder, accept_sse)
+            .headers(headers);
+        if let Some(body) = &encrypted_body {
+            request_builder = match &self.upload_progress {
+                Some(progress) => {
+                    let body = serde_json::to_vec(body)?;
+                    request_builder
+                        .header(reqwest::header::CONTENT_LENGTH, body.len())
+                        .body(progress_body(body, progress.clone()))
+                }
+                None => request_builder.json(body),
+            };
+        }
+        let retry = self
+            .retry
+            .filter(|_| !accept_sse)
+            .zip(retry_mode(method, endpoint));
+        let started = std::time::Instant::now();
+        let response = match retry {
+            Some((retry, mode)) => send_with_retry(request_builder, &retry, mode).await?,
+            None => request_builder.send().await?,
         };
+        tracing::debug!(
+            status = response.status().as_u16(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "Encrypted request completed"
+        );
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
             let error_msg = response
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
+            #[cfg(feature = "dev-capture")]
+            if let Some(capture) = &self.capture {
+                capture.record_response(method, endpoint, status, &error_msg);
+            }
+            if status == 429 {
+                return Err(Error::RateLimited {
+                    retry_after,
+                    message: error_msg,
+                });
+            }
+            if is_session_expired_response(status, &error_msg) {
+                return Err(Error::SessionExpired(error_msg));
+            }
             return Err(Error::Api {
                 status,
                 message: error_msg,
             });
         }
 
+        #[cfg(feature = "dev-capture")]
+        if accept_sse {
+            if let Some(capture) = &self.capture {
+                capture.record_response(method, endpoint, response.status().as_u16(), "<stream>");
+            }
+        }
+
         Ok((response, session.session_key))
     }
 
@@ -649,27 +2013,41 @@ impl OpenSecretClient {
         match auth_mode {
             AuthHeaderMode::None => Ok(None),
             AuthHeaderMode::Jwt => self.session_manager.get_access_token(),
-            AuthHeaderMode::ApiKeyOrJwt => {
-                if let Some(api_key) = self.session_manager.get_api_key()? {
-                    Ok(Some(api_key))
-                } else {
-                    self.session_manager.get_access_token()
+            AuthHeaderMode::ApiKeyOrJwt => match &self.credential_override {
+                Some(Credential::Jwt) => self.session_manager.get_access_token(),
+                Some(Credential::ApiKey(api_key)) => Ok(Some(api_key.clone())),
+                None => {
+                    if let Some(api_key) = self.session_manager.get_api_key()? {
+                        Ok(Some(api_key))
+                    } else {
+                        self.session_manager.get_access_token()
+                    }
                 }
-            }
+            },
         }
     }
 
     fn using_api_key(&self, auth_mode: AuthHeaderMode) -> Result<bool> {
-        match auth_mode {
-            AuthHeaderMode::ApiKeyOrJwt => Ok(self.session_manager.get_api_key()?.is_some()),
+        match (auth_mode, &self.credential_override) {
+            (AuthHeaderMode::ApiKeyOrJwt, Some(credential)) => {
+                Ok(matches!(credential, Credential::ApiKey(_)))
+            }
+            (AuthHeaderMode::ApiKeyOrJwt, None) => {
+                Ok(self.session_manager.get_api_key()?.is_some())
+            }
             _ => Ok(false),
         }
     }
 
+    fn should_rehandshake(&self, error: &Error) -> bool {
+        self.auto_rehandshake && Self::is_attestation_retryable(error)
+    }
+
     fn is_attestation_retryable(error: &Error) -> bool {
         matches!(
             error,
             Error::Session(_)
+                | Error::SessionExpired(_)
                 | Error::Api { status: 400, .. }
                 | Error::Encryption(_)
                 | Error::Decryption(_)
@@ -779,21 +2157,31 @@ impl OpenSecretClient {
 
     // OAuth Methods
 
-    pub async fn initiate_github_auth(
+    /// Starts an OAuth sign-in with `provider`. Send the user to the returned
+    /// `auth_url` and keep `state` for [`Self::complete_oauth`].
+    pub async fn initiate_oauth(
         &self,
+        provider: OAuthProvider,
         client_id: Uuid,
         invite_code: Option<String>,
-    ) -> Result<GithubAuthResponse> {
+    ) -> Result<OAuthInitResponse> {
         let request = OAuthInitRequest {
             client_id,
             invite_code,
         };
-        self.encrypted_api_call("/auth/github", "POST", Some(request))
-            .await
+        self.encrypted_api_call(
+            &format!("/auth/{}", provider.as_str()),
+            "POST",
+            Some(request),
+        )
+        .await
     }
 
-    pub async fn handle_github_callback(
+    /// Exchanges the `code` and `state` the provider redirected back with for
+    /// a session, storing the tokens like [`Self::login`].
+    pub async fn complete_oauth(
         &self,
+        provider: OAuthProvider,
         code: String,
         state: String,
         invite_code: String,
@@ -805,7 +2193,11 @@ impl OpenSecretClient {
         };
 
         let response: LoginResponse = self
-            .encrypted_api_call("/auth/github/callback", "POST", Some(request))
+            .encrypted_api_call(
+                &format!("/auth/{}/callback", provider.as_str()),
+                "POST",
+                Some(request),
+            )
             .await?;
 
         self.session_manager.set_tokens(
@@ -816,16 +2208,31 @@ impl OpenSecretClient {
         Ok(response)
     }
 
+    pub async fn initiate_github_auth(
+        &self,
+        client_id: Uuid,
+        invite_code: Option<String>,
+    ) -> Result<GithubAuthResponse> {
+        self.initiate_oauth(OAuthProvider::Github, client_id, invite_code)
+            .await
+    }
+
+    pub async fn handle_github_callback(
+        &self,
+        code: String,
+        state: String,
+        invite_code: String,
+    ) -> Result<LoginResponse> {
+        self.complete_oauth(OAuthProvider::Github, code, state, invite_code)
+            .await
+    }
+
     pub async fn initiate_google_auth(
         &self,
         client_id: Uuid,
         invite_code: Option<String>,
     ) -> Result<GoogleAuthResponse> {
-        let request = OAuthInitRequest {
-            client_id,
-            invite_code,
-        };
-        self.encrypted_api_call("/auth/google", "POST", Some(request))
+        self.initiate_oauth(OAuthProvider::Google, client_id, invite_code)
             .await
     }
 
@@ -835,22 +2242,8 @@ impl OpenSecretClient {
         state: String,
         invite_code: String,
     ) -> Result<LoginResponse> {
-        let request = OAuthCallbackRequest {
-            code,
-            state,
-            invite_code,
-        };
-
-        let response: LoginResponse = self
-            .encrypted_api_call("/auth/google/callback", "POST", Some(request))
-            .await?;
-
-        self.session_manager.set_tokens(
-            response.access_token.clone(),
-            Some(response.refresh_token.clone()),
-        )?;
-
-        Ok(response)
+        self.complete_oauth(OAuthProvider::Google, code, state, invite_code)
+            .await
     }
 
     pub async fn initiate_apple_auth(
@@ -858,11 +2251,7 @@ impl OpenSecretClient {
         client_id: Uuid,
         invite_code: Option<String>,
     ) -> Result<AppleAuthResponse> {
-        let request = OAuthInitRequest {
-            client_id,
-            invite_code,
-        };
-        self.encrypted_api_call("/auth/apple", "POST", Some(request))
+        self.initiate_oauth(OAuthProvider::Apple, client_id, invite_code)
             .await
     }
 
@@ -872,22 +2261,8 @@ impl OpenSecretClient {
         state: String,
         invite_code: String,
     ) -> Result<LoginResponse> {
-        let request = OAuthCallbackRequest {
-            code,
-            state,
-            invite_code,
-        };
-
-        let response: LoginResponse = self
-            .encrypted_api_call("/auth/apple/callback", "POST", Some(request))
-            .await?;
-
-        self.session_manager.set_tokens(
-            response.access_token.clone(),
-            Some(response.refresh_token.clone()),
-        )?;
-
-        Ok(response)
+        self.complete_oauth(OAuthProvider::Apple, code, state, invite_code)
+            .await
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -925,22 +2300,93 @@ impl OpenSecretClient {
         Ok(response)
     }
 
+    /// Exchanges the stored refresh token for new tokens.
+    ///
+    /// Concurrent callers are serialized: a caller that waited while another
+    /// refresh completed returns without refreshing again, so a one-time-use
+    /// refresh token is never sent twice. Reusing a token the server already
+    /// rotated away from fails with [`Error::StaleRefreshToken`]; the caller
+    /// must log in again.
     pub async fn refresh_token(&self) -> Result<()> {
-        let refresh_token = self
-            .session_manager
-            .get_refresh_token()?
-            .ok_or_else(|| Error::Authentication("No refresh token available".to_string()))?;
+        let observed_token = self.session_manager.get_refresh_token()?;
+        let _refresh_guard = self.session_manager.refresh_lock().lock().await;
+        self.refresh_token_locked(observed_token).await
+    }
 
-        let request = RefreshRequest { refresh_token };
+    /// Refreshes only when the access token expires within `threshold`, and
+    /// returns whether it did. Tokens without a readable `exp` claim are left
+    /// alone; the 401 retry still covers them.
+    ///
+    /// The check runs under the refresh lock, so concurrent callers near
+    /// expiry trigger a single refresh.
+    pub async fn refresh_if_needed(&self, threshold: std::time::Duration) -> Result<bool> {
+        let _refresh_guard = self.session_manager.refresh_lock().lock().await;
 
-        let response: RefreshResponse = self
-            .encrypted_api_call("/refresh", "POST", Some(request))
-            .await?;
+        let access_token = self
+            .session_manager
+            .get_access_token()?
+            .ok_or_else(|| Error::Authentication("No access token available".to_string()))?;
+        let Some(expiry) = jwt_expiry(&access_token) else {
+            return Ok(false);
+        };
 
-        self.session_manager
-            .set_tokens(response.access_token, Some(response.refresh_token))?;
+        let threshold = chrono::TimeDelta::from_std(threshold).unwrap_or(chrono::TimeDelta::MAX);
+        if expiry - chrono::Utc::now() > threshold {
+            return Ok(false);
+        }
 
-        Ok(())
+        let observed_token = self.session_manager.get_refresh_token()?;
+        self.refresh_token_locked(observed_token).await?;
+        Ok(true)
+    }
+
+    /// Body of [`Self::refresh_token`]; the caller holds the refresh lock.
+    async fn refresh_token_locked(&self, observed_token: Option<String>) -> Result<()> {
+        let refresh_token = self
+            .session_manager
+            .get_refresh_token()?
+            .ok_or_else(|| Error::Authentication("No refresh token available".to_string()))?;
+
+        if observed_token.as_deref() != Some(refresh_token.as_str()) {
+            // Another task refreshed while we waited; its tokens are current.
+            return Ok(());
+        }
+
+        if self
+            .session_manager
+            .is_refresh_token_spent(&refresh_token)?
+        {
+            return Err(Error::StaleRefreshToken(
+                "refresh token was already used; log in again".to_string(),
+            ));
+        }
+
+        let request = RefreshRequest {
+            refresh_token: refresh_token.clone(),
+        };
+
+        let response: RefreshResponse = match self
+            .encrypted_api_call("/refresh", "POST", Some(request))
+            .await
+        {
+            Err(Error::Api {
+                status: 401,
+                message,
+            }) if self.session_manager.refresh_tokens_rotate()? => {
+                return Err(Error::StaleRefreshToken(message));
+            }
+            result => result?,
+        };
+
+        if response.refresh_token != refresh_token {
+            self.session_manager
+                .mark_refresh_token_spent(refresh_token)?;
+        }
+
+        self.session_manager
+            .set_tokens(response.access_token, Some(response.refresh_token))?;
+
+        Ok(())
     }
 
     async fn logout_inner(&self, push_device_id: Option<Uuid>) -> Result<()> {
@@ -954,8 +2400,7 @@ impl OpenSecretClient {
             push_device_id,
         };
 
-        let _: serde_json::Value = self
-            .encrypted_api_call("/logout", "POST", Some(request))
+        self.encrypted_no_content_call("/logout", "POST", Some(request))
             .await?;
 
         // Clear all session data
@@ -980,6 +2425,17 @@ impl OpenSecretClient {
         self.session_manager.get_refresh_token()
     }
 
+    /// The `exp` claim of the current access token, read without verifying
+    /// the signature. `None` when logged out or when the token is not a JWT
+    /// with a numeric `exp`.
+    pub fn access_token_expiry(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        Ok(self
+            .session_manager
+            .get_access_token()?
+            .as_deref()
+            .and_then(jwt_expiry))
+    }
+
     pub fn set_tokens(&self, access_token: String, refresh_token: Option<String>) -> Result<()> {
         self.session_manager.clear_session()?;
         self.session_manager.set_tokens(access_token, refresh_token)
@@ -1032,8 +2488,7 @@ impl OpenSecretClient {
         // URL-encode the name to handle special characters
         let encoded_name = utf8_percent_encode(name, NON_ALPHANUMERIC).to_string();
         let url = format!("/protected/api-keys/{}", encoded_name);
-        let _: serde_json::Value = self
-            .authenticated_api_call(&url, "DELETE", None::<()>)
+        self.authenticated_no_content_call(&url, "DELETE", None::<()>)
             .await?;
         Ok(())
     }
@@ -1051,18 +2506,32 @@ impl OpenSecretClient {
         self.authenticated_api_call(&url, "PUT", Some(value)).await
     }
 
+    /// Reads `key` and parses its value as JSON. A missing key (404) is
+    /// `Ok(None)`.
+    pub async fn kv_get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.kv_get(key).await {
+            Ok(value) => Ok(Some(serde_json::from_str(&value)?)),
+            Err(Error::Api { status: 404, .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Stores `value` under `key` as JSON.
+    pub async fn kv_put_json<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        self.kv_put(key, serde_json::to_string(value)?).await?;
+        Ok(())
+    }
+
     pub async fn kv_delete(&self, key: &str) -> Result<()> {
         let encoded_key = utf8_percent_encode(key, NON_ALPHANUMERIC).to_string();
         let url = format!("/protected/kv/{}", encoded_key);
-        let _: serde_json::Value = self
-            .authenticated_api_call(&url, "DELETE", None::<()>)
+        self.authenticated_no_content_call(&url, "DELETE", None::<()>)
             .await?;
         Ok(())
     }
 
     pub async fn kv_delete_all(&self) -> Result<()> {
-        let _: serde_json::Value = self
-            .authenticated_api_call("/protected/kv", "DELETE", None::<()>)
+        self.authenticated_no_content_call("/protected/kv", "DELETE", None::<()>)
             .await?;
         Ok(())
     }
@@ -1074,6 +2543,7 @@ impl OpenSecretClient {
 
     // Private Key APIs
     pub async fn get_private_key(&self, options: Option<KeyOptions>) -> Result<PrivateKeyResponse> {
+        self.check_key_options(options.as_ref())?;
         let mut url = "/protected/private_key".to_string();
         if let Some(opts) = &options {
             let mut params = Vec::new();
@@ -1090,13 +2560,62 @@ impl OpenSecretClient {
                 url.push_str(&params.join("&"));
             }
         }
-        self.authenticated_api_call(&url, "GET", None::<()>).await
+        let response: PrivateKeyResponse =
+            self.authenticated_api_call(&url, "GET", None::<()>).await?;
+        response.validate()?;
+        Ok(response)
+    }
+
+    /// Fetches a BIP-85 child mnemonic with the requested number of words.
+    ///
+    /// This builds the `m/83696968'/39'/0'/{words}'/0'` path for you, so
+    /// `key_options` must not also set `seed_phrase_derivation_path`.
+    pub async fn get_private_key_words(
+        &self,
+        count: WordCount,
+        key_options: Option<KeyOptions>,
+    ) -> Result<PrivateKeyResponse> {
+        let mut options = key_options.unwrap_or(KeyOptions {
+            private_key_derivation_path: None,
+            seed_phrase_derivation_path: None,
+        });
+
+        if options.seed_phrase_derivation_path.is_some() {
+            return Err(Error::Configuration(
+                "seed_phrase_derivation_path is derived from the word count and cannot be set"
+                    .to_string(),
+            ));
+        }
+
+        options.seed_phrase_derivation_path = Some(count.bip85_path(0)?);
+        let response = self.get_private_key(Some(options)).await?;
+        if response.word_count() != count.words() as usize {
+            return Err(Error::InvalidResponse(format!(
+                "Requested a {}-word mnemonic but received {} words",
+                count.words(),
+                response.word_count()
+            )));
+        }
+        Ok(response)
+    }
+
+    /// Derives a Bitcoin output descriptor (account 0, receive chain) from the
+    /// user's mnemonic, e.g. `wpkh([fingerprint/84'/0'/0']xpub.../0/*)#checksum`.
+    #[cfg(feature = "bitcoin")]
+    pub async fn get_output_descriptor(
+        &self,
+        key_options: Option<KeyOptions>,
+        script_type: crate::descriptor::DescriptorScriptType,
+    ) -> Result<String> {
+        let response = self.get_private_key(key_options).await?;
+        crate::descriptor::output_descriptor(&response.mnemonic, script_type)
     }
 
     pub async fn get_private_key_bytes(
         &self,
         options: Option<KeyOptions>,
     ) -> Result<PrivateKeyBytesResponse> {
+        self.check_key_options(options.as_ref())?;
         let mut url = "/protected/private_key_bytes".to_string();
         if let Some(opts) = &options {
             let mut params = Vec::new();
@@ -1123,7 +2642,53 @@ impl OpenSecretClient {
         algorithm: SigningAlgorithm,
         key_options: Option<KeyOptions>,
     ) -> Result<SignMessageResponse> {
-        let message_base64 = BASE64.encode(message_bytes);
+        self.sign_bytes(message_bytes, algorithm, key_options, None)
+            .await
+    }
+
+    /// Signs a precomputed 32-byte digest as is, for schemes that define
+    /// their own hashing (Ethereum `personal_sign`, PSBT sighashes). Anything
+    /// other than 32 bytes is rejected before a request is sent.
+    ///
+    /// A server that does not support `hash_already_computed` ignores it and
+    /// signs `sha256(digest)` instead. Its `message_hash` then differs from
+    /// `digest`, and the response is rejected with
+    /// [`Error::InvalidResponse`].
+    pub async fn sign_digest(
+        &self,
+        digest: &[u8],
+        algorithm: SigningAlgorithm,
+        key_options: Option<KeyOptions>,
+    ) -> Result<SignMessageResponse> {
+        if digest.len() != 32 {
+            return Err(Error::Configuration(format!(
+                "Digest must be 32 bytes, got {}",
+                digest.len()
+            )));
+        }
+        let response = self
+            .sign_bytes(digest, algorithm, key_options, Some(true))
+            .await?;
+        if !response
+            .message_hash
+            .eq_ignore_ascii_case(&hex::encode(digest))
+        {
+            return Err(Error::InvalidResponse(
+                "Server signed a hash of the digest instead of the digest itself".to_string(),
+            ));
+        }
+        Ok(response)
+    }
+
+    async fn sign_bytes(
+        &self,
+        bytes: &[u8],
+        algorithm: SigningAlgorithm,
+        key_options: Option<KeyOptions>,
+        hash_already_computed: Option<bool>,
+    ) -> Result<SignMessageResponse> {
+        self.check_key_options(key_options.as_ref())?;
+        let message_base64 = BASE64.encode(bytes);
         let request = SignMessageRequest {
             message_base64,
             algorithm,
@@ -1131,17 +2696,42 @@ impl OpenSecretClient {
                 private_key_derivation_path: opts.private_key_derivation_path,
                 seed_phrase_derivation_path: opts.seed_phrase_derivation_path,
             }),
+            hash_already_computed,
         };
         self.authenticated_api_call("/protected/sign_message", "POST", Some(request))
             .await
     }
 
+    /// Signs each of `messages` with the same key, issuing up to
+    /// [`SIGN_MESSAGES_CONCURRENCY`] requests at a time. Results are in the
+    /// same order as `messages`, each with its own outcome, so one failed
+    /// signature does not discard the others. Invalid `key_options` fail the
+    /// whole call before anything is sent.
+    pub async fn sign_messages(
+        &self,
+        messages: &[Vec<u8>],
+        algorithm: SigningAlgorithm,
+        key_options: Option<KeyOptions>,
+    ) -> Result<Vec<Result<SignMessageResponse>>> {
+        use futures::StreamExt;
+
+        self.check_key_options(key_options.as_ref())?;
+        let requests = messages
+            .iter()
+            .map(|message| self.sign_message(message, algorithm.clone(), key_options.clone()));
+        Ok(futures::stream::iter(requests)
+            .buffered(SIGN_MESSAGES_CONCURRENCY)
+            .collect()
+            .await)
+    }
+
     // Public Key API
     pub async fn get_public_key(
         &self,
         algorithm: SigningAlgorithm,
         key_options: Option<KeyOptions>,
     ) -> Result<PublicKeyResponse> {
+        self.check_key_options(key_options.as_ref())?;
         let mut url = format!(
             "/protected/public_key?algorithm={}",
             match algorithm {
@@ -1162,6 +2752,63 @@ impl OpenSecretClient {
         self.authenticated_api_call(&url, "GET", None::<()>).await
     }
 
+    /// Fetches the public key at each of `paths` (private key derivation
+    /// paths), issuing up to [`PUBLIC_KEY_BATCH_SIZE`] requests at a time.
+    /// Results are in the same order as `paths`.
+    pub async fn get_public_keys(
+        &self,
+        algorithm: SigningAlgorithm,
+        paths: &[String],
+    ) -> Result<Vec<PublicKeyResponse>> {
+        let mut keys = Vec::with_capacity(paths.len());
+        for batch in paths.chunks(PUBLIC_KEY_BATCH_SIZE) {
+            let requests = batch.iter().map(|path| {
+                self.get_public_key(
+                    algorithm.clone(),
+                    Some(KeyOptions {
+                        private_key_derivation_path: Some(path.clone()),
+                        seed_phrase_derivation_path: None,
+                    }),
+                )
+            });
+            keys.extend(futures::future::try_join_all(requests).await?);
+        }
+        Ok(keys)
+    }
+
+    /// Searches `search_space` for the derivation path whose key pays to
+    /// `target_address`, for recovering a wallet whose path was lost. Returns
+    /// `Ok(None)` if no path in the space matches.
+    ///
+    /// Keys are fetched in batches and the search stops at the first match.
+    /// Dropping the returned future cancels the search after the batch in
+    /// flight; wrap it in `tokio::time::timeout` or `tokio::select!` to bound
+    /// its running time.
+    #[cfg(feature = "bitcoin")]
+    pub async fn find_derivation_path(
+        &self,
+        target_address: &str,
+        algorithm: SigningAlgorithm,
+        search_space: &crate::descriptor::DerivationSearchSpace,
+    ) -> Result<Option<String>> {
+        use crate::descriptor::{address_script_pubkey, public_key_script_pubkey};
+
+        let target = address_script_pubkey(target_address)?;
+        let candidates = search_space.paths(&algorithm)?;
+
+        for batch in candidates.chunks(PUBLIC_KEY_BATCH_SIZE) {
+            let paths: Vec<String> = batch.iter().map(|(path, _)| path.clone()).collect();
+            let keys = self.get_public_keys(algorithm.clone(), &paths).await?;
+
+            for ((path, script_type), key) in batch.iter().zip(keys) {
+                if public_key_script_pubkey(&key.public_key, *script_type)? == target {
+                    return Ok(Some(path.clone()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
     // Third Party Token API
     pub async fn generate_third_party_token(
         &self,
@@ -1178,6 +2825,7 @@ impl OpenSecretClient {
         data: String,
         key_options: Option<KeyOptions>,
     ) -> Result<EncryptDataResponse> {
+        self.check_key_options(key_options.as_ref())?;
         let request = EncryptDataRequest {
             data,
             key_options: key_options.map(|opts| EncryptionKeyOptions {
@@ -1194,6 +2842,7 @@ impl OpenSecretClient {
         encrypted_data: String,
         key_options: Option<KeyOptions>,
     ) -> Result<String> {
+        self.check_key_options(key_options.as_ref())?;
         let request = DecryptDataRequest {
             encrypted_data,
             key_options: key_options.map(|opts| EncryptionKeyOptions {
@@ -1231,8 +2880,28 @@ impl OpenSecretClient {
         Ok(())
     }
 
+    /// Changes the account email address. The password confirms the change;
+    /// the server then sends a verification email to the new address, which
+    /// is confirmed with [`Self::verify_email`].
+    pub async fn change_email(
+        &self,
+        new_email: String,
+        password: String,
+    ) -> Result<ChangeEmailResponse> {
+        let request = ChangeEmailRequest {
+            new_email,
+            password,
+        };
+        self.authenticated_api_call("/protected/change_email", "POST", Some(request))
+            .await
+    }
+
     /// Requests a password reset for the given email
     /// Note: This does not require authentication but still uses encryption
+    ///
+    /// `hashed_secret` is [`crypto::hash_secret`] of a secret you keep for
+    /// [`Self::confirm_password_reset`]; see
+    /// [`Self::request_password_reset_with_secret`].
     pub async fn request_password_reset(
         &self,
         email: String,
@@ -1244,12 +2913,24 @@ impl OpenSecretClient {
             hashed_secret,
             client_id,
         };
-        let _: serde_json::Value = self
-            .encrypted_api_call("/password-reset/request", "POST", Some(request))
+        self.encrypted_no_content_call("/password-reset/request", "POST", Some(request))
             .await?;
         Ok(())
     }
 
+    /// Like [`Self::request_password_reset`], but hashes `plaintext_secret`
+    /// (e.g. from [`crypto::generate_secret`]) itself. Pass the same secret to
+    /// [`Self::confirm_password_reset`].
+    pub async fn request_password_reset_with_secret(
+        &self,
+        email: String,
+        plaintext_secret: &str,
+        client_id: Uuid,
+    ) -> Result<()> {
+        self.request_password_reset(email, crypto::hash_secret(plaintext_secret), client_id)
+            .await
+    }
+
     /// Confirms a password reset with the code from email
     /// Note: This does not require authentication but still uses encryption
     pub async fn confirm_password_reset(
@@ -1267,8 +2948,7 @@ impl OpenSecretClient {
             new_password,
             client_id,
         };
-        let _: serde_json::Value = self
-            .encrypted_api_call("/password-reset/confirm", "POST", Some(request))
+        self.encrypted_no_content_call("/password-reset/confirm", "POST", Some(request))
             .await?;
         Ok(())
     }
@@ -1276,8 +2956,7 @@ impl OpenSecretClient {
     /// Verifies an email address with the code from the verification email
     /// Note: This does not require authentication but still uses encryption
     pub async fn verify_email(&self, code: String) -> Result<()> {
-        let _: serde_json::Value = self
-            .encrypted_api_call(&format!("/verify-email/{}", code), "GET", None::<()>)
+        self.encrypted_no_content_call(&format!("/verify-email/{}", code), "GET", None::<()>)
             .await?;
         Ok(())
     }
@@ -1285,21 +2964,39 @@ impl OpenSecretClient {
     /// Requests a new email verification code
     pub async fn request_new_verification_code(&self) -> Result<()> {
         let request = RequestVerificationCodeRequest {};
-        let _: serde_json::Value = self
-            .authenticated_api_call("/protected/request_verification", "POST", Some(request))
-            .await?;
+        self.authenticated_no_content_call(
+            "/protected/request_verification",
+            "POST",
+            Some(request),
+        )
+        .await?;
         Ok(())
     }
 
     /// Initiates the account deletion process
+    ///
+    /// `hashed_secret` is [`crypto::hash_secret`] of a secret you keep for
+    /// [`Self::confirm_account_deletion`]; see
+    /// [`Self::request_account_deletion_with_secret`].
     pub async fn request_account_deletion(&self, hashed_secret: String) -> Result<()> {
         let request = InitiateAccountDeletionRequest { hashed_secret };
-        let _: serde_json::Value = self
-            .authenticated_api_call("/protected/delete-account/request", "POST", Some(request))
-            .await?;
+        self.authenticated_no_content_call(
+            "/protected/delete-account/request",
+            "POST",
+            Some(request),
+        )
+        .await?;
         Ok(())
     }
 
+    /// Like [`Self::request_account_deletion`], but hashes
+    /// `plaintext_secret` (e.g. from [`crypto::generate_secret`]) itself. Pass
+    /// the same secret to [`Self::confirm_account_deletion`].
+    pub async fn request_account_deletion_with_secret(&self, plaintext_secret: &str) -> Result<()> {
+        self.request_account_deletion(crypto::hash_secret(plaintext_secret))
+            .await
+    }
+
     /// Confirms account deletion with the code from email
     pub async fn confirm_account_deletion(
         &self,
@@ -1310,9 +3007,12 @@ impl OpenSecretClient {
             confirmation_code,
             plaintext_secret,
         };
-        let _: serde_json::Value = self
-            .authenticated_api_call("/protected/delete-account/confirm", "POST", Some(request))
-            .await?;
+        self.authenticated_no_content_call(
+            "/protected/delete-account/confirm",
+            "POST",
+            Some(request),
+        )
+        .await?;
         Ok(())
     }
 
@@ -1507,6 +3207,14 @@ impl OpenSecretClient {
             .await
     }
 
+    /// Fetches a single model's metadata. Fails with `Error::Api { status: 404, .. }`
+    /// when the model does not exist.
+    pub async fn get_model(&self, id: &str) -> Result<Model> {
+        let encoded_id = utf8_percent_encode(id, NON_ALPHANUMERIC).to_string();
+        self.encrypted_openai_call(&format!("/v1/models/{}", encoded_id), "GET", None::<()>)
+            .await
+    }
+
     /// Creates embeddings for the given input text(s)
     ///
     /// # Example
@@ -1525,15 +3233,289 @@ impl OpenSecretClient {
             .await
     }
 
-    /// Creates a chat completion (non-streaming)
+    /// Generates spoken audio for `request.input` and returns the encoded
+    /// audio bytes, in `request.response_format` (mp3 unless set).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let audio = client
+    ///     .create_speech(SpeechRequest::new("kokoro", "Hello, world!", "af_sky"))
+    ///     .await?;
+    /// std::fs::write("hello.mp3", audio)?;
+    /// ```
+    pub async fn create_speech(&self, request: SpeechRequest) -> Result<Vec<u8>> {
+        self.encrypted_openai_bytes_call("/v1/audio/speech", "POST", Some(request))
+            .await
+    }
+
+    /// Transcribes an audio file, e.g. with a Whisper model.
+    ///
+    /// The audio is base64-encoded into the encrypted request, which is
+    /// about a third larger than the file. `progress`, if given, is called as
+    /// that request is sent with the bytes sent so far and its total size; it
+    /// starts over if the request has to be retried after a new handshake.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let audio = std::fs::read("meeting.mp3")?;
+    /// let transcript = client
+    ///     .create_transcription(
+    ///         &audio,
+    ///         "meeting.mp3",
+    ///         "whisper-large-v3",
+    ///         Some("en"),
+    ///         None,
+    ///         Some(Box::new(|sent, total| println!("{sent}/{total:?}"))),
+    ///     )
+    ///     .await?;
+    /// println!("{}", transcript.text);
+    /// ```
+    pub async fn create_transcription(
+        &self,
+        file: &[u8],
+        filename: &str,
+        model: &str,
+        language: Option<&str>,
+        response_format: Option<&str>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<TranscriptionResponse> {
+        let request = TranscriptionRequest {
+            file: BASE64.encode(file),
+            filename: filename.to_string(),
+            content_type: audio_content_type(filename).to_string(),
+            model: model.to_string(),
+            language: language.map(str::to_string),
+            response_format: response_format.map(str::to_string),
+        };
+
+        let mut handle = self.handle(self.session_manager.clone());
+        handle.upload_progress = progress.map(|progress| Arc::new(Mutex::new(progress)));
+        let body = handle
+            .encrypted_openai_bytes_call("/v1/audio/transcriptions", "POST", Some(request))
+            .await?;
+        TranscriptionResponse::from_body(&body)
+    }
+
+    /// Generates images from a text prompt.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut request = ImageGenerationRequest::new("a lighthouse at dusk");
+    /// request.response_format = Some("b64_json".to_string());
+    /// let response = client.create_image(request).await?;
+    /// ```
+    pub async fn create_image(
+        &self,
+        request: ImageGenerationRequest,
+    ) -> Result<ImageGenerationResponse> {
+        self.encrypted_openai_call("/v1/images/generations", "POST", Some(request))
+            .await
+    }
+
+    /// Classifies `input` against the moderation categories, e.g. to screen
+    /// user text before sending it to a chat completion. Pass several strings
+    /// to get one result per string.
+    pub async fn create_moderation(
+        &self,
+        input: EmbeddingInput,
+        model: Option<String>,
+    ) -> Result<ModerationResponse> {
+        let request = ModerationRequest { input, model };
+        self.encrypted_openai_call("/v1/moderations", "POST", Some(request))
+            .await
+    }
+
+    /// Embeds `inputs` in requests of at most `max_batch` inputs each, with up
+    /// to [`EMBEDDING_BATCH_CONCURRENCY`] requests in flight, and returns one
+    /// response: embeddings in input order with `index` set to the position in
+    /// `inputs`, and usage summed across requests. Fails on the first failed
+    /// request.
+    pub async fn create_embeddings_batched(
+        &self,
+        inputs: Vec<String>,
+        model: impl Into<String>,
+        max_batch: usize,
+    ) -> Result<EmbeddingResponse> {
+        use futures::TryStreamExt;
+
+        if max_batch == 0 {
+            return Err(Error::Configuration(
+                "max_batch must be at least 1".to_string(),
+            ));
+        }
+
+        let model = model.into();
+        let requests = inputs.chunks(max_batch).map(|batch| {
+            let request = EmbeddingRequest {
+                input: EmbeddingInput::Multiple(batch.to_vec()),
+                model: model.clone(),
+                encoding_format: None,
+                dimensions: None,
+                user: None,
+            };
+            let expected = batch.len();
+            async move {
+                let response = self.create_embeddings(request).await?;
+                if response.data.len() != expected {
+                    return Err(Error::InvalidResponse(format!(
+                        "Expected {} embeddings, got {}",
+                        expected,
+                        response.data.len()
+                    )));
+                }
+                Ok(response)
+            }
+        });
+        let responses: Vec<EmbeddingResponse> = futures::stream::iter(requests)
+            .buffered(EMBEDDING_BATCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let mut combined = EmbeddingResponse {
+            object: "list".to_string(),
+            data: Vec::with_capacity(inputs.len()),
+            model,
+            usage: EmbeddingUsage {
+                prompt_tokens: 0,
+                total_tokens: 0,
+            },
+        };
+        for response in responses {
+            let offset = combined.data.len();
+            let mut data = response.data;
+            data.sort_by_key(|embedding| embedding.index);
+            for (position, mut embedding) in data.into_iter().enumerate() {
+                embedding.index = (offset + position) as i32;
+                combined.data.push(embedding);
+            }
+            combined.model = response.model;
+            combined.usage.prompt_tokens += response.usage.prompt_tokens;
+            combined.usage.total_tokens += response.usage.total_tokens;
+        }
+        Ok(combined)
+    }
+
+    /// Embeds `texts` in sub-batches of at most `max_batch`, yielding
+    /// `(index, vector)` pairs, where `index` is the position in `texts`, as
+    /// each sub-batch completes. Order across sub-batches is not preserved.
+    ///
+    /// Indices in `completed` are skipped, so an interrupted job can resume by
+    /// passing the indices it already stored. Cancelling `cancel` stops new
+    /// sub-batches, abandons those in flight and yields [`Error::Cancelled`].
+    /// The stream ends after the first error.
+    pub fn create_embeddings_resumable(
+        &self,
+        texts: Vec<String>,
+        model: impl Into<String>,
+        max_batch: usize,
+        completed: &HashSet<usize>,
+        cancel: CancellationToken,
+    ) -> Result<EmbeddingStream<'_>> {
+        if max_batch == 0 {
+            return Err(Error::Configuration(
+                "max_batch must be at least 1".to_string(),
+            ));
+        }
+
+        let pending: Vec<(usize, String)> = texts
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !completed.contains(index))
+            .collect();
+        let batches: Vec<Vec<(usize, String)>> =
+            pending.chunks(max_batch).map(<[_]>::to_vec).collect();
+        let model = model.into();
+
+        let results = futures::stream::iter(batches)
+            .map(move |batch| {
+                let model = model.clone();
+                let cancel = cancel.clone();
+                async move {
+                    let (indices, inputs): (Vec<usize>, Vec<String>) = batch.into_iter().unzip();
+                    let request = EmbeddingRequest {
+                        input: EmbeddingInput::Multiple(inputs),
+                        model,
+                        encoding_format: None,
+                        dimensions: None,
+                        user: None,
+                    };
+
+                    let response = tokio::select! {
+                        biased;
+                        _ = cancel.cancelled() => return Err(Error::Cancelled),
+                        response = self.create_embeddings(request) => response?,
+                    };
+                    if response.data.len() != indices.len() {
+                        return Err(Error::InvalidResponse(format!(
+                            "Expected {} embeddings, got {}",
+                            indices.len(),
+                            response.data.len()
+                        )));
+                    }
+
+                    response
+                        .data
+                        .into_iter()
+                        .map(|data| {
+                            let index = usize::try_from(data.index)
+                                .ok()
+                                .and_then(|position| indices.get(position))
+                                .ok_or_else(|| {
+                                    Error::InvalidResponse(format!(
+                                        "Embedding index {} out of range",
+                                        data.index
+                                    ))
+                                })?;
+                            let vector = data.embedding.into_iter().map(|x| x as f32).collect();
+                            Ok((*index, vector))
+                        })
+                        .collect::<Result<Vec<_>>>()
+                }
+            })
+            .buffer_unordered(EMBEDDING_BATCH_CONCURRENCY)
+            .flat_map(|batch| {
+                futures::stream::iter(match batch {
+                    Ok(vectors) => vectors.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                })
+            });
+
+        let mut failed = false;
+        Ok(Box::pin(results.take_while(move |item| {
+            let keep = !failed;
+            failed |= item.is_err();
+            futures::future::ready(keep)
+        })))
+    }
+
+    /// Creates a chat completion and returns it once it is complete. The
+    /// server only streams completions, so this is
+    /// [`Self::create_chat_completion_collected`].
     pub async fn create_chat_completion(
         &self,
         request: ChatCompletionRequest,
     ) -> Result<ChatCompletionResponse> {
-        let mut modified_request = request;
-        modified_request.stream = Some(false);
-        self.encrypted_openai_call("/v1/chat/completions", "POST", Some(modified_request))
-            .await
+        self.create_chat_completion_collected(request).await
+    }
+
+    /// Drives [`Self::create_chat_completion_stream`] to the end and returns
+    /// the assembled response: one choice per `choices[].index` (several
+    /// when `n` is set) with concatenated content, tool calls merged by
+    /// index (fragments of several calls may interleave) and the last
+    /// reported usage. See [`ChatCompletionCollector`] to do the same while
+    /// also consuming the stream yourself.
+    pub async fn create_chat_completion_collected(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse> {
+        use futures::StreamExt;
+
+        let mut stream = self.create_chat_completion_stream(request).await?;
+        let mut collector = ChatCompletionCollector::new();
+        while let Some(chunk) = stream.next().await {
+            collector.push(&chunk?);
+        }
+        Ok(collector.finish())
     }
 
     /// Creates a streaming chat completion
@@ -1545,73 +3527,176 @@ impl OpenSecretClient {
         use eventsource_stream::Eventsource;
         use futures::StreamExt;
 
+        request.validate_extra()?;
         let mut modified_request = request;
         modified_request.stream = Some(true);
         modified_request.stream_options = Some(StreamOptions {
             include_usage: true,
         });
+        let requested_model = modified_request.model.clone();
 
+        let cancel = self.cancellation_token()?;
         let (response, session_key) = self
-            .retry_encrypted_stream_call(
+            .cancellable(self.retry_encrypted_stream_call(
                 "/v1/chat/completions",
                 "POST",
                 Some(modified_request),
                 AuthHeaderMode::ApiKeyOrJwt,
-                true,
-            )
+                self.auto_refresh,
+            ))
             .await?;
 
         let stream = response
             .bytes_stream()
             .map(|result| result.map_err(std::io::Error::other));
 
+        let mut decoder = EventDecoder::new(session_key, "chunk");
+        let mut json = JsonReassembler::default();
+        let received_data = Arc::new(AtomicBool::new(false));
+        let received = received_data.clone();
         let event_stream = stream.eventsource().filter_map(move |event| {
-            let session_key = session_key;
-            async move {
-                match event {
-                    Ok(event) => {
-                        // Check if this is the [DONE] event
-                        if event.data == "[DONE]" {
-                            return None;
-                        }
+            let item = match event {
+                // Check if this is the [DONE] event
+                Ok(event) if event.data == "[DONE]" => {
+                    received.store(true, Ordering::Relaxed);
+                    None
+                }
+                // Decrypt the event data - server sends base64 encrypted chunks.
+                // Skip non-base64 events (heartbeats, retries, etc.) to match TS SDK.
+                // A chunk split over several events is yielded once it is complete.
+                Ok(event) => decoder
+                    .decode(&event.data)
+                    .and_then(|decoded| match decoded {
+                        Ok(json_str) => json.push::<ChatCompletionChunk>(&json_str),
+                        Err(e) => Some(Err(e)),
+                    }),
+                Err(e) => Some(Err(Error::Api {
+                    status: 0,
+                    message: format!("SSE error: {}", e),
+                })),
+            };
+            if item.is_some() {
+                received.store(true, Ordering::Relaxed);
+            }
+            futures::future::ready(item)
+        });
 
-                        // Decrypt the event data - server sends base64 encrypted chunks.
-                        // Skip non-base64 events (heartbeats, retries, etc.) to match TS SDK.
-                        let encrypted_bytes = match BASE64.decode(&event.data) {
-                            Ok(bytes) => bytes,
-                            Err(_) => return None,
-                        };
-                        match crypto::decrypt_data(&session_key, &encrypted_bytes) {
-                            Ok(decrypted) => match String::from_utf8(decrypted) {
-                                Ok(json_str) => {
-                                    match serde_json::from_str::<ChatCompletionChunk>(&json_str) {
-                                        Ok(chunk) => Some(Ok(chunk)),
-                                        Err(e) => Some(Err(Error::Api {
-                                            status: 0,
-                                            message: format!("Failed to parse chunk: {}", e),
-                                        })),
-                                    }
-                                }
-                                Err(e) => Some(Err(Error::Api {
-                                    status: 0,
-                                    message: format!("Invalid UTF-8 in decrypted data: {}", e),
-                                })),
-                            },
-                            Err(e) => Some(Err(Error::Decryption(format!(
-                                "Failed to decrypt chunk: {}",
-                                e
-                            )))),
-                        }
-                    }
-                    Err(e) => Some(Err(Error::Api {
+        // A connection dropped before the first event would otherwise look like
+        // an empty, successful completion.
+        let event_stream = event_stream.chain(
+            futures::stream::once(async move {
+                (!received_data.load(Ordering::Relaxed)).then(|| {
+                    Err(Error::Api {
                         status: 0,
-                        message: format!("SSE error: {}", e),
-                    })),
+                        message: "stream closed before any data".to_string(),
+                    })
+                })
+            })
+            .filter_map(futures::future::ready),
+        );
+
+        let strict_model = self.strict_model;
+        let mut model_checked = false;
+        let checked_stream = event_stream.map(move |item| match item {
+            Ok(chunk) if strict_model && !model_checked => {
+                let Some(model) = chunk.0.get("model").and_then(|m| m.as_str()) else {
+                    return Ok(chunk);
+                };
+                model_checked = true;
+                if models_match(&requested_model, model) {
+                    Ok(chunk)
+                } else {
+                    Err(Error::InvalidResponse(format!(
+                        "Requested model {} but the server responded with {}",
+                        requested_model, model
+                    )))
                 }
             }
+            other => other,
         });
 
-        Ok(Box::pin(event_stream))
+        Ok(cancel_on(
+            apply_error_policy(checked_stream, self.stream_error_policy),
+            cancel,
+        ))
+    }
+
+    /// Like [`Self::create_chat_completion_stream`], returning a
+    /// [`StreamHandle`] that can stop the generation early.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut stream = client.create_chat_completion_stream_handle(request).await?;
+    /// let stop = stream.cancellation_token();
+    /// // Elsewhere, e.g. in a stop button handler: stop.cancel();
+    /// while let Some(chunk) = stream.next().await {
+    ///     // ...
+    /// }
+    /// ```
+    pub async fn create_chat_completion_stream_handle(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<StreamHandle<ChatCompletionChunk>> {
+        let stream = self.create_chat_completion_stream(request).await?;
+        Ok(StreamHandle::new(stream))
+    }
+
+    /// Like [`Self::create_chat_completion_stream`], also returning a
+    /// [`UsageHandle`] that resolves to the final token usage once the stream
+    /// has been consumed (or dropped), or `None` if the server reported none.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let (mut stream, usage) = client.create_chat_completion_stream_with_usage(request).await?;
+    /// while let Some(chunk) = stream.next().await {
+    ///     // ...
+    /// }
+    /// if let Some(usage) = usage.await {
+    ///     println!("{} tokens", usage.total_tokens);
+    /// }
+    /// ```
+    pub async fn create_chat_completion_stream_with_usage(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<(
+        std::pin::Pin<Box<dyn futures::Stream<Item = Result<ChatCompletionChunk>> + Send>>,
+        UsageHandle,
+    )> {
+        let stream = self.create_chat_completion_stream(request).await?;
+        Ok(track_usage(stream))
+    }
+
+    /// Sends a single user prompt and returns the full streamed reply.
+    pub async fn ask(&self, model: impl Into<String>, prompt: impl Into<String>) -> Result<String> {
+        let request = ChatCompletionRequest::new(model, vec![ChatMessage::user(prompt)]);
+        self.collect_stream_text(request).await
+    }
+
+    /// Like [`ask`](Self::ask), with a system message ahead of the prompt.
+    pub async fn ask_with_system(
+        &self,
+        model: impl Into<String>,
+        system: impl Into<String>,
+        prompt: impl Into<String>,
+    ) -> Result<String> {
+        let request = ChatCompletionRequest::new(
+            model,
+            vec![ChatMessage::system(system), ChatMessage::user(prompt)],
+        );
+        self.collect_stream_text(request).await
+    }
+
+    async fn collect_stream_text(&self, request: ChatCompletionRequest) -> Result<String> {
+        use futures::StreamExt;
+
+        let mut stream = self.create_chat_completion_stream(request).await?;
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            if let Some(content) = chunk?.delta_content() {
+                text.push_str(content);
+            }
+        }
+        Ok(text)
     }
 
     async fn agent_chat_stream(
@@ -1626,134 +3711,44 @@ impl OpenSecretClient {
             input: input.to_string(),
         };
 
+        let cancel = self.cancellation_token()?;
         let (response, session_key) = self
-            .retry_encrypted_stream_call(
+            .cancellable(self.retry_encrypted_stream_call(
                 &endpoint,
                 "POST",
                 Some(request),
                 AuthHeaderMode::Jwt,
-                true,
-            )
+                self.auto_refresh,
+            ))
             .await?;
 
         let stream = response
             .bytes_stream()
             .map(|result| result.map_err(std::io::Error::other));
 
+        let mut decoder = EventDecoder::new(session_key, "agent event");
         let event_stream = stream.eventsource().filter_map(move |event| {
-            let session_key = session_key;
-            async move {
-                match event {
-                    Ok(event) => {
-                        if event.data == "[DONE]" {
-                            return None;
-                        }
-
-                        // Skip non-base64 events (heartbeats, retries, etc.)
-                        let encrypted_bytes = match BASE64.decode(&event.data) {
-                            Ok(bytes) => bytes,
-                            Err(_) => return None,
-                        };
-                        match crypto::decrypt_data(&session_key, &encrypted_bytes) {
-                            Ok(decrypted) => match String::from_utf8(decrypted) {
-                                Ok(json_str) => {
-                                    let event_type = event.event.as_str();
-                                    match event_type {
-                                        "agent.message" => {
-                                            match serde_json::from_str::<AgentMessageEvent>(
-                                                &json_str,
-                                            ) {
-                                                Ok(msg) => Some(Ok(AgentSseEvent::Message(msg))),
-                                                Err(e) => Some(Err(Error::Api {
-                                                    status: 0,
-                                                    message: format!(
-                                                        "Failed to parse agent message: {}",
-                                                        e
-                                                    ),
-                                                })),
-                                            }
-                                        }
-                                        "agent.reaction" => {
-                                            match serde_json::from_str::<AgentReactionEvent>(
-                                                &json_str,
-                                            ) {
-                                                Ok(reaction) => {
-                                                    Some(Ok(AgentSseEvent::Reaction(reaction)))
-                                                }
-                                                Err(e) => Some(Err(Error::Api {
-                                                    status: 0,
-                                                    message: format!(
-                                                        "Failed to parse agent reaction: {}",
-                                                        e
-                                                    ),
-                                                })),
-                                            }
-                                        }
-                                        "agent.typing" => {
-                                            match serde_json::from_str::<AgentTypingEvent>(
-                                                &json_str,
-                                            ) {
-                                                Ok(typing) => {
-                                                    Some(Ok(AgentSseEvent::Typing(typing)))
-                                                }
-                                                Err(e) => Some(Err(Error::Api {
-                                                    status: 0,
-                                                    message: format!(
-                                                        "Failed to parse agent typing: {}",
-                                                        e
-                                                    ),
-                                                })),
-                                            }
-                                        }
-                                        "agent.done" => {
-                                            match serde_json::from_str::<AgentDoneEvent>(&json_str)
-                                            {
-                                                Ok(done) => Some(Ok(AgentSseEvent::Done(done))),
-                                                Err(e) => Some(Err(Error::Api {
-                                                    status: 0,
-                                                    message: format!(
-                                                        "Failed to parse agent done: {}",
-                                                        e
-                                                    ),
-                                                })),
-                                            }
-                                        }
-                                        "agent.error" => {
-                                            match serde_json::from_str::<AgentErrorEvent>(&json_str)
-                                            {
-                                                Ok(err) => Some(Ok(AgentSseEvent::Error(err))),
-                                                Err(e) => Some(Err(Error::Api {
-                                                    status: 0,
-                                                    message: format!(
-                                                        "Failed to parse agent error: {}",
-                                                        e
-                                                    ),
-                                                })),
-                                            }
-                                        }
-                                        _ => None,
-                                    }
-                                }
-                                Err(e) => Some(Err(Error::Api {
-                                    status: 0,
-                                    message: format!("Invalid UTF-8 in decrypted data: {}", e),
-                                })),
-                            },
-                            Err(e) => Some(Err(Error::Decryption(format!(
-                                "Failed to decrypt agent event: {}",
-                                e
-                            )))),
-                        }
-                    }
-                    Err(e) => Some(Err(Error::Api {
-                        status: 0,
-                        message: format!("SSE error: {}", e),
-                    })),
-                }
-            }
+            let item = match event {
+                Ok(event) if event.data == "[DONE]" => None,
+                // Skip non-base64 events (heartbeats, retries, etc.)
+                Ok(event) => decoder
+                    .decode(&event.data)
+                    .and_then(|decoded| match decoded {
+                        Ok(json_str) => parse_agent_event(&event.event, &json_str),
+                        Err(e) => Some(Err(e)),
+                    }),
+                Err(e) => Some(Err(Error::Api {
+                    status: 0,
+                    message: format!("SSE error: {}", e),
+                })),
+            };
+            futures::future::ready(item)
         });
 
-        Ok(Box::pin(event_stream))
+        Ok(cancel_on(
+            apply_error_policy(event_stream, self.stream_error_policy),
+            cancel,
+        ))
     }
 
     // Agent API Methods
@@ -1930,11 +3925,12 @@ impl OpenSecretClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::{server_key_exchange, SessionCipher};
     use crate::PushNotificationKeyPair;
     use futures::StreamExt;
     use serde_json::json;
     use wiremock::{
-        matchers::{header, method, path},
+        matchers::{body_partial_json, header, method, path, query_param},
         Match, Mock, MockServer, Request, Respond, ResponseTemplate,
     };
 
@@ -1978,20 +3974,15 @@ mod tests {
     impl Respond for KeyExchangeResponder {
         fn respond(&self, request: &Request) -> ResponseTemplate {
             let body: KeyExchangeRequest = serde_json::from_slice(request.body.as_ref()).unwrap();
-            let client_public_bytes = BASE64.decode(body.client_public_key.as_bytes()).unwrap();
-            let client_public_key = x25519_dalek::PublicKey::from(
-                <[u8; 32]>::try_from(client_public_bytes.as_slice()).unwrap(),
-            );
-            let server_secret = x25519_dalek::StaticSecret::from(self.server_secret_key);
-            let shared_secret =
-                crypto::perform_static_key_exchange(&server_secret, &client_public_key);
-            let encrypted_session_key = BASE64
-                .encode(crypto::encrypt_data(shared_secret.as_bytes(), &self.session_key).unwrap());
+            let response = server_key_exchange(
+                &x25519_dalek::StaticSecret::from(self.server_secret_key),
+                &body.client_public_key,
+                &self.session_key,
+                Uuid::parse_str(&self.session_id).unwrap(),
+            )
+            .unwrap();
 
-            ResponseTemplate::new(200).set_body_json(json!({
-                "encrypted_session_key": encrypted_session_key,
-                "session_id": self.session_id,
-            }))
+            ResponseTemplate::new(200).set_body_json(response)
         }
     }
 
@@ -2019,15 +4010,16 @@ mod tests {
     }
 
     fn encrypted_response<T: Serialize>(session_key: &[u8; 32], payload: &T) -> serde_json::Value {
-        let plaintext = serde_json::to_vec(payload).unwrap();
-        let encrypted = crypto::encrypt_data(session_key, &plaintext).unwrap();
-        json!({ "encrypted": BASE64.encode(encrypted) })
+        let response = SessionCipher::new(*session_key)
+            .seal_response(payload)
+            .unwrap();
+        json!({ "encrypted": response.encrypted })
     }
 
     fn encrypted_sse_data<T: Serialize>(session_key: &[u8; 32], payload: &T) -> String {
-        let plaintext = serde_json::to_vec(payload).unwrap();
-        let encrypted = crypto::encrypt_data(session_key, &plaintext).unwrap();
-        format!("data: {}\n\n", BASE64.encode(encrypted))
+        SessionCipher::new(*session_key)
+            .seal_sse_event(payload)
+            .unwrap()
     }
 
     fn decrypt_request_body<T: serde::de::DeserializeOwned>(
@@ -2035,9 +4027,7 @@ mod tests {
         session_key: &[u8; 32],
     ) -> T {
         let body: EncryptedRequest = serde_json::from_slice(request.body.as_ref()).unwrap();
-        let encrypted = BASE64.decode(body.encrypted.as_bytes()).unwrap();
-        let plaintext = crypto::decrypt_data(session_key, &encrypted).unwrap();
-        serde_json::from_slice(&plaintext).unwrap()
+        SessionCipher::new(*session_key).open(&body).unwrap()
     }
 
     struct RegisterPushDeviceResponder {
@@ -2073,6 +4063,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_normalize_base_url_keeps_path_prefix() {
+        assert_eq!(
+            normalize_base_url("https://enclave.example.com/").unwrap(),
+            "https://enclave.example.com"
+        );
+        assert_eq!(
+            normalize_base_url(" https://host.example.com/opensecret/ ").unwrap(),
+            "https://host.example.com/opensecret"
+        );
+        assert!(normalize_base_url("enclave.example.com").is_err());
+        assert!(normalize_base_url("ftp://enclave.example.com").is_err());
+        assert!(normalize_base_url("https://host.example.com/api?x=1").is_err());
+    }
+
+    #[test]
+    fn test_models_match_tolerates_aliases() {
+        assert!(models_match("llama-3.3-70b", "llama3-3-70b"));
+        assert!(models_match("llama-3.3-70b", "meta-llama/Llama-3.3-70B"));
+        assert!(models_match("gpt-4o", "gpt-4o-2024-08-06"));
+        assert!(models_match("gpt-4o-mini-2024-07-18", "openai/gpt-4o-mini"));
+        assert!(!models_match("gpt-4o", "gpt-4o-mini-2024-07-18"));
+        assert!(!models_match("llama-3.3-70b", "qwen2-5-72b"));
+        assert!(!models_match("gpt-4", "gpt-4o"));
+        assert!(!models_match("gpt-4", "gpt-4o-mini"));
+        assert!(!models_match("llama-3", "llama-3.3-70b"));
+        assert!(!models_match("", "gpt-4o"));
+        assert!(!models_match("", ""));
+    }
+
     #[test]
     fn test_build_conversations_endpoint_includes_filters() {
         let endpoint = build_conversations_endpoint(Some(&ConversationsListParams {
@@ -2687,9 +4707,7 @@ mod tests {
             temperature: Some(0.0),
             max_tokens: Some(100),
             stream: Some(true),
-            stream_options: None,
-            tools: None,
-            tool_choice: None,
+            ..Default::default()
         };
 
         let mut stream = client.create_chat_completion_stream(request).await.unwrap();
@@ -3067,4 +5085,3130 @@ mod tests {
 
         assert!(stream.next().await.is_none());
     }
+
+    #[tokio::test]
+    async fn test_handshake_rejects_low_order_server_public_key() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+
+        Mock::given(method("GET"))
+            .and(PathPrefixMatcher("/attestation/"))
+            .respond_with(AttestationResponder {
+                server_public_key: [0u8; 32],
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .respond_with(KeyExchangeResponder {
+                server_secret_key: [11u8; 32],
+                session_key: [9u8; 32],
+                session_id: Uuid::new_v4().to_string(),
+            })
+            .mount(&mock_server)
+            .await;
+
+        let error = client.perform_attestation_handshake().await.unwrap_err();
+
+        assert!(matches!(error, Error::KeyExchange(message) if message.contains("low-order")));
+        assert!(client.get_session_id().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_private_key_words_requests_bip85_path() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [36u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/protected/private_key"))
+            .and(query_param(
+                "seed_phrase_derivation_path",
+                "m/83696968'/39'/0'/18'/0'",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({ "mnemonic": format!("{} agent", ["abandon"; 17].join(" ")) }),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = client
+            .get_private_key_words(WordCount::Eighteen, None)
+            .await
+            .unwrap();
+        assert_eq!(response.word_count(), 18);
+
+        let error = client
+            .get_private_key_words(
+                WordCount::Twelve,
+                Some(KeyOptions {
+                    private_key_derivation_path: None,
+                    seed_phrase_derivation_path: Some("m/0'".to_string()),
+                }),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Configuration(_)));
+    }
+
+    #[tokio::test]
+    async fn test_strict_model_rejects_substituted_stream_model() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_strict_model(true);
+        let session_id = Uuid::new_v4();
+        let session_key = [37u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let sse_body = format!(
+            "{}data: [DONE]\n\n",
+            encrypted_sse_data(
+                &session_key,
+                &json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion.chunk",
+                    "created": 1,
+                    "model": "fallback-model",
+                    "choices": [{ "index": 0, "delta": { "content": "hi" }, "finish_reason": null }]
+                })
+            )
+        );
+
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let request = ChatCompletionRequest {
+            model: "llama-3.3-70b".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: json!("hello"),
+                tool_calls: None,
+                reasoning_content: None,
+            }],
+            stream: Some(true),
+            ..Default::default()
+        };
+
+        let mut stream = client.create_chat_completion_stream(request).await.unwrap();
+        let error = stream.next().await.unwrap().unwrap_err();
+
+        assert!(
+            matches!(error, Error::InvalidResponse(message) if message.contains("fallback-model"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_joins_multibyte_character_split_across_events() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [38u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let chunk = serde_json::to_string(&json!({
+            "id": "chatcmpl-test",
+            "object": "chat.completion.chunk",
+            "created": 1,
+            "model": "llama-3.3-70b",
+            "choices": [{ "index": 0, "delta": { "content": "你好 👋" }, "finish_reason": null }]
+        }))
+        .unwrap();
+        let split = chunk.find('👋').unwrap() + 1;
+        let sse_body = [&chunk.as_bytes()[..split], &chunk.as_bytes()[split..]]
+            .iter()
+            .map(|part| {
+                let encrypted = crypto::encrypt_data(&session_key, part).unwrap();
+                format!("data: {}\n\n", BASE64.encode(encrypted))
+            })
+            .collect::<String>()
+            + "data: [DONE]\n\n";
+
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let request = ChatCompletionRequest {
+            model: "llama-3.3-70b".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: json!("say hi"),
+                tool_calls: None,
+                reasoning_content: None,
+            }],
+            stream: Some(true),
+            ..Default::default()
+        };
+
+        let chunks: Vec<_> = client
+            .create_chat_completion_stream(request)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(chunks.len(), 1);
+        let chunk = chunks[0].as_ref().unwrap();
+        assert_eq!(chunk.0["choices"][0]["delta"]["content"], "你好 👋");
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_reassembles_chunk_split_across_events() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [39u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let chunk = serde_json::to_string(&json!({
+            "id": "chatcmpl-test",
+            "object": "chat.completion.chunk",
+            "created": 1,
+            "model": "llama-3.3-70b",
+            "choices": [{ "index": 0, "delta": { "content": "split" }, "finish_reason": null }]
+        }))
+        .unwrap();
+        let (first, second) = chunk.split_at(chunk.len() / 2);
+        let sse_body = [first, second]
+            .iter()
+            .map(|part| {
+                let encrypted = crypto::encrypt_data(&session_key, part.as_bytes()).unwrap();
+                format!("data: {}\n\n", BASE64.encode(encrypted))
+            })
+            .collect::<String>()
+            + &encrypted_sse_data(
+                &session_key,
+                &json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion.chunk",
+                    "created": 1,
+                    "model": "llama-3.3-70b",
+                    "choices": [{ "index": 0, "delta": { "content": "!" }, "finish_reason": "stop" }]
+                }),
+            )
+            + "data: [DONE]\n\n";
+
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let request = ChatCompletionRequest {
+            model: "llama-3.3-70b".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: json!("split please"),
+                tool_calls: None,
+                reasoning_content: None,
+            }],
+            stream: Some(true),
+            ..Default::default()
+        };
+
+        let chunks: Vec<_> = client
+            .create_chat_completion_stream(request)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(chunks.len(), 2);
+        let first = chunks[0].as_ref().unwrap();
+        assert_eq!(first.0["choices"][0]["delta"]["content"], "split");
+        let second = chunks[1].as_ref().unwrap();
+        assert_eq!(second.0["choices"][0]["delta"]["content"], "!");
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_yields_trailing_usage_only_chunk() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [40u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let sse_body = format!(
+            "{}{}data: [DONE]\n\n",
+            encrypted_sse_data(
+                &session_key,
+                &json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion.chunk",
+                    "created": 1,
+                    "model": "llama-3.3-70b",
+                    "choices": [{ "index": 0, "delta": { "content": "hi" }, "finish_reason": "stop" }]
+                })
+            ),
+            encrypted_sse_data(
+                &session_key,
+                &json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion.chunk",
+                    "created": 1,
+                    "model": "llama-3.3-70b",
+                    "choices": [],
+                    "usage": { "prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6 }
+                })
+            )
+        );
+
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let request = ChatCompletionRequest {
+            model: "llama-3.3-70b".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: json!("hello"),
+                tool_calls: None,
+                reasoning_content: None,
+            }],
+            stream: Some(true),
+            ..Default::default()
+        };
+
+        let chunks: Vec<_> = client
+            .create_chat_completion_stream(request)
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks.len(), 2);
+        assert!(!chunks[0].is_usage_only());
+        assert!(chunks[1].is_usage_only());
+        assert_eq!(chunks[1].usage().unwrap().total_tokens, 6);
+    }
+
+    async fn mount_stream_with_bad_chunk(mock_server: &MockServer, session_key: &[u8; 32]) {
+        let chunk = |content: &str| {
+            encrypted_sse_data(
+                session_key,
+                &json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion.chunk",
+                    "created": 1,
+                    "model": "llama-3.3-70b",
+                    "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+                }),
+            )
+        };
+        let undecryptable = BASE64.encode(crypto::encrypt_data(&[0u8; 32], b"{}").unwrap());
+        let sse_body = format!(
+            "{}data: {}\n\n{}data: [DONE]\n\n",
+            chunk("before"),
+            undecryptable,
+            chunk("after")
+        );
+
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .mount(mock_server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_error_policy_aborts_or_skips() {
+        let mock_server = MockServer::start().await;
+        let session_key = [41u8; 32];
+        mount_stream_with_bad_chunk(&mock_server, &session_key).await;
+
+        let request = ChatCompletionRequest {
+            model: "llama-3.3-70b".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: json!("hello"),
+                tool_calls: None,
+                reasoning_content: None,
+            }],
+            stream: Some(true),
+            ..Default::default()
+        };
+
+        for (policy, expected) in [
+            (StreamErrorPolicy::AbortOnError, vec![Some("before"), None]),
+            (
+                StreamErrorPolicy::SkipErrors,
+                vec![Some("before"), Some("after")],
+            ),
+        ] {
+            let client = OpenSecretClient::new(mock_server.uri())
+                .unwrap()
+                .with_stream_error_policy(policy);
+            client
+                .session_manager
+                .set_session(Uuid::new_v4(), session_key)
+                .unwrap();
+            client
+                .session_manager
+                .set_tokens("access_token".to_string(), None)
+                .unwrap();
+
+            let items: Vec<_> = client
+                .create_chat_completion_stream(request.clone())
+                .await
+                .unwrap()
+                .map(|item| {
+                    item.ok().map(|chunk| {
+                        chunk.0["choices"][0]["delta"]["content"]
+                            .as_str()
+                            .unwrap()
+                            .to_string()
+                    })
+                })
+                .collect()
+                .await;
+            let items: Vec<_> = items.iter().map(|item| item.as_deref()).collect();
+
+            assert_eq!(items, expected, "{:?}", policy);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefixed_base_url_routes_requests_under_prefix() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(format!("{}/opensecret/", mock_server.uri())).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [42u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/opensecret/protected/kv/greeting"))
+            .and(header("authorization", "Bearer access_token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(&session_key, &json!("hello"))),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/opensecret/health-check"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("OK"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_eq!(client.kv_get("greeting").await.unwrap(), "hello");
+        assert_eq!(client.test_connection().await.unwrap(), "OK");
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_reports_steps_and_stops_at_first_failure() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let server_secret_key = [12u8; 32];
+        let server_public_key =
+            x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(server_secret_key));
+
+        Mock::given(method("GET"))
+            .and(path("/health-check"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("OK"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(PathPrefixMatcher("/attestation/"))
+            .respond_with(AttestationResponder {
+                server_public_key: server_public_key.to_bytes(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("enclave unavailable"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let report = client.diagnose().await.unwrap();
+
+        assert!(!report.passed());
+        assert!(report.mock_attestation);
+        let steps: Vec<_> = report
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.passed))
+            .collect();
+        assert_eq!(steps, vec![("health", true), ("attestation", false)]);
+        assert!(matches!(
+            report.first_error,
+            Some(Error::Api { status: 503, .. })
+        ));
+        assert!(report.to_string().contains("[FAIL] attestation"));
+        assert!(serde_json::to_value(&report).unwrap()["steps"].is_array());
+    }
+
+    #[tokio::test]
+    async fn test_with_credential_overrides_api_key_for_one_call() {
+        let mock_server = MockServer::start().await;
+        let client =
+            OpenSecretClient::new_with_api_key(mock_server.uri(), "stored-key".to_string())
+                .unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [43u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let models = json!({ "object": "list", "data": [] });
+        for token in ["stored-key", "access_token", "other-key"] {
+            Mock::given(method("GET"))
+                .and(path("/v1/models"))
+                .and(header("authorization", format!("Bearer {}", token)))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(encrypted_response(&session_key, &models)),
+                )
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        client.get_models().await.unwrap();
+        client
+            .with_credential(Credential::Jwt)
+            .get_models()
+            .await
+            .unwrap();
+        client
+            .with_credential(Credential::ApiKey("other-key".to_string()))
+            .get_models()
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "dev-capture")]
+    #[tokio::test]
+    async fn test_capture_to_writes_request_and_response_envelopes() {
+        let mock_server = MockServer::start().await;
+        let capture_path =
+            std::env::temp_dir().join(format!("opensecret-capture-{}.jsonl", Uuid::new_v4()));
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .capture_to(&capture_path)
+            .unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [44u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("PUT"))
+            .and(path("/protected/kv/greeting"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(&session_key, &json!("hello"))),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        client
+            .kv_put("greeting", "hello".to_string())
+            .await
+            .unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&capture_path)
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let captured = std::fs::read_to_string(&capture_path).unwrap();
+        std::fs::remove_file(&capture_path).unwrap();
+        let records: Vec<serde_json::Value> = captured
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["direction"], "request");
+        assert_eq!(records[0]["session_key"], BASE64.encode(session_key));
+        let cipher = SessionCipher::new(session_key);
+        let request: serde_json::Value = cipher
+            .open(&EncryptedRequest {
+                encrypted: records[0]["envelope"].as_str().unwrap().to_string(),
+            })
+            .unwrap();
+        assert_eq!(request, "hello");
+        assert_eq!(records[1]["direction"], "response");
+        assert_eq!(records[1]["status"], 200);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_refreshes_send_rotated_refresh_token_once() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [45u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_0".to_string(), Some("refresh_0".to_string()))
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/refresh"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(
+                        &session_key,
+                        &json!({ "access_token": "access_1", "refresh_token": "refresh_1" }),
+                    ))
+                    .set_delay(std::time::Duration::from_millis(100)),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (first, second) = tokio::join!(client.refresh_token(), client.refresh_token());
+        first.unwrap();
+        second.unwrap();
+
+        assert_eq!(
+            client
+                .session_manager
+                .get_refresh_token()
+                .unwrap()
+                .as_deref(),
+            Some("refresh_1")
+        );
+
+        // Restoring the rotated-away token is caught before it reaches the server.
+        client
+            .session_manager
+            .set_tokens("access_0".to_string(), Some("refresh_0".to_string()))
+            .unwrap();
+        assert!(matches!(
+            client.refresh_token().await,
+            Err(Error::StaleRefreshToken(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_reports_connection_closed_before_any_data() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [46u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(": keep-alive\n\n"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let request = ChatCompletionRequest {
+            model: "llama-3.3-70b".to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: json!("hello"),
+                tool_calls: None,
+                reasoning_content: None,
+            }],
+            stream: Some(true),
+            ..Default::default()
+        };
+
+        let items: Vec<_> = client
+            .create_chat_completion_stream(request)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 1);
+        assert!(matches!(
+            &items[0],
+            Err(Error::Api { status: 0, message }) if message == "stream closed before any data"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_deep_derivation_paths_are_rejected_before_sending() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_max_derivation_depth(3);
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), [47u8; 32])
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let deep = KeyOptions {
+            private_key_derivation_path: Some("m/44'/0'/0'/0".to_string()),
+            seed_phrase_derivation_path: None,
+        };
+
+        let error = client
+            .sign_message(b"hello", SigningAlgorithm::Schnorr, Some(deep.clone()))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Configuration(message) if message.contains("depth")));
+        assert!(matches!(
+            client.encrypt_data("secret".to_string(), Some(deep)).await,
+            Err(Error::Configuration(_))
+        ));
+        assert!(mock_server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_discover_endpoints_parses_directory() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/directory.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "endpoints": [
+                    {
+                        "region": "us-east-1",
+                        "url": "https://us-east-1.enclave.example.com/",
+                        "expected_pcrs": { "0": "abcd" }
+                    },
+                    { "region": "eu-west-1", "url": "https://eu-west-1.enclave.example.com" }
+                ]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoints =
+            OpenSecretClient::discover_endpoints(&format!("{}/directory.json", mock_server.uri()))
+                .await
+                .unwrap();
+
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[0].url, "https://us-east-1.enclave.example.com");
+        assert_eq!(
+            endpoints[0].expected_pcr_bytes().unwrap().unwrap()[&0],
+            vec![0xab, 0xcd]
+        );
+        assert_eq!(endpoints[1].region, "eu-west-1");
+        assert!(endpoints[1].expected_pcr_bytes().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ask_with_system_collects_streamed_reply() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [48u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let chunk = |content: &str| {
+            encrypted_sse_data(
+                &session_key,
+                &json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion.chunk",
+                    "created": 1,
+                    "model": "llama-3.3-70b",
+                    "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+                }),
+            )
+        };
+        let sse_body = format!("{}{}data: [DONE]\n\n", chunk("Hello, "), chunk("world"));
+
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let reply = client
+            .ask_with_system("llama-3.3-70b", "Be brief.", "Say hello")
+            .await
+            .unwrap();
+        assert_eq!(reply, "Hello, world");
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: ChatCompletionRequest = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(body.messages.len(), 2);
+        assert_eq!(body.messages[0].role, "system");
+        assert_eq!(
+            body.messages[1].text_content().as_deref(),
+            Some("Say hello")
+        );
+        assert_eq!(body.stream, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_gzip_encoded_encrypted_response_is_decoded() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [49u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let body =
+            serde_json::to_vec(&encrypted_response(&session_key, &json!("compressed"))).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/greeting"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .set_body_raw(gzipped, "application/json"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_eq!(client.kv_get("greeting").await.unwrap(), "compressed");
+    }
+
+    #[test]
+    fn test_pinned_tls_spki_rejects_malformed_pins_and_custom_clients() {
+        let builder = || OpenSecretClient::builder().base_url("https://example.com");
+        let error = builder()
+            .pinned_tls_spki(vec![vec![0u8; 16]])
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::Configuration(_)));
+
+        assert!(builder()
+            .pinned_tls_spki(vec![vec![0u8; 32]])
+            .build()
+            .is_ok());
+
+        let error = builder()
+            .http_client(Client::new())
+            .pinned_tls_spki(vec![vec![0u8; 32]])
+            .build()
+            .err()
+            .unwrap();
+        assert!(
+            matches!(error, Error::Configuration(message) if message.contains("custom HTTP client"))
+        );
+    }
+
+    #[cfg(feature = "bitcoin")]
+    #[tokio::test]
+    async fn test_find_derivation_path_returns_matching_path() {
+        use crate::descriptor::{DerivationSearchSpace, DescriptorScriptType};
+
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [48u8; 32];
+
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/protected/public_key"))
+            .and(query_param("private_key_derivation_path", "m/84'/0'/0'/0/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({
+                    "public_key": "0330d54fd0dd420a6e5f8d3624f5f3482cae350f79d5f0753bf5beef9c2d91af3c",
+                    "algorithm": "ecdsa",
+                }),
+            )))
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/protected/public_key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({
+                    "public_key": "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                    "algorithm": "ecdsa",
+                }),
+            )))
+            .mount(&mock_server)
+            .await;
+
+        let search_space = DerivationSearchSpace {
+            script_types: vec![DescriptorScriptType::P2wpkh],
+            coin_types: vec![0],
+            accounts: 1,
+            address_indices: 3,
+            include_change: false,
+        };
+
+        let found = client
+            .find_derivation_path(
+                "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu",
+                SigningAlgorithm::Ecdsa,
+                &search_space,
+            )
+            .await
+            .unwrap();
+        assert_eq!(found.as_deref(), Some("m/84'/0'/0'/0/1"));
+
+        let missing = client
+            .find_derivation_path(
+                "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu",
+                SigningAlgorithm::Schnorr,
+                &search_space,
+            )
+            .await
+            .unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_uses_configured_nonce_source() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_nonce_source(crate::attestation::FixedNonce("fixed-nonce".to_string()));
+        let server_secret_key = [49u8; 32];
+        let server_public_key =
+            x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(server_secret_key));
+        let session_id = Uuid::new_v4().to_string();
+
+        Mock::given(method("GET"))
+            .and(path("/attestation/fixed-nonce"))
+            .respond_with(AttestationResponder {
+                server_public_key: server_public_key.to_bytes(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .and(body_partial_json(json!({ "nonce": "fixed-nonce" })))
+            .respond_with(KeyExchangeResponder {
+                server_secret_key,
+                session_key: [50u8; 32],
+                session_id: session_id.clone(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert!(client.get_attestation_document().unwrap().is_none());
+        client.perform_attestation_handshake().await.unwrap();
+        assert_eq!(
+            client.get_session_id().unwrap(),
+            Some(Uuid::parse_str(&session_id).unwrap())
+        );
+
+        let doc = client.get_attestation_document().unwrap().unwrap();
+        assert_eq!(doc.nonce.as_deref(), Some(b"fixed-nonce".as_slice()));
+        assert_eq!(
+            doc.public_key.as_deref(),
+            Some(server_public_key.as_bytes().as_slice())
+        );
+    }
+
+    struct EmbeddingResponder {
+        session_key: [u8; 32],
+    }
+
+    impl Respond for EmbeddingResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let body: serde_json::Value = decrypt_request_body(request, &self.session_key);
+            let data: Vec<_> = body["input"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .enumerate()
+                .map(|(index, text)| {
+                    json!({
+                        "object": "embedding",
+                        "index": index,
+                        "embedding": [text.as_str().unwrap().len() as f64],
+                    })
+                })
+                .collect();
+
+            ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &self.session_key,
+                &json!({
+                    "object": "list",
+                    "data": data,
+                    "model": body["model"],
+                    "usage": { "prompt_tokens": 1, "total_tokens": 1 },
+                }),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_embeddings_resumable_skips_completed_and_honors_cancel() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [51u8; 32];
+
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(EmbeddingResponder { session_key })
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let texts: Vec<String> = ["a", "bb", "ccc", "dddd", "eeeee"]
+            .iter()
+            .map(|text| text.to_string())
+            .collect();
+        let completed = HashSet::from([1]);
+
+        let mut results: Vec<(usize, Vec<f32>)> = client
+            .create_embeddings_resumable(
+                texts.clone(),
+                "nomic-embed-text",
+                2,
+                &completed,
+                CancellationToken::new(),
+            )
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+
+        assert_eq!(
+            results,
+            vec![
+                (0, vec![1.0]),
+                (2, vec![3.0]),
+                (3, vec![4.0]),
+                (4, vec![5.0]),
+            ]
+        );
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let results: Vec<_> = client
+            .create_embeddings_resumable(texts, "nomic-embed-text", 2, &HashSet::new(), cancel)
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(Error::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_accounts_share_session_but_not_tokens() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_id = Uuid::new_v4();
+        let session_key = [52u8; 32];
+
+        client
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+
+        for (token, email) in [
+            ("alice_token", "alice@test.dev"),
+            ("bob_token", "bob@test.dev"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path("/protected/user"))
+                .and(header("authorization", format!("Bearer {}", token)))
+                .and(header("x-session-id", session_id.to_string()))
+                .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                    &session_key,
+                    &json!({
+                        "user": {
+                            "id": Uuid::new_v4(),
+                            "name": null,
+                            "email": email,
+                            "email_verified": true,
+                            "login_method": "email",
+                            "created_at": "2024-01-01T00:00:00Z",
+                            "updated_at": "2024-01-01T00:00:00Z"
+                        }
+                    }),
+                )))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let alice = client.account("alice").unwrap();
+        alice
+            .session_manager
+            .set_tokens("alice_token".to_string(), None)
+            .unwrap();
+        client
+            .account("bob")
+            .unwrap()
+            .session_manager
+            .set_tokens("bob_token".to_string(), None)
+            .unwrap();
+
+        let bob = client.account("bob").unwrap();
+        assert_eq!(
+            alice.get_user().await.unwrap().user.email.as_deref(),
+            Some("alice@test.dev")
+        );
+        assert_eq!(
+            bob.get_user().await.unwrap().user.email.as_deref(),
+            Some("bob@test.dev")
+        );
+        assert!(client.get_access_token().unwrap().is_none());
+
+        let mut ids = client.account_ids().unwrap();
+        ids.sort();
+        assert_eq!(ids, ["alice", "bob"]);
+        assert!(client.remove_account("alice").unwrap());
+        assert!(client
+            .account("alice")
+            .unwrap()
+            .get_access_token()
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_no_content_responses_are_success_for_deletes() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [53u8; 32];
+
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens(
+                "access_token".to_string(),
+                Some("refresh_token".to_string()),
+            )
+            .unwrap();
+
+        Mock::given(method("DELETE"))
+            .and(path("/protected/kv/empty"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/protected/kv/json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({ "message": "deleted" }),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/empty"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/logout"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        client.kv_delete("empty").await.unwrap();
+        client.kv_delete("json").await.unwrap();
+
+        let error = client.kv_get("empty").await.unwrap_err();
+        assert!(matches!(error, Error::InvalidResponse(_)));
+
+        client.logout().await.unwrap();
+        assert!(client.get_access_token().unwrap().is_none());
+    }
+
+    fn test_jwt(exp: i64) -> String {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+            URL_SAFE_NO_PAD.encode(json!({ "sub": "user", "exp": exp }).to_string())
+        )
+    }
+
+    #[tokio::test]
+    async fn test_refresh_if_needed_only_refreshes_near_expiry() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [54u8; 32];
+        let now = chrono::Utc::now().timestamp();
+        let fresh = test_jwt(now + 3600);
+
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/refresh"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({ "access_token": fresh, "refresh_token": "refresh_token" }),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let threshold = std::time::Duration::from_secs(300);
+
+        client
+            .session_manager
+            .set_tokens(fresh.clone(), Some("refresh_token".to_string()))
+            .unwrap();
+        assert!(!client.refresh_if_needed(threshold).await.unwrap());
+
+        client
+            .session_manager
+            .set_tokens("not-a-jwt".to_string(), Some("refresh_token".to_string()))
+            .unwrap();
+        assert!(!client.refresh_if_needed(threshold).await.unwrap());
+
+        client
+            .session_manager
+            .set_tokens(test_jwt(now + 60), Some("refresh_token".to_string()))
+            .unwrap();
+        assert!(client.refresh_if_needed(threshold).await.unwrap());
+        assert_eq!(client.get_access_token().unwrap(), Some(fresh));
+        assert!(!client.refresh_if_needed(threshold).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_all_aborts_in_flight_calls_promptly() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [55u8; 32];
+
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/slow"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(&session_key, &"value"))
+                    .set_delay(std::time::Duration::from_secs(30)),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/fast"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(&session_key, &"value")),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let started = std::time::Instant::now();
+        let (result, _) = tokio::join!(client.kv_get("slow"), async {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            client.cancel_all().unwrap();
+        });
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+        assert_eq!(client.kv_get("fast").await.unwrap(), "value");
+    }
+
+    #[test]
+    fn test_is_local_host_checks_the_host_not_the_whole_url() {
+        assert!(is_local_host("http://localhost:3000"));
+        assert!(is_local_host("http://127.0.0.1:3000"));
+        assert!(is_local_host("http://[::1]:3000"));
+        assert!(is_local_host("http://10.0.2.2:3000"));
+        assert!(!is_local_host("https://localhost.example.com"));
+        assert!(!is_local_host("https://api.example.com/localhost"));
+    }
+
+    #[tokio::test]
+    async fn test_require_real_attestation_rejects_mock_for_non_local_host() {
+        let client = OpenSecretClient::new("https://enclave.example.com/localhost")
+            .unwrap()
+            .with_mock_attestation(true)
+            .require_real_attestation();
+        assert!(client.is_attestation_mocked());
+
+        let document = build_mock_attestation_document("nonce", &[1u8; 32]);
+        let error = client.verify_attestation(&document, "nonce").unwrap_err();
+        assert!(matches!(error, Error::AttestationVerificationFailed(_)));
+
+        let local = OpenSecretClient::new("http://localhost:3000")
+            .unwrap()
+            .require_real_attestation();
+        assert!(local.verify_attestation(&document, "nonce").is_ok());
+
+        let remote = OpenSecretClient::new("https://enclave.example.com").unwrap();
+        assert!(!remote.is_attestation_mocked());
+    }
+
+    #[test]
+    fn test_with_expected_pcrs_hex_decodes_values() {
+        let client = OpenSecretClient::new("https://enclave.example.com")
+            .unwrap()
+            .with_expected_pcrs_hex(HashMap::from([(0, "abcd".to_string())]))
+            .unwrap();
+        assert_eq!(
+            client.expected_pcrs,
+            Some(HashMap::from([(0, vec![0xab, 0xcd])]))
+        );
+
+        let error = OpenSecretClient::new("https://enclave.example.com")
+            .unwrap()
+            .with_expected_pcrs_hex(HashMap::from([(1, "not hex".to_string())]))
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::Configuration(_)));
+    }
+
+    #[tokio::test]
+    async fn test_health_monitor_attests_and_reports_state() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_nonce_source(crate::attestation::FixedNonce("monitor-nonce".to_string()));
+        assert!(client
+            .start_health_monitor(std::time::Duration::ZERO)
+            .is_err());
+
+        let monitor = client
+            .start_health_monitor(std::time::Duration::from_millis(20))
+            .unwrap();
+        let mut states = monitor.subscribe();
+        states.changed().await.unwrap();
+        assert!(matches!(
+            monitor.state(),
+            ConnectionState::Unreachable(message) if message.contains("404")
+        ));
+
+        let server_secret_key = [51u8; 32];
+        let server_public_key =
+            x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(server_secret_key));
+        Mock::given(method("GET"))
+            .and(path("/health-check"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("OK"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/attestation/monitor-nonce"))
+            .respond_with(AttestationResponder {
+                server_public_key: server_public_key.to_bytes(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .respond_with(KeyExchangeResponder {
+                server_secret_key,
+                session_key: [52u8; 32],
+                session_id: Uuid::new_v4().to_string(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        states.changed().await.unwrap();
+        assert_eq!(monitor.state(), ConnectionState::Healthy);
+        assert!(client.get_session_id().unwrap().is_some());
+
+        assert!(monitor.is_running());
+        monitor.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_imported_session_skips_handshake() {
+        let mock_server = MockServer::start().await;
+        let session_id = Uuid::new_v4();
+        let session_key = [53u8; 32];
+
+        let original = OpenSecretClient::new(mock_server.uri()).unwrap();
+        assert!(original.export_session().is_err());
+        original
+            .session_manager
+            .set_session(session_id, session_key)
+            .unwrap();
+        original
+            .session_manager
+            .set_tokens(
+                "saved-access".to_string(),
+                Some("saved-refresh".to_string()),
+            )
+            .unwrap();
+
+        let exported = original.export_session().unwrap();
+        let debug = format!("{:?}", exported);
+        assert!(!debug.contains("saved-access"));
+        assert!(!debug.contains("53, 53"));
+        let stored = serde_json::to_string(&exported).unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/protected/user"))
+            .and(header("authorization", "Bearer saved-access"))
+            .and(header("x-session-id", session_id.to_string()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({
+                    "user": {
+                        "id": Uuid::new_v4(),
+                        "name": null,
+                        "email": "sdk@test.dev",
+                        "email_verified": true,
+                        "login_method": "email",
+                        "created_at": "2024-01-01T00:00:00Z",
+                        "updated_at": "2024-01-01T00:00:00Z"
+                    }
+                }),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let restored = OpenSecretClient::new(mock_server.uri()).unwrap();
+        restored
+            .import_session(serde_json::from_str(&stored).unwrap())
+            .unwrap();
+        assert_eq!(restored.get_session_id().unwrap(), Some(session_id));
+        assert_eq!(
+            restored.get_refresh_token().unwrap().as_deref(),
+            Some("saved-refresh")
+        );
+        let user = restored.get_user().await.unwrap();
+        assert_eq!(user.user.email.as_deref(), Some("sdk@test.dev"));
+    }
+
+    #[tokio::test]
+    async fn test_auto_refresh_disabled_returns_401() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_auto_refresh(false);
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), [54u8; 32])
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("expired".to_string(), Some("refresh".to_string()))
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/protected/user"))
+            .respond_with(
+                ResponseTemplate::new(401).set_body_json(json!({ "message": "jwt expired" })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/refresh"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let error = client.get_user().await.unwrap_err();
+        assert!(matches!(error, Error::Api { status: 401, .. }));
+    }
+
+    #[test]
+    fn test_access_token_expiry_reads_exp_claim() {
+        let client = OpenSecretClient::new("https://enclave.example.com").unwrap();
+        assert_eq!(client.access_token_expiry().unwrap(), None);
+
+        client
+            .session_manager
+            .set_tokens(test_jwt(1_700_000_000), None)
+            .unwrap();
+        assert_eq!(
+            client.access_token_expiry().unwrap(),
+            chrono::DateTime::from_timestamp(1_700_000_000, 0)
+        );
+
+        client
+            .session_manager
+            .set_tokens("not.a-jwt".to_string(), None)
+            .unwrap();
+        assert_eq!(client.access_token_expiry().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_reports_timeout_error() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/health-check"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("OK")
+                    .set_delay(std::time::Duration::from_millis(500)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_timeout(std::time::Duration::from_millis(50))
+            .with_stream_timeout(std::time::Duration::from_secs(60));
+        let error = client.test_connection().await.unwrap_err();
+        assert!(matches!(error, Error::Timeout(_)), "{error:?}");
+
+        let patient = OpenSecretClient::new(mock_server.uri()).unwrap();
+        assert_eq!(patient.test_connection().await.unwrap(), "OK");
+    }
+
+    #[tokio::test]
+    async fn test_with_http_client_uses_supplied_client() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/health-check"))
+            .and(header("x-custom-client", "yes"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("OK"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-custom-client", HeaderValue::from_static("yes"));
+        let http_client = Client::builder().default_headers(headers).build().unwrap();
+
+        let client = OpenSecretClient::with_http_client(mock_server.uri(), http_client).unwrap();
+        assert_eq!(client.test_connection().await.unwrap(), "OK");
+    }
+
+    #[test]
+    fn test_parse_retry_after_accepts_seconds_and_dates() {
+        assert_eq!(
+            parse_retry_after(" 7 "),
+            Some(std::time::Duration::from_secs(7))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(std::time::Duration::ZERO)
+        );
+        let future = (chrono::Utc::now() + chrono::TimeDelta::seconds(120)).to_rfc2822();
+        let delay = parse_retry_after(&future).unwrap();
+        assert!(delay > std::time::Duration::from_secs(100));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_with_retry_retries_transient_failures_of_safe_requests() {
+        let mock_server = MockServer::start().await;
+        let session_key = [55u8; 32];
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_retry(RetryConfig {
+                max_retries: 2,
+                base_delay: std::time::Duration::from_millis(1),
+                max_delay: std::time::Duration::from_millis(50),
+            });
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/v1/models"))
+            .respond_with(ResponseTemplate::new(503).insert_header("retry-after", "0"))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({ "object": "list", "data": [] }),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        client.get_models().await.unwrap();
+
+        Mock::given(method("PUT"))
+            .and(path("/protected/kv/key"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let error = client.kv_put("key", "value".to_string()).await.unwrap_err();
+        assert!(matches!(error, Error::Api { status: 503, .. }));
+
+        Mock::given(method("GET"))
+            .and(path("/protected/kv"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let error = client.kv_list().await.unwrap_err();
+        assert!(matches!(error, Error::Api { status: 404, .. }));
+
+        // Billed POSTs are retried on a 502/503 but not on a 500, which the
+        // server may have sent after processing the request.
+        let embeddings = || EmbeddingRequest {
+            input: EmbeddingInput::Single("hello".to_string()),
+            model: "nomic-embed-text".to_string(),
+            encoding_format: None,
+            dimensions: None,
+            user: None,
+        };
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let error = client.create_embeddings(embeddings()).await.unwrap_err();
+        assert!(matches!(error, Error::Api { status: 500, .. }));
+
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+        let error = client.create_embeddings(embeddings()).await.unwrap_err();
+        assert!(matches!(error, Error::Api { status: 502, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_surfaces_retry_after() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), [56u8; 32])
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/v1/models"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("retry-after", "30")
+                    .set_body_string("slow down"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let error = client.get_models().await.unwrap_err();
+        match error {
+            Error::RateLimited {
+                retry_after,
+                message,
+            } => {
+                assert_eq!(retry_after, Some(std::time::Duration::from_secs(30)));
+                assert_eq!(message, "slow down");
+            }
+            other => panic!("expected RateLimited, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_builder_applies_options() {
+        assert!(matches!(
+            OpenSecretClient::builder().build(),
+            Err(Error::Configuration(_))
+        ));
+
+        let client = OpenSecretClient::builder()
+            .base_url("https://enclave.example.com/")
+            .api_key("key")
+            .timeout(std::time::Duration::from_secs(5))
+            .expected_pcrs(HashMap::from([(0, vec![1, 2])]))
+            .mock_attestation(true)
+            .auto_refresh(false)
+            .build()
+            .unwrap();
+        assert_eq!(client.base_url, "https://enclave.example.com");
+        assert_eq!(
+            client.session_manager.get_api_key().unwrap().as_deref(),
+            Some("key")
+        );
+        assert_eq!(client.timeout, Some(std::time::Duration::from_secs(5)));
+        assert_eq!(client.expected_pcrs, Some(HashMap::from([(0, vec![1, 2])])));
+        assert!(client.is_attestation_mocked());
+        assert!(!client.auto_refresh);
+    }
+
+    #[test]
+    fn test_mock_attestation_override_beats_url_heuristic() {
+        let local = OpenSecretClient::builder()
+            .base_url("http://localhost:3000")
+            .mock_attestation(false)
+            .build()
+            .unwrap();
+        assert!(!local.is_attestation_mocked());
+
+        let remote = OpenSecretClient::new("https://dev.example.com")
+            .unwrap()
+            .with_mock_attestation(true);
+        assert!(remote.is_attestation_mocked());
+
+        let heuristic = OpenSecretClient::new("http://127.0.0.1:3000").unwrap();
+        assert!(heuristic.is_attestation_mocked());
+    }
+
+    #[test]
+    fn test_localhost_outside_the_host_defaults_to_real_attestation() {
+        for url in [
+            "https://localhost.attacker.com",
+            "https://prod.example.com/localhost-proxy",
+            "https://127.0.0.1.example.com",
+            "https://enclave.example.com/10.0.2.2",
+        ] {
+            let client = OpenSecretClient::new(url).unwrap();
+            assert!(!client.is_attestation_mocked(), "{}", url);
+        }
+        assert!(OpenSecretClient::new("http://0.0.0.0:3000")
+            .unwrap()
+            .is_attestation_mocked());
+    }
+
+    #[tokio::test]
+    async fn test_create_chat_completion_aggregates_stream() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [57u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let chunk = |choices: serde_json::Value, usage: serde_json::Value| {
+            encrypted_sse_data(
+                &session_key,
+                &json!({
+                    "id": "chatcmpl-agg",
+                    "object": "chat.completion.chunk",
+                    "created": 7,
+                    "model": "llama-3.3-70b",
+                    "choices": choices,
+                    "usage": usage
+                }),
+            )
+        };
+        let sse_body = format!(
+            "{}{}{}{}data: [DONE]\n\n",
+            chunk(
+                json!([{ "index": 0, "delta": { "role": "assistant", "content": "Hel" }, "finish_reason": null }]),
+                json!(null)
+            ),
+            chunk(
+                json!([{ "index": 0, "delta": { "content": "lo" }, "finish_reason": null }]),
+                json!(null)
+            ),
+            chunk(
+                json!([{ "index": 0, "delta": {}, "finish_reason": "stop" }]),
+                json!(null)
+            ),
+            chunk(
+                json!([]),
+                json!({ "prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5 })
+            )
+        );
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = client
+            .create_chat_completion(ChatCompletionRequest::new(
+                "llama-3.3-70b",
+                vec![ChatMessage::user("hi")],
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.id, "chatcmpl-agg");
+        assert_eq!(response.created, 7);
+        assert_eq!(response.choices.len(), 1);
+        assert_eq!(response.choices[0].message.role, "assistant");
+        assert_eq!(response.choices[0].message.content, json!("Hello"));
+        assert_eq!(response.choices[0].finish_reason.as_deref(), Some("stop"));
+        assert!(response.choices[0].message.tool_calls.is_none());
+        assert_eq!(response.usage.unwrap().total_tokens, 5);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: ChatCompletionRequest = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(body.stream, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_collected_completion_merges_interleaved_tool_calls() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [58u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let chunk = |delta: serde_json::Value, finish_reason: serde_json::Value| {
+            encrypted_sse_data(
+                &session_key,
+                &json!({
+                    "id": "chatcmpl-tools",
+                    "object": "chat.completion.chunk",
+                    "created": 1,
+                    "model": "llama-3.3-70b",
+                    "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+                }),
+            )
+        };
+        let call = |index: i64, id: Option<&str>, name: Option<&str>, arguments: &str| {
+            let mut function = json!({ "arguments": arguments });
+            if let Some(name) = name {
+                function["name"] = json!(name);
+            }
+            let mut call = json!({ "index": index, "function": function });
+            if let Some(id) = id {
+                call["id"] = json!(id);
+                call["type"] = json!("function");
+            }
+            json!({ "tool_calls": [call] })
+        };
+        let sse_body = [
+            chunk(
+                json!({ "role": "assistant", "content": "Checking" }),
+                json!(null),
+            ),
+            chunk(
+                call(0, Some("call_a"), Some("weather"), "{\"city\":"),
+                json!(null),
+            ),
+            chunk(
+                call(1, Some("call_b"), Some("time"), "{\"tz\":"),
+                json!(null),
+            ),
+            chunk(call(0, None, None, "\"Paris\"}"), json!(null)),
+            chunk(call(1, None, None, "\"CET\"}"), json!(null)),
+            chunk(json!({}), json!("tool_calls")),
+            encrypted_sse_data(
+                &session_key,
+                &json!({
+                    "choices": [],
+                    "usage": { "prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21 }
+                }),
+            ),
+        ]
+        .concat()
+            + "data: [DONE]\n\n";
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = client
+            .create_chat_completion_collected(ChatCompletionRequest::new(
+                "llama-3.3-70b",
+                vec![ChatMessage::user("weather and time in Paris?")],
+            ))
+            .await
+            .unwrap();
+
+        let choice = &response.choices[0];
+        assert_eq!(choice.message.content, json!("Checking"));
+        assert_eq!(choice.finish_reason.as_deref(), Some("tool_calls"));
+        let calls = choice.message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_a");
+        assert_eq!(calls[0].function.name, "weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+        assert_eq!(calls[1].id, "call_b");
+        assert_eq!(calls[1].function.name, "time");
+        assert_eq!(calls[1].function.arguments, r#"{"tz":"CET"}"#);
+        assert_eq!(response.usage.unwrap().total_tokens, 21);
+    }
+
+    #[tokio::test]
+    async fn test_create_speech_returns_decrypted_audio() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [54u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        // Not valid UTF-8 or JSON, as real audio would not be.
+        let audio = vec![0xff, 0xfb, 0x90, 0x44, 0x00, 0x00, 0x0f, 0xe0];
+        let encrypted = crypto::encrypt_data(&session_key, &audio).unwrap();
+        Mock::given(method("POST"))
+            .and(path("/v1/audio/speech"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "encrypted": BASE64.encode(encrypted) })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut request = SpeechRequest::new("kokoro", "Hello there", "af_sky");
+        request.response_format = Some("mp3".to_string());
+        let bytes = client.create_speech(request).await.unwrap();
+        assert_eq!(bytes, audio);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(
+            body,
+            json!({
+                "model": "kokoro",
+                "input": "Hello there",
+                "voice": "af_sky",
+                "response_format": "mp3"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_handle_cancel_ends_stream() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [55u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let chunk = |content: &str| {
+            encrypted_sse_data(
+                &session_key,
+                &json!({"model": "m", "choices": [{"index": 0, "delta": {"content": content}}]}),
+            )
+        };
+        let sse_body = format!("{}{}data: [DONE]\n\n", chunk("one"), chunk("two"));
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let request = ChatCompletionRequest::new("m", vec![ChatMessage::user("hi")]);
+        let mut stream = client
+            .create_chat_completion_stream_handle(request.clone())
+            .await
+            .unwrap();
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first.delta_content(), Some("one"));
+
+        let stop = stream.cancellation_token();
+        stop.cancel();
+        assert!(stream.is_cancelled());
+        assert!(stream.next().await.is_none());
+        assert!(stream.next().await.is_none());
+
+        // Left alone, the same stream runs to completion.
+        let stream = client
+            .create_chat_completion_stream_handle(request)
+            .await
+            .unwrap();
+        let chunks: Vec<_> = stream.collect().await;
+        assert_eq!(chunks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_create_embeddings_batched_preserves_order_and_sums_usage() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [56u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(EmbeddingResponder { session_key })
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let inputs: Vec<String> = ["a", "bb", "ccc", "dddd", "eeeee"]
+            .iter()
+            .map(|text| text.to_string())
+            .collect();
+        let response = client
+            .create_embeddings_batched(inputs, "nomic-embed-text", 2)
+            .await
+            .unwrap();
+
+        let indices: Vec<i32> = response.data.iter().map(|data| data.index).collect();
+        assert_eq!(indices, [0, 1, 2, 3, 4]);
+        let vectors: Vec<f64> = response.data.iter().map(|data| data.embedding[0]).collect();
+        assert_eq!(vectors, [1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(response.model, "nomic-embed-text");
+        assert_eq!(response.usage.prompt_tokens, 3);
+        assert_eq!(response.usage.total_tokens, 3);
+
+        assert!(matches!(
+            client
+                .create_embeddings_batched(vec![], "nomic-embed-text", 0)
+                .await,
+            Err(Error::Configuration(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_model_encodes_id_and_reports_missing() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [57u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/v1/models/org%2Fllama%2D3%2E3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({"id": "org/llama-3.3", "object": "model", "owned_by": "org"}),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/models/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("model not found"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let model = client.get_model("org/llama-3.3").await.unwrap();
+        assert_eq!(model.id, "org/llama-3.3");
+        assert_eq!(model.owned_by.as_deref(), Some("org"));
+
+        assert!(matches!(
+            client.get_model("missing").await,
+            Err(Error::Api { status: 404, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_kv_json_helpers() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Settings {
+            theme: String,
+            font_size: u32,
+        }
+
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [58u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let stored = r#"{"theme":"dark","font_size":14}"#;
+        Mock::given(method("PUT"))
+            .and(path("/protected/kv/settings"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(encrypted_response(&session_key, &stored)),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/settings"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(encrypted_response(&session_key, &stored)),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("Not found"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let settings = Settings {
+            theme: "dark".to_string(),
+            font_size: 14,
+        };
+        client.kv_put_json("settings", &settings).await.unwrap();
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: String = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(body, stored);
+
+        let loaded: Option<Settings> = client.kv_get_json("settings").await.unwrap();
+        assert_eq!(loaded, Some(settings));
+        let missing: Option<Settings> = client.kv_get_json("missing").await.unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oauth_flow_stores_tokens() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [59u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/auth/google"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({"auth_url": "https://accounts.google.com/o/oauth2/auth?x=1", "csrf_token": "st4te"}),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/auth/google/callback"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({
+                    "id": Uuid::new_v4(),
+                    "email": "oauth@test.dev",
+                    "access_token": "oauth-access",
+                    "refresh_token": "oauth-refresh"
+                }),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let init = client
+            .initiate_oauth(OAuthProvider::Google, Uuid::new_v4(), None)
+            .await
+            .unwrap();
+        assert_eq!(init.state, "st4te");
+
+        let login = client
+            .complete_oauth(
+                OAuthProvider::Google,
+                "auth-code".to_string(),
+                init.state,
+                String::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(login.email.as_deref(), Some("oauth@test.dev"));
+        assert_eq!(
+            client.get_access_token().unwrap().as_deref(),
+            Some("oauth-access")
+        );
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let callback: serde_json::Value = decrypt_request_body(&requests[1], &session_key);
+        assert_eq!(callback["code"], "auth-code");
+        assert_eq!(callback["state"], "st4te");
+    }
+
+    #[tokio::test]
+    async fn test_change_email() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [60u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/protected/change_email"))
+            .and(header("authorization", "Bearer access_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({"message": "Verification email sent"}),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = client
+            .change_email("new@test.dev".to_string(), "credential".to_string())
+            .await
+            .unwrap();
+        assert!(response.verification_required);
+        assert_eq!(response.message, "Verification email sent");
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(
+            body,
+            json!({"new_email": "new@test.dev", "password": "credential"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sign_messages_keeps_order_and_per_message_errors() {
+        struct SignResponder {
+            session_key: [u8; 32],
+        }
+
+        impl Respond for SignResponder {
+            fn respond(&self, request: &Request) -> ResponseTemplate {
+                let body: SignMessageRequest = decrypt_request_body(request, &self.session_key);
+                let message = BASE64.decode(&body.message_base64).unwrap();
+                if message == b"bad" {
+                    return ResponseTemplate::new(422).set_body_string("cannot sign");
+                }
+                ResponseTemplate::new(200).set_body_json(encrypted_response(
+                    &self.session_key,
+                    &json!({
+                        "signature": body.message_base64,
+                        "message_hash": hex::encode(&message),
+                    }),
+                ))
+            }
+        }
+
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [61u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/protected/sign_message"))
+            .respond_with(SignResponder { session_key })
+            .expect(4)
+            .mount(&mock_server)
+            .await;
+
+        let messages: Vec<Vec<u8>> = vec![
+            b"one".to_vec(),
+            b"bad".to_vec(),
+            b"three".to_vec(),
+            b"four".to_vec(),
+        ];
+        let results = client
+            .sign_messages(&messages, SigningAlgorithm::Schnorr, None)
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[1], Err(Error::Api { status: 422, .. })));
+        for index in [0, 2, 3] {
+            let response = results[index].as_ref().unwrap();
+            assert_eq!(response.message_hash, hex::encode(&messages[index]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_digest_flags_request_and_checks_length() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [62u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let digest = [0xabu8; 32];
+        Mock::given(method("POST"))
+            .and(path("/protected/sign_message"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({"signature": "c2ln", "message_hash": hex::encode(digest)}),
+            )))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let response = client
+            .sign_digest(&digest, SigningAlgorithm::Ecdsa, None)
+            .await
+            .unwrap();
+        assert_eq!(response.message_hash, hex::encode(digest));
+        client
+            .sign_message(b"plain", SigningAlgorithm::Ecdsa, None)
+            .await
+            .unwrap();
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(body["hash_already_computed"], json!(true));
+        assert_eq!(body["message_base64"], json!(BASE64.encode(digest)));
+        let body: serde_json::Value = decrypt_request_body(&requests[1], &session_key);
+        assert!(body.get("hash_already_computed").is_none());
+
+        assert!(matches!(
+            client
+                .sign_digest(&[0u8; 31], SigningAlgorithm::Ecdsa, None)
+                .await,
+            Err(Error::Configuration(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_calls_are_traced_without_secrets() {
+        #[derive(Clone, Default)]
+        struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [64u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("secret-access-token".to_string(), None)
+            .unwrap();
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/traced"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(&session_key, &"secret-value")),
+            )
+            .mount(&mock_server)
+            .await;
+
+        assert_eq!(client.kv_get("traced").await.unwrap(), "secret-value");
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("Encrypted request completed"), "{}", output);
+        assert!(
+            output.contains("endpoint=/protected/kv/traced"),
+            "{}",
+            output
+        );
+        assert!(output.contains("status=200"), "{}", output);
+        assert!(!output.contains("secret-access-token"));
+        assert!(!output.contains("secret-value"));
+    }
+
+    #[tokio::test]
+    async fn test_health_check_requires_json() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/health-check"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "healthy",
+                "timestamp": "2024-01-01T00:00:00Z",
+                "version": "3.2.0"
+            })))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/health-check"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("OK"))
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let health = client.health_check().await.unwrap();
+        assert!(health.is_healthy());
+        assert_eq!(health.version.as_deref(), Some("3.2.0"));
+
+        let error = client.health_check().await.unwrap_err();
+        assert!(matches!(error, Error::InvalidResponse(_)), "{error:?}");
+        assert_eq!(client.test_connection().await.unwrap(), "OK");
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_ensure_session_runs_one_handshake() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let server_secret_key = [65u8; 32];
+        let server_public_key =
+            x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(server_secret_key));
+        let session_id = Uuid::new_v4();
+
+        Mock::given(method("GET"))
+            .and(PathPrefixMatcher("/attestation/"))
+            .respond_with(AttestationResponder {
+                server_public_key: server_public_key.to_bytes(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .respond_with(KeyExchangeResponder {
+                server_secret_key,
+                session_key: [66u8; 32],
+                session_id: session_id.to_string(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = futures::future::join_all((0..8).map(|_| client.ensure_session())).await;
+        assert!(results.iter().all(Result::is_ok), "{results:?}");
+        assert_eq!(client.get_session_id().unwrap(), Some(session_id));
+
+        client.ensure_session().await.unwrap();
+    }
+
+    #[test]
+    fn test_is_session_expired_response() {
+        assert!(is_session_expired_response(400, "Session not found"));
+        assert!(is_session_expired_response(
+            401,
+            r#"{"error":"invalid session id"}"#
+        ));
+        assert!(!is_session_expired_response(400, "Invalid model"));
+        assert!(!is_session_expired_response(401, "Invalid token"));
+        assert!(!is_session_expired_response(500, "session expired"));
+    }
+
+    #[tokio::test]
+    async fn test_expired_session_rehandshakes_once_or_surfaces() {
+        let mock_server = MockServer::start().await;
+        let server_secret_key = [67u8; 32];
+        let server_public_key =
+            x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(server_secret_key));
+        let session_key = [68u8; 32];
+        let session_id = Uuid::new_v4();
+
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/theme"))
+            .respond_with(ResponseTemplate::new(400).set_body_string("Session expired"))
+            .up_to_n_times(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/protected/kv/theme"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(encrypted_response(&session_key, &"dark")),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(PathPrefixMatcher("/attestation/"))
+            .respond_with(AttestationResponder {
+                server_public_key: server_public_key.to_bytes(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .respond_with(KeyExchangeResponder {
+                server_secret_key,
+                session_key,
+                session_id: session_id.to_string(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let manual = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_auto_rehandshake(false);
+        manual
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        manual
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+        let error = manual.kv_get("theme").await.unwrap_err();
+        assert!(matches!(error, Error::SessionExpired(_)), "{error:?}");
+
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+        assert_eq!(client.kv_get("theme").await.unwrap(), "dark");
+        assert_eq!(client.get_session_id().unwrap(), Some(session_id));
+    }
+
+    #[tokio::test]
+    async fn test_create_transcription_encrypts_audio_and_reports_progress() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [69u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/v1/audio/transcriptions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({ "text": "hello world", "language": "en" }),
+            )))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/audio/transcriptions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(&session_key, &"1\n00:00:00,000 --> ...")),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // Not valid UTF-8, to check the audio is carried as raw bytes.
+        let audio: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+        let transcript = client
+            .create_transcription(
+                &audio,
+                "memo.MP3",
+                "whisper-large-v3",
+                Some("en"),
+                None,
+                Some(Box::new(move |sent, total| {
+                    recorded.lock().unwrap().push((sent, total));
+                })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(transcript.text, "hello world");
+        assert_eq!(transcript.language.as_deref(), Some("en"));
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: TranscriptionRequest = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(BASE64.decode(&body.file).unwrap(), audio);
+        assert_eq!(body.content_type, "audio/mpeg");
+        assert_eq!(body.language.as_deref(), Some("en"));
+
+        let reports = reports.lock().unwrap().clone();
+        let total = requests[0].body.len() as u64;
+        assert!(reports.len() > 1, "{reports:?}");
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(reports.last(), Some(&(total, Some(total))));
+
+        let subtitles = client
+            .create_transcription(
+                &audio,
+                "memo.wav",
+                "whisper-large-v3",
+                None,
+                Some("srt"),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(subtitles.text.starts_with("1\n00:00:00"));
+    }
+
+    #[tokio::test]
+    async fn test_make_encrypted_requests_reach_unwrapped_endpoints() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [70u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+        client.set_api_key("api_key".to_string()).unwrap();
+
+        Mock::given(method("PUT"))
+            .and(path("/protected/widgets/7"))
+            .and(header("authorization", "Bearer access_token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(&session_key, &json!({ "id": 7 }))),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/widgets"))
+            .and(header("authorization", "Bearer api_key"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(encrypted_response(&session_key, &json!({ "data": [] }))),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let widget: serde_json::Value = client
+            .make_encrypted_request("PUT", "/protected/widgets/7", Some(json!({ "name": "x" })))
+            .await
+            .unwrap();
+        assert_eq!(widget["id"], 7);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let sent: serde_json::Value = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(sent, json!({ "name": "x" }));
+
+        let widgets: serde_json::Value = client
+            .make_encrypted_openai_request("GET", "/v1/widgets", None::<()>)
+            .await
+            .unwrap();
+        assert_eq!(widgets["data"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_call_bytes_round_trips_binary() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [71u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        let download = vec![0xffu8, 0x00, 0xfe, 0x80];
+        Mock::given(method("POST"))
+            .and(path("/protected/blobs"))
+            .and(header("authorization", "Bearer access_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "encrypted": BASE64.encode(crypto::encrypt_data(&session_key, &download).unwrap())
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/protected/blobs/1"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let upload = vec![0x89u8, b'P', b'N', b'G', 0x00, 0xc3];
+        let response = client
+            .encrypted_call_bytes("POST", "/protected/blobs", Some(upload.clone()))
+            .await
+            .unwrap();
+        assert_eq!(response, download);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: EncryptedRequest = serde_json::from_slice(&requests[0].body).unwrap();
+        let sent =
+            crypto::decrypt_data(&session_key, &BASE64.decode(&body.encrypted).unwrap()).unwrap();
+        assert_eq!(sent, upload);
+
+        let empty = client
+            .encrypted_call_bytes("DELETE", "/protected/blobs/1", None)
+            .await
+            .unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_request_password_reset_with_secret_sends_its_hash() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [72u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/password-reset/request"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let secret = crypto::generate_secret();
+        client
+            .request_password_reset_with_secret(
+                "user@example.com".to_string(),
+                &secret,
+                Uuid::new_v4(),
+            )
+            .await
+            .unwrap();
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = decrypt_request_body(&requests[0], &session_key);
+        assert_eq!(body["hashed_secret"], crypto::hash_secret(&secret));
+        assert_ne!(body["hashed_secret"], secret);
+    }
+
+    #[tokio::test]
+    async fn test_attestation_cache_still_fetches_a_document_per_handshake() {
+        let mock_server = MockServer::start().await;
+        let server_secret_key = [73u8; 32];
+        let server_public_key =
+            x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(server_secret_key));
+
+        Mock::given(method("GET"))
+            .and(PathPrefixMatcher("/attestation/"))
+            .respond_with(AttestationResponder {
+                server_public_key: server_public_key.to_bytes(),
+            })
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .respond_with(KeyExchangeResponder {
+                server_secret_key,
+                session_key: [74u8; 32],
+                session_id: Uuid::new_v4().to_string(),
+            })
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let cache = AttestationCache::new(std::time::Duration::from_secs(60));
+        for nonce in ["cache-nonce-1", "cache-nonce-2"] {
+            let client = OpenSecretClient::new(mock_server.uri())
+                .unwrap()
+                .with_nonce_source(crate::attestation::FixedNonce(nonce.to_string()))
+                .with_attestation_cache(cache.clone());
+            let outcome = client.perform_handshake_detailed().await.unwrap();
+            // Mock attestation has no certificate chain to skip.
+            assert!(!outcome.cached_attestation);
+            let document = client.get_attestation_document().unwrap().unwrap();
+            assert_eq!(document.nonce.as_deref(), Some(nonce.as_bytes()));
+        }
+
+        let again = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_attestation_cache(cache.clone());
+        again.perform_attestation_handshake().await.unwrap();
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let attestation_paths: Vec<_> = requests
+            .iter()
+            .filter(|request| request.url.path().starts_with("/attestation/"))
+            .map(|request| request.url.path().to_string())
+            .collect();
+        assert_eq!(attestation_paths[0], "/attestation/cache-nonce-1");
+        assert_eq!(attestation_paths[1], "/attestation/cache-nonce-2");
+    }
+
+    #[tokio::test]
+    async fn test_perform_handshake_detailed_reports_outcome() {
+        let mock_server = MockServer::start().await;
+        let server_secret_key = [75u8; 32];
+        let server_public_key =
+            x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(server_secret_key));
+        let session_id = Uuid::new_v4();
+
+        Mock::given(method("GET"))
+            .and(PathPrefixMatcher("/attestation/"))
+            .respond_with(AttestationResponder {
+                server_public_key: server_public_key.to_bytes(),
+            })
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .respond_with(KeyExchangeResponder {
+                server_secret_key,
+                session_key: [76u8; 32],
+                session_id: session_id.to_string(),
+            })
+            .mount(&mock_server)
+            .await;
+
+        let cache = AttestationCache::new(std::time::Duration::from_secs(60));
+        let client = OpenSecretClient::new(mock_server.uri())
+            .unwrap()
+            .with_attestation_cache(cache);
+        let outcome = client.perform_handshake_detailed().await.unwrap();
+        assert_eq!(outcome.session_id, session_id);
+        assert!(outcome.mock_attestation);
+        assert!(!outcome.cached_attestation);
+        assert_eq!(
+            outcome.attestation_document.public_key.as_deref(),
+            Some(server_public_key.as_bytes().as_slice())
+        );
+        assert_eq!(client.get_session_id().unwrap(), Some(session_id));
+
+        let again = client.perform_handshake_detailed().await.unwrap();
+        assert!(!again.cached_attestation);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_with_nonce_uses_and_validates_the_nonce() {
+        let mock_server = MockServer::start().await;
+        let server_secret_key = [77u8; 32];
+        let server_public_key =
+            x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(server_secret_key));
+
+        Mock::given(method("GET"))
+            .and(path("/attestation/order-1234"))
+            .respond_with(AttestationResponder {
+                server_public_key: server_public_key.to_bytes(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/key_exchange"))
+            .and(body_partial_json(json!({ "nonce": "order-1234" })))
+            .respond_with(KeyExchangeResponder {
+                server_secret_key,
+                session_key: [78u8; 32],
+                session_id: Uuid::new_v4().to_string(),
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        client
+            .perform_attestation_handshake_with_nonce("order-1234")
+            .await
+            .unwrap();
+        let doc = client.get_attestation_document().unwrap().unwrap();
+        assert_eq!(doc.nonce.as_deref(), Some(b"order-1234".as_slice()));
+
+        for nonce in ["", "a/b", "has space"] {
+            assert!(matches!(
+                client.perform_attestation_handshake_with_nonce(nonce).await,
+                Err(Error::Configuration(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_digest_rejects_a_rehashed_digest() {
+        let mock_server = MockServer::start().await;
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        let session_key = [79u8; 32];
+        client
+            .session_manager
+            .set_session(Uuid::new_v4(), session_key)
+            .unwrap();
+        client
+            .session_manager
+            .set_tokens("access_token".to_string(), None)
+            .unwrap();
+
+        // A server without `hash_already_computed` support hashes again.
+        let digest = [0xcdu8; 32];
+        Mock::given(method("POST"))
+            .and(path("/protected/sign_message"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(encrypted_response(
+                &session_key,
+                &json!({
+                    "signature": "c2ln",
+                    "message_hash": hex::encode(<sha2::Sha256 as sha2::Digest>::digest(digest)),
+                }),
+            )))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let error = client
+            .sign_digest(&digest, SigningAlgorithm::Ecdsa, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidResponse(_)));
+    }
+
+    #[tokio::test]
+    async fn test_ensure_session_handshake_is_traced() {
+        #[derive(Clone, Default)]
+        struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(PathPrefixMatcher("/attestation/"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let client = OpenSecretClient::new(mock_server.uri()).unwrap();
+        assert!(client.ensure_session().await.is_err());
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("attest_and_exchange_locked"), "{}", output);
+        assert!(output.contains("WARN"), "{}", output);
+    }
 }
@@ -1,15 +1,56 @@
 use crate::error::{Error, Result};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use chacha20poly1305::{
-    aead::{Aead, KeyInit, Nonce},
+    aead::{Aead, KeyInit, Nonce, Payload},
     ChaCha20Poly1305,
 };
 use p256::elliptic_curve::rand_core::{OsRng, RngCore};
+use subtle::ConstantTimeEq;
 use x25519_dalek::{EphemeralSecret, PublicKey as X25519PublicKey, SharedSecret, StaticSecret};
 
 // Re-export for tests
 pub use x25519_dalek::PublicKey;
 
+/// u-coordinates of the small-order x25519 points (with the high bit cleared).
+/// Any of these as a peer key forces the ECDH output to a predictable value.
+const LOW_ORDER_POINTS: [[u8; 32]; 7] = [
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ],
+    [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ],
+    [
+        0xe0, 0xeb, 0x7a, 0x7c, 0x3b, 0x41, 0xb8, 0xae, 0x16, 0x56, 0xe3, 0xfa, 0xf1, 0x9f, 0xc4,
+        0x6a, 0xda, 0x09, 0x8d, 0xeb, 0x9c, 0x32, 0xb1, 0xfd, 0x86, 0x62, 0x05, 0x16, 0x5f, 0x49,
+        0xb8, 0x00,
+    ],
+    [
+        0x5f, 0x9c, 0x95, 0xbc, 0xa3, 0x50, 0x8c, 0x24, 0xb1, 0xd0, 0xb1, 0x55, 0x9c, 0x83, 0xef,
+        0x5b, 0x04, 0x44, 0x5c, 0xc4, 0x58, 0x1c, 0x8e, 0x86, 0xd8, 0x22, 0x4e, 0xdd, 0xd0, 0x9f,
+        0x11, 0x57,
+    ],
+    [
+        0xec, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    [
+        0xed, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    [
+        0xee, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+];
+
 // Public test utilities
 pub struct KeyPair {
     pub secret: StaticSecret,
@@ -52,6 +93,63 @@ pub fn generate_static_keypair() -> (StaticSecret, PublicKey) {
     (secret, public)
 }
 
+/// Compares two byte strings in time that depends only on their lengths, not on
+/// where they first differ. Use it for MACs, hashes and other values an
+/// attacker could probe byte by byte.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// A fresh secret for the password-reset and account-deletion flows: 32
+/// random bytes as 64 lowercase hex characters, the same shape the TypeScript
+/// SDK generates. Keep it until the flow's confirm step, which takes it as
+/// `plaintext_secret`.
+pub fn generate_secret() -> String {
+    hex::encode(generate_random_bytes::<32>())
+}
+
+/// The `hashed_secret` the server expects for `plaintext`: the lowercase hex
+/// SHA-256 digest of its UTF-8 bytes. The server hashes the `plaintext_secret`
+/// sent at confirmation the same way and compares.
+pub fn hash_secret(plaintext: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    hex::encode(Sha256::digest(plaintext.as_bytes()))
+}
+
+/// Parses a peer's x25519 public key, rejecting keys of the wrong length and
+/// small-order points.
+pub fn parse_public_key(bytes: &[u8]) -> Result<PublicKey> {
+    let key: [u8; 32] = bytes.try_into().map_err(|_| {
+        Error::KeyExchange(format!(
+            "Invalid public key length: expected 32 bytes, got {}",
+            bytes.len()
+        ))
+    })?;
+
+    let mut masked = key;
+    masked[31] &= 0x7f;
+    if LOW_ORDER_POINTS
+        .iter()
+        .any(|point| constant_time_eq(point, &masked))
+    {
+        return Err(Error::KeyExchange(
+            "Invalid public key: low-order x25519 point".to_string(),
+        ));
+    }
+
+    Ok(PublicKey::from(key))
+}
+
+/// Rejects an all-zero ECDH output. That only happens when the peer supplied a
+/// small-order point, and a session key wrapped with it is effectively public.
+pub fn ensure_contributory(shared_secret: &SharedSecret) -> Result<()> {
+    if !shared_secret.was_contributory() {
+        return Err(Error::KeyExchange("degenerate shared secret".to_string()));
+    }
+    Ok(())
+}
+
 pub fn perform_key_exchange(secret: EphemeralSecret, their_public: &PublicKey) -> SharedSecret {
     secret.diffie_hellman(their_public)
 }
@@ -63,8 +161,19 @@ pub fn perform_static_key_exchange(
     secret.diffie_hellman(their_public)
 }
 
-#[allow(deprecated)]
 pub fn encrypt_data(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    encrypt_data_with_aad(key, plaintext, &[])
+}
+
+pub fn decrypt_data(key: &[u8; 32], encrypted_data: &[u8]) -> Result<Vec<u8>> {
+    decrypt_data_with_aad(key, encrypted_data, &[])
+}
+
+/// Like [`encrypt_data`], but authenticates `aad` (e.g. a session id or
+/// endpoint) alongside the ciphertext, so it only decrypts with the same
+/// `aad`. `aad` itself is not included in the output.
+#[allow(deprecated)]
+pub fn encrypt_data_with_aad(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
     let cipher = ChaCha20Poly1305::new_from_slice(key)
         .map_err(|e| Error::Crypto(format!("Failed to create cipher: {}", e)))?;
 
@@ -72,7 +181,13 @@ pub fn encrypt_data(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
     let nonce = Nonce::<ChaCha20Poly1305>::from_slice(&nonce_bytes);
 
     let ciphertext = cipher
-        .encrypt(nonce, plaintext)
+        .encrypt(
+            nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
         .map_err(|e| Error::Encryption(format!("Encryption failed: {}", e)))?;
 
     // Prepend nonce to ciphertext
@@ -83,8 +198,9 @@ pub fn encrypt_data(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
     Ok(result)
 }
 
+/// Decrypts data produced by [`encrypt_data_with_aad`] with the same `aad`.
 #[allow(deprecated)]
-pub fn decrypt_data(key: &[u8; 32], encrypted_data: &[u8]) -> Result<Vec<u8>> {
+pub fn decrypt_data_with_aad(key: &[u8; 32], encrypted_data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
     if encrypted_data.len() < 12 {
         return Err(Error::Decryption("Encrypted data too short".to_string()));
     }
@@ -96,7 +212,45 @@ pub fn decrypt_data(key: &[u8; 32], encrypted_data: &[u8]) -> Result<Vec<u8>> {
         .map_err(|e| Error::Crypto(format!("Failed to create cipher: {}", e)))?;
 
     cipher
-        .decrypt(nonce, ciphertext)
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|e| Error::Decryption(format!("Decryption failed: {}", e)))
+}
+
+/// Decrypts a blob produced by the server's `/protected/encrypt` endpoint
+/// without contacting the server, so the format can be audited and stored
+/// blobs can be read offline.
+///
+/// Format of `encrypted_data` (the string returned by `encrypt_data`):
+/// base64 of a 12-byte AES-256-GCM nonce followed by the ciphertext and its
+/// 16-byte tag. The AES key is the 32-byte private key the data was encrypted
+/// with, as returned (hex encoded) by `get_private_key_bytes` with the same
+/// key options.
+pub fn verify_server_encryption(encrypted_data: &str, private_key_bytes: &[u8]) -> Result<Vec<u8>> {
+    use aes_gcm::{Aes256Gcm, Nonce as AesNonce};
+
+    if private_key_bytes.len() != 32 {
+        return Err(Error::Crypto(format!(
+            "Private key must be 32 bytes, got {}",
+            private_key_bytes.len()
+        )));
+    }
+
+    let encrypted = BASE64.decode(encrypted_data.trim())?;
+    if encrypted.len() < 12 + 16 {
+        return Err(Error::Decryption("Encrypted data too short".to_string()));
+    }
+    let (nonce_bytes, ciphertext) = encrypted.split_at(12);
+
+    let cipher = Aes256Gcm::new_from_slice(private_key_bytes)
+        .map_err(|e| Error::Crypto(format!("Failed to create cipher: {}", e)))?;
+    cipher
+        .decrypt(AesNonce::from_slice(nonce_bytes), ciphertext)
         .map_err(|e| Error::Decryption(format!("Decryption failed: {}", e)))
 }
 
@@ -129,6 +283,214 @@ pub fn decrypt_session_key(shared_secret: &SharedSecret, encrypted_data: &str) -
     Ok(session_key)
 }
 
+/// Version byte that starts an [`encrypt_data_xchacha`] ciphertext.
+#[cfg(feature = "xchacha")]
+pub const XCHACHA_VERSION: u8 = 2;
+
+/// Encrypts with XChaCha20-Poly1305 and a random 24-byte nonce, which can be
+/// drawn safely for far more messages under one key than the 12-byte nonce of
+/// [`encrypt_data`].
+///
+/// Output is [`XCHACHA_VERSION`], the nonce, then the ciphertext and tag.
+/// No server endpoint accepts this format: encrypted API calls, `kv_*`
+/// values and `/protected/encrypt` all use [`encrypt_data`]'s format. Use it
+/// for data the client encrypts and decrypts itself.
+#[cfg(feature = "xchacha")]
+#[allow(deprecated)]
+pub fn encrypt_data_xchacha(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+    let cipher = XChaCha20Poly1305::new_from_slice(key)
+        .map_err(|e| Error::Crypto(format!("Failed to create cipher: {}", e)))?;
+    let nonce_bytes = generate_random_bytes::<24>();
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce_bytes), plaintext)
+        .map_err(|e| Error::Encryption(format!("Encryption failed: {}", e)))?;
+
+    let mut result = Vec::with_capacity(1 + 24 + ciphertext.len());
+    result.push(XCHACHA_VERSION);
+    result.extend_from_slice(&nonce_bytes);
+    result.extend_from_slice(&ciphertext);
+    Ok(result)
+}
+
+/// Decrypts either an [`encrypt_data_xchacha`] ciphertext or one in the
+/// default [`encrypt_data`] format. A leading [`XCHACHA_VERSION`] byte can
+/// also begin a 12-byte nonce, so XChaCha is tried first and the default
+/// format second; the tag check rejects the wrong one.
+#[cfg(feature = "xchacha")]
+#[allow(deprecated)]
+pub fn decrypt_data_xchacha(key: &[u8; 32], encrypted_data: &[u8]) -> Result<Vec<u8>> {
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+    if let Some((&XCHACHA_VERSION, rest)) = encrypted_data.split_first() {
+        if rest.len() >= 24 {
+            let (nonce_bytes, ciphertext) = rest.split_at(24);
+            let cipher = XChaCha20Poly1305::new_from_slice(key)
+                .map_err(|e| Error::Crypto(format!("Failed to create cipher: {}", e)))?;
+            if let Ok(plaintext) = cipher.decrypt(XNonce::from_slice(nonce_bytes), ciphertext) {
+                return Ok(plaintext);
+            }
+        }
+    }
+
+    decrypt_data(key, encrypted_data)
+}
+
+/// Recommended plaintext chunk size for [`StreamEncryptor`].
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Length of the random nonce prefix written before a stream's frames.
+pub const STREAM_HEADER_LEN: usize = 7;
+
+/// Per-frame overhead: a last-frame flag, a big-endian `u32` ciphertext
+/// length and the 16-byte tag.
+pub const STREAM_FRAME_OVERHEAD: usize = 5 + 16;
+
+/// Nonce for chunk `counter`: the stream's prefix, the big-endian counter
+/// and a flag marking the final chunk. Binding position and finality into the
+/// nonce makes reordered, dropped or truncated frames fail to decrypt.
+fn stream_nonce(prefix: &[u8; STREAM_HEADER_LEN], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..STREAM_HEADER_LEN].copy_from_slice(prefix);
+    nonce[STREAM_HEADER_LEN..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = u8::from(last);
+    nonce
+}
+
+/// Encrypts a payload chunk by chunk, so it never has to be held in memory
+/// whole. Each chunk becomes a frame with its own nonce and tag.
+///
+/// Output is [`Self::header`] followed by the frames from
+/// [`Self::encrypt_chunk`] and, last, [`Self::finish`]; read it back with
+/// [`StreamDecryptor`].
+pub struct StreamEncryptor {
+    cipher: ChaCha20Poly1305,
+    prefix: [u8; STREAM_HEADER_LEN],
+    counter: u32,
+}
+
+impl StreamEncryptor {
+    pub fn new(key: &[u8; 32]) -> Result<Self> {
+        let cipher = ChaCha20Poly1305::new_from_slice(key)
+            .map_err(|e| Error::Crypto(format!("Failed to create cipher: {}", e)))?;
+        Ok(Self {
+            cipher,
+            prefix: generate_random_bytes::<STREAM_HEADER_LEN>(),
+            counter: 0,
+        })
+    }
+
+    /// Bytes to write before the first frame.
+    pub fn header(&self) -> [u8; STREAM_HEADER_LEN] {
+        self.prefix
+    }
+
+    /// Encrypts a chunk that is not the last one into a frame.
+    pub fn encrypt_chunk(&mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_frame(chunk, false)
+    }
+
+    /// Encrypts the final chunk, which may be empty, ending the stream.
+    pub fn finish(mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_frame(chunk, true)
+    }
+
+    #[allow(deprecated)]
+    fn encrypt_frame(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>> {
+        let nonce_bytes = stream_nonce(&self.prefix, self.counter, last);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::<ChaCha20Poly1305>::from_slice(&nonce_bytes), chunk)
+            .map_err(|e| Error::Encryption(format!("Encryption failed: {}", e)))?;
+        let length = u32::try_from(ciphertext.len())
+            .map_err(|_| Error::Encryption("Stream chunk too large".to_string()))?;
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| Error::Encryption("Too many stream chunks".to_string()))?;
+
+        let mut frame = Vec::with_capacity(5 + ciphertext.len());
+        frame.push(u8::from(last));
+        frame.extend_from_slice(&length.to_be_bytes());
+        frame.extend_from_slice(&ciphertext);
+        Ok(frame)
+    }
+}
+
+/// Decrypts the output of a [`StreamEncryptor`] incrementally.
+pub struct StreamDecryptor {
+    cipher: ChaCha20Poly1305,
+    prefix: [u8; STREAM_HEADER_LEN],
+    counter: u32,
+    finished: bool,
+}
+
+impl StreamDecryptor {
+    /// Starts decrypting a stream whose header is `header`.
+    pub fn new(key: &[u8; 32], header: &[u8]) -> Result<Self> {
+        let prefix = header
+            .try_into()
+            .map_err(|_| Error::Decryption("Invalid stream header length".to_string()))?;
+        let cipher = ChaCha20Poly1305::new_from_slice(key)
+            .map_err(|e| Error::Crypto(format!("Failed to create cipher: {}", e)))?;
+        Ok(Self {
+            cipher,
+            prefix,
+            counter: 0,
+            finished: false,
+        })
+    }
+
+    /// Decrypts the frame at the start of `input`, returning its plaintext
+    /// and the number of bytes consumed, or `None` if `input` does not yet
+    /// hold a whole frame.
+    #[allow(deprecated)]
+    pub fn decrypt_frame(&mut self, input: &[u8]) -> Result<Option<(Vec<u8>, usize)>> {
+        if self.finished {
+            return Err(Error::Decryption(
+                "Data after the final stream frame".to_string(),
+            ));
+        }
+        let Some((&flag, rest)) = input.split_first() else {
+            return Ok(None);
+        };
+        let Some(length) = rest.get(..4) else {
+            return Ok(None);
+        };
+        let length = u32::from_be_bytes(length.try_into().expect("4 bytes")) as usize;
+        let Some(ciphertext) = rest[4..].get(..length) else {
+            return Ok(None);
+        };
+        let last = match flag {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::Decryption("Invalid stream frame".to_string())),
+        };
+
+        let nonce_bytes = stream_nonce(&self.prefix, self.counter, last);
+        let plaintext = self
+            .cipher
+            .decrypt(
+                Nonce::<ChaCha20Poly1305>::from_slice(&nonce_bytes),
+                ciphertext,
+            )
+            .map_err(|e| Error::Decryption(format!("Decryption failed: {}", e)))?;
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| Error::Decryption("Too many stream chunks".to_string()))?;
+        self.finished = last;
+        Ok(Some((plaintext, 5 + length)))
+    }
+
+    /// Whether the final frame has been decrypted. A stream that ends before
+    /// this is truncated and must be rejected.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,6 +506,144 @@ mod tests {
         assert_eq!(plaintext.to_vec(), decrypted);
     }
 
+    #[test]
+    fn test_encrypt_decrypt_with_aad() {
+        let key = generate_random_bytes::<32>();
+        let plaintext = b"Hello, World!";
+
+        let encrypted = encrypt_data_with_aad(&key, plaintext, b"session-a").unwrap();
+        assert_eq!(
+            decrypt_data_with_aad(&key, &encrypted, b"session-a").unwrap(),
+            plaintext.to_vec()
+        );
+        assert!(matches!(
+            decrypt_data_with_aad(&key, &encrypted, b"session-b"),
+            Err(Error::Decryption(_))
+        ));
+        assert!(decrypt_data(&key, &encrypted).is_err());
+
+        // Empty AAD is what the plain functions use.
+        let plain = encrypt_data(&key, plaintext).unwrap();
+        assert_eq!(
+            decrypt_data_with_aad(&key, &plain, &[]).unwrap(),
+            plaintext.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_stream_encryption_round_trips_in_chunks() {
+        let key = generate_random_bytes::<32>();
+        let payload: Vec<u8> = (0..3 * 1024 * 1024 + 123).map(|i| i as u8).collect();
+
+        let mut encryptor = StreamEncryptor::new(&key).unwrap();
+        let mut encrypted = encryptor.header().to_vec();
+        let chunks: Vec<&[u8]> = payload.chunks(STREAM_CHUNK_SIZE).collect();
+        let (last, rest) = chunks.split_last().unwrap();
+        for chunk in rest {
+            encrypted.extend(encryptor.encrypt_chunk(chunk).unwrap());
+        }
+        encrypted.extend(encryptor.finish(last).unwrap());
+        assert_eq!(
+            encrypted.len(),
+            STREAM_HEADER_LEN + payload.len() + chunks.len() * STREAM_FRAME_OVERHEAD
+        );
+
+        // Feed the decryptor in reads that do not line up with frames.
+        let (header, body) = encrypted.split_at(STREAM_HEADER_LEN);
+        let mut decryptor = StreamDecryptor::new(&key, header).unwrap();
+        let mut decrypted = Vec::new();
+        let mut buffer = Vec::new();
+        for read in body.chunks(10_000) {
+            buffer.extend_from_slice(read);
+            while let Some((plaintext, used)) = decryptor.decrypt_frame(&buffer).unwrap() {
+                decrypted.extend(plaintext);
+                buffer.drain(..used);
+                if decryptor.is_finished() {
+                    break;
+                }
+            }
+        }
+        assert!(decryptor.is_finished());
+        assert!(buffer.is_empty());
+        assert_eq!(decrypted, payload);
+    }
+
+    #[test]
+    fn test_stream_decryption_rejects_reordered_or_truncated_frames() {
+        let key = generate_random_bytes::<32>();
+        let mut encryptor = StreamEncryptor::new(&key).unwrap();
+        let header = encryptor.header();
+        let first = encryptor.encrypt_chunk(b"first").unwrap();
+        let second = encryptor.encrypt_chunk(b"second").unwrap();
+        let last = encryptor.finish(b"").unwrap();
+
+        let mut decryptor = StreamDecryptor::new(&key, &header).unwrap();
+        assert!(matches!(
+            decryptor.decrypt_frame(&second),
+            Err(Error::Decryption(_))
+        ));
+
+        let mut decryptor = StreamDecryptor::new(&key, &header).unwrap();
+        decryptor.decrypt_frame(&first).unwrap().unwrap();
+        decryptor.decrypt_frame(&second).unwrap().unwrap();
+        assert!(!decryptor.is_finished());
+        assert!(decryptor.decrypt_frame(&last[..3]).unwrap().is_none());
+
+        // Marking a middle frame as final does not authenticate.
+        let mut forged = second.clone();
+        forged[0] = 1;
+        let mut decryptor = StreamDecryptor::new(&key, &header).unwrap();
+        decryptor.decrypt_frame(&first).unwrap().unwrap();
+        assert!(decryptor.decrypt_frame(&forged).is_err());
+    }
+
+    #[cfg(feature = "xchacha")]
+    #[test]
+    fn test_xchacha_round_trips_and_reads_default_format() {
+        let key = generate_random_bytes::<32>();
+        let plaintext = b"Hello, World!";
+
+        let encrypted = encrypt_data_xchacha(&key, plaintext).unwrap();
+        assert_eq!(encrypted[0], XCHACHA_VERSION);
+        assert_eq!(encrypted.len(), 1 + 24 + plaintext.len() + 16);
+        assert_eq!(
+            decrypt_data_xchacha(&key, &encrypted).unwrap(),
+            plaintext.to_vec()
+        );
+
+        let legacy = encrypt_data(&key, plaintext).unwrap();
+        assert_eq!(
+            decrypt_data_xchacha(&key, &legacy).unwrap(),
+            plaintext.to_vec()
+        );
+
+        let other_key = generate_random_bytes::<32>();
+        assert!(matches!(
+            decrypt_data_xchacha(&other_key, &encrypted),
+            Err(Error::Decryption(_))
+        ));
+    }
+
+    #[test]
+    fn test_hash_secret_known_answer() {
+        assert_eq!(
+            hash_secret("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        assert_eq!(hash_secret(&generate_secret()).len(), 64);
+    }
+
+    #[test]
+    fn test_generate_secret_is_lowercase_hex() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 64);
+        assert!(secret
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c)));
+        assert_ne!(secret, generate_secret());
+    }
+
     #[test]
     fn test_key_exchange() {
         // Use static secrets for testing since ephemeral secrets are consumed
@@ -155,4 +655,81 @@ mod tests {
 
         assert_eq!(alice_shared.as_bytes(), bob_shared.as_bytes());
     }
+
+    #[test]
+    fn test_parse_public_key_rejects_wrong_length() {
+        let error = parse_public_key(&[9u8; 33]).unwrap_err();
+        assert!(matches!(error, Error::KeyExchange(message) if message.contains("got 33")));
+    }
+
+    #[test]
+    fn test_parse_public_key_rejects_low_order_points() {
+        let (secret, _) = generate_static_keypair();
+
+        for point in LOW_ORDER_POINTS {
+            // Sanity check: every listed point really does collapse the ECDH output
+            let shared = perform_static_key_exchange(&secret, &PublicKey::from(point));
+            assert_eq!(shared.as_bytes(), &[0u8; 32]);
+
+            let error = parse_public_key(&point).unwrap_err();
+            assert!(matches!(error, Error::KeyExchange(message) if message.contains("low-order")));
+
+            let mut high_bit_set = point;
+            high_bit_set[31] |= 0x80;
+            assert!(parse_public_key(&high_bit_set).is_err());
+        }
+
+        let (_, valid) = generate_static_keypair();
+        assert!(parse_public_key(valid.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_ensure_contributory_rejects_low_order_shared_secret() {
+        let (secret, _) = generate_static_keypair();
+        // Order-8 point on curve25519
+        let low_order = PublicKey::from(LOW_ORDER_POINTS[2]);
+
+        let shared = perform_static_key_exchange(&secret, &low_order);
+        let error = ensure_contributory(&shared).unwrap_err();
+        assert!(
+            matches!(error, Error::KeyExchange(message) if message == "degenerate shared secret")
+        );
+
+        let (_, peer_public) = generate_static_keypair();
+        let shared = perform_static_key_exchange(&secret, &peer_public);
+        assert!(ensure_contributory(&shared).is_ok());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_verify_server_encryption_decrypts_aes_gcm_blob() {
+        use aes_gcm::{Aes256Gcm, Nonce as AesNonce};
+
+        let private_key = [7u8; 32];
+        let nonce = [9u8; 12];
+        let cipher = Aes256Gcm::new_from_slice(&private_key).unwrap();
+        let mut blob = nonce.to_vec();
+        blob.extend(
+            cipher
+                .encrypt(AesNonce::from_slice(&nonce), b"stored secret".as_ref())
+                .unwrap(),
+        );
+        let encrypted_data = BASE64.encode(&blob);
+
+        assert_eq!(
+            verify_server_encryption(&encrypted_data, &private_key).unwrap(),
+            b"stored secret"
+        );
+        assert!(verify_server_encryption(&encrypted_data, &[8u8; 32]).is_err());
+        assert!(verify_server_encryption(&encrypted_data, &[7u8; 31]).is_err());
+        assert!(verify_server_encryption("AAAA", &private_key).is_err());
+    }
 }
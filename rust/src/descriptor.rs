@@ -0,0 +1,338 @@
+//! Bitcoin output descriptors derived from an OpenSecret mnemonic.
+//!
+//! Only compiled with the `bitcoin` feature.
+
+use crate::error::{Error, Result};
+use crate::types::SigningAlgorithm;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::bip32::{DerivationPath, Xpriv, Xpub};
+use bitcoin::key::{CompressedPublicKey, XOnlyPublicKey};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, KnownHrp, NetworkKind, ScriptBuf};
+use std::str::FromStr;
+
+/// Script type of a single-key wallet descriptor, following the BIP-44/49/84/86
+/// account layouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorScriptType {
+    /// Legacy `pkh(...)`, BIP-44 (`m/44'/0'/0'`)
+    P2pkh,
+    /// Nested segwit `sh(wpkh(...))`, BIP-49 (`m/49'/0'/0'`)
+    P2shP2wpkh,
+    /// Native segwit `wpkh(...)`, BIP-84 (`m/84'/0'/0'`)
+    P2wpkh,
+    /// Taproot `tr(...)`, BIP-86 (`m/86'/0'/0'`)
+    P2tr,
+}
+
+impl DescriptorScriptType {
+    pub fn purpose(self) -> u32 {
+        match self {
+            Self::P2pkh => 44,
+            Self::P2shP2wpkh => 49,
+            Self::P2wpkh => 84,
+            Self::P2tr => 86,
+        }
+    }
+
+    fn wrap(self, key: &str) -> String {
+        match self {
+            Self::P2pkh => format!("pkh({})", key),
+            Self::P2shP2wpkh => format!("sh(wpkh({}))", key),
+            Self::P2wpkh => format!("wpkh({})", key),
+            Self::P2tr => format!("tr({})", key),
+        }
+    }
+}
+
+/// Builds the mainnet receive descriptor for account 0 of `mnemonic`, e.g.
+/// `wpkh([73c5da0a/84'/0'/0']xpub.../0/*)#checksum`.
+pub fn output_descriptor(mnemonic: &str, script_type: DescriptorScriptType) -> Result<String> {
+    let mnemonic = bip39::Mnemonic::parse_normalized(mnemonic)
+        .map_err(|e| Error::Crypto(format!("Invalid mnemonic: {}", e)))?;
+    let seed = mnemonic.to_seed_normalized("");
+
+    let secp = Secp256k1::new();
+    let master = Xpriv::new_master(NetworkKind::Main, &seed)
+        .map_err(|e| Error::Crypto(format!("Failed to derive master key: {}", e)))?;
+    let fingerprint = master.fingerprint(&secp);
+
+    let account_path = format!("{}'/0'/0'", script_type.purpose());
+    let path = DerivationPath::from_str(&format!("m/{}", account_path))
+        .map_err(|e| Error::Crypto(format!("Invalid derivation path: {}", e)))?;
+    let account = master
+        .derive_priv(&secp, &path)
+        .map_err(|e| Error::Crypto(format!("Failed to derive account key: {}", e)))?;
+    let xpub = Xpub::from_priv(&secp, &account);
+
+    let key = format!("[{}/{}]{}/0/*", fingerprint, account_path, xpub);
+    let descriptor = script_type.wrap(&key);
+    let checksum = descriptor_checksum(&descriptor)?;
+
+    Ok(format!("{}#{}", descriptor, checksum))
+}
+
+/// Upper bound on the number of paths a single derivation search may query.
+pub const MAX_DERIVATION_SEARCH_PATHS: usize = 4096;
+
+/// Standard paths tried by `OpenSecretClient::find_derivation_path`:
+/// `m/{purpose}'/{coin_type}'/{account}'/{change}/{index}` for every
+/// combination of the fields below.
+#[derive(Debug, Clone)]
+pub struct DerivationSearchSpace {
+    pub script_types: Vec<DescriptorScriptType>,
+    /// `0` for mainnet, `1` for testnet wallets.
+    pub coin_types: Vec<u32>,
+    /// Accounts `0..accounts`.
+    pub accounts: u32,
+    /// Address indices `0..address_indices` on each chain.
+    pub address_indices: u32,
+    /// Also search the internal (change) chain.
+    pub include_change: bool,
+}
+
+impl Default for DerivationSearchSpace {
+    fn default() -> Self {
+        Self {
+            script_types: vec![
+                DescriptorScriptType::P2pkh,
+                DescriptorScriptType::P2shP2wpkh,
+                DescriptorScriptType::P2wpkh,
+                DescriptorScriptType::P2tr,
+            ],
+            coin_types: vec![0, 1],
+            accounts: 3,
+            address_indices: 20,
+            include_change: true,
+        }
+    }
+}
+
+impl DerivationSearchSpace {
+    /// Every path in the space whose script type can be produced by
+    /// `algorithm` (Schnorr keys for taproot, ECDSA keys for the rest).
+    /// Fails if the space exceeds [`MAX_DERIVATION_SEARCH_PATHS`].
+    pub fn paths(
+        &self,
+        algorithm: &SigningAlgorithm,
+    ) -> Result<Vec<(String, DescriptorScriptType)>> {
+        let script_types: Vec<_> = self
+            .script_types
+            .iter()
+            .copied()
+            .filter(|script_type| {
+                matches!(
+                    (algorithm, script_type),
+                    (SigningAlgorithm::Schnorr, DescriptorScriptType::P2tr)
+                ) || matches!(
+                    (algorithm, script_type),
+                    (
+                        SigningAlgorithm::Ecdsa,
+                        DescriptorScriptType::P2pkh
+                            | DescriptorScriptType::P2shP2wpkh
+                            | DescriptorScriptType::P2wpkh
+                    )
+                )
+            })
+            .collect();
+        let chains: &[u32] = if self.include_change { &[0, 1] } else { &[0] };
+
+        let total = script_types.len()
+            * self.coin_types.len()
+            * self.accounts as usize
+            * chains.len()
+            * self.address_indices as usize;
+        if total > MAX_DERIVATION_SEARCH_PATHS {
+            return Err(Error::Configuration(format!(
+                "Derivation search space has {} paths, exceeding the maximum of {}",
+                total, MAX_DERIVATION_SEARCH_PATHS
+            )));
+        }
+
+        let mut paths = Vec::with_capacity(total);
+        for &script_type in &script_types {
+            for coin_type in &self.coin_types {
+                for account in 0..self.accounts {
+                    for chain in chains {
+                        for index in 0..self.address_indices {
+                            paths.push((
+                                format!(
+                                    "m/{}'/{}'/{}'/{}/{}",
+                                    script_type.purpose(),
+                                    coin_type,
+                                    account,
+                                    chain,
+                                    index
+                                ),
+                                script_type,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(paths)
+    }
+}
+
+/// Output script of `address`, on any network.
+pub fn address_script_pubkey(address: &str) -> Result<ScriptBuf> {
+    let address = Address::<NetworkUnchecked>::from_str(address)
+        .map_err(|e| Error::Configuration(format!("Invalid address: {}", e)))?;
+    Ok(address.assume_checked().script_pubkey())
+}
+
+/// Output script paying to the hex `public_key` with `script_type`. Taproot
+/// outputs use the BIP-86 tweak of the key (no script path).
+pub fn public_key_script_pubkey(
+    public_key: &str,
+    script_type: DescriptorScriptType,
+) -> Result<ScriptBuf> {
+    let bytes = hex::decode(public_key)
+        .map_err(|e| Error::Crypto(format!("Invalid public key hex: {}", e)))?;
+
+    let address = if script_type == DescriptorScriptType::P2tr {
+        let x_only = if bytes.len() == 33 {
+            &bytes[1..]
+        } else {
+            &bytes[..]
+        };
+        let key = XOnlyPublicKey::from_slice(x_only)
+            .map_err(|e| Error::Crypto(format!("Invalid x-only public key: {}", e)))?;
+        Address::p2tr(
+            &Secp256k1::verification_only(),
+            key,
+            None,
+            KnownHrp::Mainnet,
+        )
+    } else {
+        let key = CompressedPublicKey::from_slice(&bytes)
+            .map_err(|e| Error::Crypto(format!("Invalid compressed public key: {}", e)))?;
+        match script_type {
+            DescriptorScriptType::P2pkh => Address::p2pkh(key, NetworkKind::Main),
+            DescriptorScriptType::P2shP2wpkh => Address::p2shwpkh(&key, NetworkKind::Main),
+            _ => Address::p2wpkh(&key, KnownHrp::Mainnet),
+        }
+    };
+    Ok(address.script_pubkey())
+}
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn polymod(c: u64, value: u64) -> u64 {
+    const GENERATORS: [u64; 5] = [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ];
+
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7ffffffff) << 5) ^ value;
+    for (i, generator) in GENERATORS.iter().enumerate() {
+        if (c0 >> i) & 1 == 1 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+/// BIP-380 descriptor checksum.
+pub fn descriptor_checksum(descriptor: &str) -> Result<String> {
+    let mut c = 1u64;
+    let mut class = 0u64;
+    let mut class_count = 0;
+
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET.find(ch).ok_or_else(|| {
+            Error::Configuration(format!("Invalid character {:?} in descriptor", ch))
+        })? as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    Ok((0..8)
+        .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_MNEMONIC: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn test_descriptor_checksum_matches_bip380_vector() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+    }
+
+    #[test]
+    fn test_output_descriptor_matches_bip84_and_bip86_vectors() {
+        let wpkh = output_descriptor(TEST_MNEMONIC, DescriptorScriptType::P2wpkh).unwrap();
+        assert_eq!(
+            wpkh,
+            "wpkh([73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/0/*)#wc3n3van"
+        );
+
+        let tr = output_descriptor(TEST_MNEMONIC, DescriptorScriptType::P2tr).unwrap();
+        assert!(tr.starts_with(
+            "tr([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*)#"
+        ));
+    }
+
+    #[test]
+    fn test_public_key_script_matches_bip84_address() {
+        let script = public_key_script_pubkey(
+            "0330d54fd0dd420a6e5f8d3624f5f3482cae350f79d5f0753bf5beef9c2d91af3c",
+            DescriptorScriptType::P2wpkh,
+        )
+        .unwrap();
+        assert_eq!(
+            script,
+            address_script_pubkey("bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_search_space_filters_by_algorithm_and_is_bounded() {
+        let space = DerivationSearchSpace::default();
+        let ecdsa = space.paths(&SigningAlgorithm::Ecdsa).unwrap();
+        let schnorr = space.paths(&SigningAlgorithm::Schnorr).unwrap();
+        assert_eq!(ecdsa.len(), 3 * schnorr.len());
+        assert_eq!(ecdsa[0].0, "m/44'/0'/0'/0/0");
+        assert!(schnorr
+            .iter()
+            .all(|(path, script_type)| path.starts_with("m/86'")
+                && *script_type == DescriptorScriptType::P2tr));
+
+        let huge = DerivationSearchSpace {
+            accounts: 100,
+            address_indices: 1000,
+            ..DerivationSearchSpace::default()
+        };
+        assert!(huge.paths(&SigningAlgorithm::Ecdsa).is_err());
+    }
+
+    #[test]
+    fn test_output_descriptor_rejects_invalid_mnemonic() {
+        assert!(output_descriptor("not a mnemonic", DescriptorScriptType::P2wpkh).is_err());
+    }
+}
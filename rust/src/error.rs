@@ -1,9 +1,14 @@
+use serde::Deserialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("HTTP request failed: {0}")]
-    Http(#[from] reqwest::Error),
+    Http(reqwest::Error),
+
+    /// The request exceeded the client's configured timeout.
+    #[error("Request timed out: {0}")]
+    Timeout(reqwest::Error),
 
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
@@ -20,6 +25,12 @@ pub enum Error {
     #[error("Session error: {0}")]
     Session(String),
 
+    /// The server no longer recognizes the session, e.g. after it expired or
+    /// the enclave restarted. A new attestation handshake is needed; see
+    /// [`crate::OpenSecretClient::with_auto_rehandshake`].
+    #[error("Session expired: {0}")]
+    SessionExpired(String),
+
     #[error("Key exchange failed: {0}")]
     KeyExchange(String),
 
@@ -32,12 +43,25 @@ pub enum Error {
     #[error("Authentication error: {0}")]
     Authentication(String),
 
+    #[error("Stale refresh token: {0}")]
+    StaleRefreshToken(String),
+
     #[error("Invalid response: {0}")]
     InvalidResponse(String),
 
+    /// A non-success response. `message` is the raw response body; see
+    /// [`Error::api_error`] for its structured form.
     #[error("API error: {status}: {message}")]
     Api { status: u16, message: String },
 
+    /// The server answered 429. `retry_after` comes from the `Retry-After`
+    /// header when present.
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        retry_after: Option<std::time::Duration>,
+        message: String,
+    },
+
     #[error("Configuration error: {0}")]
     Configuration(String),
 
@@ -50,8 +74,100 @@ pub enum Error {
     #[error("Base64 decode error: {0}")]
     Base64Decode(#[from] base64::DecodeError),
 
+    #[error("Operation cancelled")]
+    Cancelled,
+
     #[error("Other error: {0}")]
     Other(String),
 }
 
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout(error)
+        } else {
+            Self::Http(error)
+        }
+    }
+}
+
+impl Error {
+    /// The structured error in the body of an [`Error::Api`] or
+    /// [`Error::RateLimited`] response, when the body is a JSON error
+    /// object. `None` for other errors and for plain-text bodies, whose text
+    /// stays available as `message`.
+    pub fn api_error(&self) -> Option<ApiError> {
+        match self {
+            Error::Api { message, .. } | Error::RateLimited { message, .. } => {
+                ApiError::parse(message)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A JSON error body, either `{"error": {"code": ..., "message": ...}}` or
+/// the same fields at the top level.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ApiError {
+    /// Machine-readable code such as `invalid_request` or
+    /// `insufficient_quota`.
+    #[serde(default)]
+    pub code: Option<String>,
+    pub message: String,
+    /// The request parameter the error refers to.
+    #[serde(default)]
+    pub param: Option<String>,
+    #[serde(default, rename = "type")]
+    pub error_type: Option<String>,
+}
+
+impl ApiError {
+    /// Parses a response body, returning `None` if it is not a JSON error.
+    pub fn parse(body: &str) -> Option<Self> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Body {
+            Nested { error: ApiError },
+            Flat(ApiError),
+        }
+
+        match serde_json::from_str(body).ok()? {
+            Body::Nested { error } | Body::Flat(error) => Some(error),
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_parses_json_bodies() {
+        let error = Error::Api {
+            status: 429,
+            message: r#"{"error":{"code":"insufficient_quota","message":"Quota exceeded","param":null,"type":"billing"}}"#.to_string(),
+        };
+        let parsed = error.api_error().unwrap();
+        assert_eq!(parsed.code.as_deref(), Some("insufficient_quota"));
+        assert_eq!(parsed.message, "Quota exceeded");
+        assert_eq!(parsed.param, None);
+        assert_eq!(parsed.error_type.as_deref(), Some("billing"));
+
+        let flat =
+            ApiError::parse(r#"{"code":"invalid_request","message":"bad model","param":"model"}"#)
+                .unwrap();
+        assert_eq!(flat.code.as_deref(), Some("invalid_request"));
+        assert_eq!(flat.param.as_deref(), Some("model"));
+
+        let plain = Error::Api {
+            status: 500,
+            message: "Internal Server Error".to_string(),
+        };
+        assert!(plain.api_error().is_none());
+        assert!(ApiError::parse(r#"{"status":"error"}"#).is_none());
+        assert!(Error::Cancelled.api_error().is_none());
+    }
+}
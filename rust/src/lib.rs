@@ -1,13 +1,27 @@
 pub mod attestation;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "dev-capture")]
+pub mod capture;
 mod cbor;
 pub mod client;
 pub mod crypto;
+#[cfg(feature = "bitcoin")]
+pub mod descriptor;
 pub mod error;
+pub mod monitor;
 pub mod push;
 pub mod session;
+mod sse;
+pub mod stream;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod tls;
 pub mod types;
 
-pub use client::OpenSecretClient;
-pub use error::{Error, Result};
+pub use client::{OpenSecretClient, OpenSecretClientBuilder};
+pub use error::{ApiError, Error, Result};
+pub use monitor::{ConnectionState, HealthMonitor};
 pub use push::*;
+pub use stream::{StreamHandle, UsageHandle};
 pub use types::*;
@@ -0,0 +1,78 @@
+//! Background keep-alive for long-running clients.
+//!
+//! [`OpenSecretClient::start_health_monitor`](crate::OpenSecretClient::start_health_monitor)
+//! spawns a task that periodically checks the server, re-attests when the
+//! session is missing and refreshes tokens shortly before they expire. State
+//! changes are published on a [`tokio::sync::watch`] channel.
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Connection state observed by a [`HealthMonitor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// No check has completed yet.
+    Starting,
+    /// The server is reachable, the session is attested and tokens (if any)
+    /// are fresh.
+    Healthy,
+    /// The health-check endpoint could not be reached or returned an error.
+    Unreachable(String),
+    /// The server is reachable but re-attestation or token refresh failed.
+    Degraded(String),
+}
+
+/// Handle to a running health monitor.
+///
+/// Dropping the handle stops the task at its next await point; call
+/// [`Self::stop`] to also wait for it to exit.
+pub struct HealthMonitor {
+    state: watch::Receiver<ConnectionState>,
+    cancel: CancellationToken,
+    task: Option<JoinHandle<()>>,
+}
+
+impl HealthMonitor {
+    pub(crate) fn new(
+        state: watch::Receiver<ConnectionState>,
+        cancel: CancellationToken,
+        task: JoinHandle<()>,
+    ) -> Self {
+        Self {
+            state,
+            cancel,
+            task: Some(task),
+        }
+    }
+
+    /// The most recently observed state.
+    pub fn state(&self) -> ConnectionState {
+        self.state.borrow().clone()
+    }
+
+    /// A receiver that is notified whenever the state changes.
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    /// Whether the monitor task is still running.
+    pub fn is_running(&self) -> bool {
+        self.task.as_ref().is_some_and(|task| !task.is_finished())
+    }
+
+    /// Stops the monitor, abandoning any check in progress, and waits for the
+    /// task to exit.
+    pub async fn stop(mut self) {
+        self.cancel.cancel();
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for HealthMonitor {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
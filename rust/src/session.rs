@@ -2,11 +2,20 @@ use crate::error::{Error, Result};
 use crate::types::{SessionState, TokenPair};
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
+use zeroize::Zeroize;
 
+/// Cloning shares the underlying session, tokens and API key.
+///
+/// Secrets are wiped in place when cleared, and the session key and tokens
+/// are also wiped when the last clone is dropped.
+#[derive(Clone)]
 pub struct SessionManager {
     session: Arc<RwLock<Option<SessionState>>>,
     tokens: Arc<RwLock<Option<TokenPair>>>,
     api_key: Arc<RwLock<Option<String>>>,
+    // Last refresh token the server rotated away from. Only rotating backends set it.
+    spent_refresh_token: Arc<RwLock<Option<String>>>,
+    refresh_lock: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl SessionManager {
@@ -15,6 +24,8 @@ impl SessionManager {
             session: Arc::new(RwLock::new(None)),
             tokens: Arc::new(RwLock::new(None)),
             api_key: Arc::new(RwLock::new(None)),
+            spent_refresh_token: Arc::new(RwLock::new(None)),
+            refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
         }
     }
 
@@ -23,6 +34,8 @@ impl SessionManager {
             session: Arc::new(RwLock::new(None)),
             tokens: Arc::new(RwLock::new(None)),
             api_key: Arc::new(RwLock::new(Some(api_key))),
+            spent_refresh_token: Arc::new(RwLock::new(None)),
+            refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
         }
     }
 
@@ -48,7 +61,7 @@ impl SessionManager {
             Error::Authentication(format!("Failed to acquire API key write lock: {}", e))
         })?;
 
-        *api_key_guard = None;
+        api_key_guard.zeroize();
         Ok(())
     }
 
@@ -66,6 +79,8 @@ impl SessionManager {
         Ok(())
     }
 
+    /// A copy of the current session. The copy wipes its key when dropped,
+    /// but bytes copied out of `session_key` are not wiped.
     pub fn get_session(&self) -> Result<Option<SessionState>> {
         let session_guard = self
             .session
@@ -81,6 +96,10 @@ impl SessionManager {
             .write()
             .map_err(|e| Error::Session(format!("Failed to acquire session write lock: {}", e)))?;
 
+        // Wipe the key where it is stored; `take()` would only move it.
+        if let Some(session) = session_guard.as_mut() {
+            session.zeroize();
+        }
         *session_guard = None;
         Ok(())
     }
@@ -135,12 +154,55 @@ impl SessionManager {
         }
     }
 
+    /// Records that the server rotated away from `refresh_token`, so it must
+    /// never be sent again.
+    pub fn mark_refresh_token_spent(&self, refresh_token: String) -> Result<()> {
+        let mut spent_guard = self.spent_refresh_token.write().map_err(|e| {
+            Error::Authentication(format!("Failed to acquire tokens write lock: {}", e))
+        })?;
+
+        *spent_guard = Some(refresh_token);
+        Ok(())
+    }
+
+    pub fn is_refresh_token_spent(&self, refresh_token: &str) -> Result<bool> {
+        let spent_guard = self.spent_refresh_token.read().map_err(|e| {
+            Error::Authentication(format!("Failed to acquire tokens read lock: {}", e))
+        })?;
+
+        Ok(spent_guard.as_deref() == Some(refresh_token))
+    }
+
+    /// Whether the server has been seen rotating refresh tokens (one-time use).
+    pub fn refresh_tokens_rotate(&self) -> Result<bool> {
+        let spent_guard = self.spent_refresh_token.read().map_err(|e| {
+            Error::Authentication(format!("Failed to acquire tokens read lock: {}", e))
+        })?;
+
+        Ok(spent_guard.is_some())
+    }
+
+    /// A manager that shares this one's attested session but has its own
+    /// tokens and API key, for signing in a second account over the same
+    /// session.
+    pub fn with_shared_session(&self) -> Self {
+        Self {
+            session: self.session.clone(),
+            ..Self::new()
+        }
+    }
+
+    /// Serializes token refreshes so a refresh token is only sent once.
+    pub(crate) fn refresh_lock(&self) -> &tokio::sync::Mutex<()> {
+        &self.refresh_lock
+    }
+
     pub fn clear_tokens(&self) -> Result<()> {
         let mut tokens_guard = self.tokens.write().map_err(|e| {
             Error::Authentication(format!("Failed to acquire tokens write lock: {}", e))
         })?;
 
-        *tokens_guard = None;
+        tokens_guard.zeroize();
         Ok(())
     }
 
@@ -214,4 +276,56 @@ mod tests {
         manager.clear_tokens().unwrap();
         assert!(manager.get_tokens().unwrap().is_none());
     }
+
+    #[test]
+    fn test_shared_session_keeps_tokens_separate() {
+        let manager = SessionManager::new();
+        let account = manager.with_shared_session();
+
+        manager.set_session(Uuid::new_v4(), [1u8; 32]).unwrap();
+        manager.set_tokens("first".to_string(), None).unwrap();
+
+        assert!(account.get_session().unwrap().is_some());
+        assert!(account.get_tokens().unwrap().is_none());
+
+        account.set_tokens("second".to_string(), None).unwrap();
+        assert_eq!(
+            manager.get_access_token().unwrap(),
+            Some("first".to_string())
+        );
+    }
+
+    #[test]
+    fn test_zeroize_wipes_secrets_but_not_session_id() {
+        let session_id = Uuid::new_v4();
+        let mut session = SessionState {
+            session_id,
+            session_key: [0xa5; 32],
+        };
+        session.zeroize();
+        assert_eq!(session.session_key, [0u8; 32]);
+        assert_eq!(session.session_id, session_id);
+
+        let mut tokens = TokenPair {
+            access_token: "access".to_string(),
+            refresh_token: Some("refresh".to_string()),
+        };
+        tokens.zeroize();
+        assert!(tokens.access_token.is_empty());
+        assert!(tokens.refresh_token.is_none());
+    }
+
+    #[test]
+    fn test_clear_session_keeps_tokens() {
+        let manager = SessionManager::new();
+        manager.set_session(Uuid::new_v4(), [0xa5; 32]).unwrap();
+        manager.set_tokens("access".to_string(), None).unwrap();
+
+        manager.clear_session().unwrap();
+        assert!(manager.get_session().unwrap().is_none());
+        assert_eq!(
+            manager.get_access_token().unwrap(),
+            Some("access".to_string())
+        );
+    }
 }
@@ -0,0 +1,217 @@
+use crate::crypto;
+use crate::error::{Error, Result};
+use crate::types::StreamErrorPolicy;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use std::pin::Pin;
+use tokio_util::sync::CancellationToken;
+
+/// Upper bound on a JSON payload held while waiting for its remaining frames.
+const MAX_PENDING_JSON_BYTES: usize = 1024 * 1024;
+
+/// Accumulates decrypted bytes until they form complete UTF-8 text, so a
+/// multi-byte character split across two SSE frames is decoded once both halves
+/// have arrived instead of failing the first frame.
+#[derive(Default)]
+pub(crate) struct Utf8Buffer {
+    pending: Vec<u8>,
+}
+
+impl Utf8Buffer {
+    /// Returns `Ok(None)` while the buffered bytes end in an incomplete sequence.
+    /// Bytes that can never become valid UTF-8 are an error and are discarded.
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Result<Option<String>> {
+        self.pending.extend_from_slice(bytes);
+
+        match std::str::from_utf8(&self.pending) {
+            Ok(_) => {
+                let bytes = std::mem::take(&mut self.pending);
+                Ok(Some(String::from_utf8(bytes).expect("validated above")))
+            }
+            Err(e) if e.error_len().is_none() => Ok(None),
+            Err(e) => {
+                self.pending.clear();
+                Err(Error::Api {
+                    status: 0,
+                    message: format!("Invalid UTF-8 in decrypted data: {}", e),
+                })
+            }
+        }
+    }
+}
+
+/// Accumulates decrypted fragments until they parse as one complete JSON value,
+/// so a payload the server split over several `data:` events is yielded once.
+#[derive(Default)]
+pub(crate) struct JsonReassembler {
+    pending: String,
+}
+
+impl JsonReassembler {
+    /// Returns `None` while the buffered text is a truncated JSON document.
+    /// Malformed JSON is an error and resets the buffer.
+    pub(crate) fn push<T: DeserializeOwned>(&mut self, fragment: &str) -> Option<Result<T>> {
+        self.pending.push_str(fragment);
+
+        match serde_json::from_str::<T>(&self.pending) {
+            Ok(value) => {
+                self.pending.clear();
+                Some(Ok(value))
+            }
+            Err(e) if e.is_eof() && self.pending.len() <= MAX_PENDING_JSON_BYTES => None,
+            Err(e) => {
+                self.pending.clear();
+                Some(Err(Error::Api {
+                    status: 0,
+                    message: format!("Failed to parse chunk: {}", e),
+                }))
+            }
+        }
+    }
+}
+
+/// Decrypts the `data:` payloads of an encrypted event stream.
+pub(crate) struct EventDecoder {
+    session_key: [u8; 32],
+    utf8: Utf8Buffer,
+    label: &'static str,
+}
+
+impl EventDecoder {
+    pub(crate) fn new(session_key: [u8; 32], label: &'static str) -> Self {
+        Self {
+            session_key,
+            utf8: Utf8Buffer::default(),
+            label,
+        }
+    }
+
+    /// Returns `None` for events that carry nothing to yield yet: non-base64
+    /// events (heartbeats, retries, etc.) and frames ending mid-character.
+    pub(crate) fn decode(&mut self, data: &str) -> Option<Result<String>> {
+        let encrypted_bytes = BASE64.decode(data).ok()?;
+        let decrypted = match crypto::decrypt_data(&self.session_key, &encrypted_bytes) {
+            Ok(decrypted) => decrypted,
+            Err(e) => {
+                return Some(Err(Error::Decryption(format!(
+                    "Failed to decrypt {}: {}",
+                    self.label, e
+                ))))
+            }
+        };
+
+        self.utf8.push(&decrypted).transpose()
+    }
+}
+
+/// Applies `policy` to a stream of decoded events: either end the stream after
+/// yielding the first error, or log errors and keep going.
+pub(crate) fn apply_error_policy<T, S>(
+    stream: S,
+    policy: StreamErrorPolicy,
+) -> Pin<Box<dyn Stream<Item = Result<T>> + Send>>
+where
+    T: Send + 'static,
+    S: Stream<Item = Result<T>> + Send + 'static,
+{
+    match policy {
+        StreamErrorPolicy::AbortOnError => {
+            let mut failed = false;
+            Box::pin(stream.take_while(move |item| {
+                let keep = !failed;
+                failed |= item.is_err();
+                futures::future::ready(keep)
+            }))
+        }
+        StreamErrorPolicy::SkipErrors => Box::pin(stream.filter_map(|item| {
+            futures::future::ready(match item {
+                Ok(value) => Some(Ok(value)),
+                Err(e) => {
+                    tracing::warn!("Skipping stream event: {}", e);
+                    None
+                }
+            })
+        })),
+    }
+}
+
+/// Ends `stream` with [`Error::Cancelled`] as soon as `cancel` fires.
+pub(crate) fn cancel_on<T>(
+    stream: Pin<Box<dyn Stream<Item = Result<T>> + Send>>,
+    cancel: CancellationToken,
+) -> Pin<Box<dyn Stream<Item = Result<T>> + Send>>
+where
+    T: Send + 'static,
+{
+    let stopped = cancel.clone();
+    let cancelled =
+        futures::stream::once(
+            async move { stopped.is_cancelled().then_some(Err(Error::Cancelled)) },
+        )
+        .filter_map(futures::future::ready);
+
+    Box::pin(stream.take_until(cancel.cancelled_owned()).chain(cancelled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8_buffer_joins_character_split_across_chunks() {
+        let text = "{\"content\":\"héllo 👋\"}";
+        let bytes = text.as_bytes();
+        let split = text.find('👋').unwrap() + 2;
+
+        let mut buffer = Utf8Buffer::default();
+        assert!(buffer.push(&bytes[..split]).unwrap().is_none());
+        assert_eq!(buffer.push(&bytes[split..]).unwrap().as_deref(), Some(text));
+    }
+
+    #[test]
+    fn test_json_reassembler_waits_for_complete_object() {
+        let mut json = JsonReassembler::default();
+        assert!(json
+            .push::<serde_json::Value>("{\"id\":\"abc\",\"choi")
+            .is_none());
+
+        let value = json
+            .push::<serde_json::Value>("ces\":[]}")
+            .unwrap()
+            .unwrap();
+        assert_eq!(value["id"], "abc");
+
+        assert!(json
+            .push::<serde_json::Value>("{\"broken\" 1}")
+            .unwrap()
+            .is_err());
+        assert!(json.push::<serde_json::Value>("{}").unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_utf8_buffer_rejects_invalid_bytes() {
+        let mut buffer = Utf8Buffer::default();
+        assert!(buffer.push(&[b'a', 0xff, b'b']).is_err());
+        assert_eq!(buffer.push(b"ok").unwrap().as_deref(), Some("ok"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_on_ends_stream_with_cancelled_error() {
+        let cancel = CancellationToken::new();
+        let finished = cancel_on(
+            Box::pin(futures::stream::iter([Ok(1), Ok(2)])),
+            cancel.clone(),
+        );
+        let items: Vec<Result<i32>> = finished.collect().await;
+        assert_eq!(items.len(), 2);
+
+        let pending = cancel_on(
+            Box::pin(futures::stream::pending::<Result<i32>>()),
+            cancel.clone(),
+        );
+        cancel.cancel();
+        let items: Vec<Result<i32>> = pending.collect().await;
+        assert!(matches!(items.as_slice(), [Err(Error::Cancelled)]));
+    }
+}
@@ -0,0 +1,193 @@
+//! Cancellable streaming responses and usage reporting for streams.
+
+use crate::error::Result;
+use crate::types::{ChatCompletionChunk, Usage};
+use futures::{Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::oneshot;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+type BoxStream<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send>>;
+
+/// A streamed response that can be stopped, e.g. from a "stop generating"
+/// button.
+///
+/// After [`Self::cancel`] the stream ends cleanly (yields `None`, not an
+/// error) and the underlying HTTP response is dropped, closing the
+/// connection. Cancellation is best-effort: the server only learns of it when
+/// the connection closes, and tokens it generated in the meantime may still
+/// be billed.
+pub struct StreamHandle<T> {
+    inner: Option<BoxStream<T>>,
+    cancel: CancellationToken,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl<T> StreamHandle<T> {
+    pub(crate) fn new(inner: BoxStream<T>) -> Self {
+        let cancel = CancellationToken::new();
+        Self {
+            inner: Some(inner),
+            cancelled: Box::pin(cancel.clone().cancelled_owned()),
+            cancel,
+        }
+    }
+
+    /// Stops the stream. Safe to call more than once.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// A token that cancels this stream, for stopping it from another task
+    /// while this one consumes it.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+}
+
+impl<T> Stream for StreamHandle<T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(inner) = this.inner.as_mut() else {
+            return Poll::Ready(None);
+        };
+
+        if this.cancelled.as_mut().poll(cx).is_ready() {
+            // Dropping the inner stream releases the response and its connection.
+            this.inner = None;
+            return Poll::Ready(None);
+        }
+
+        match inner.as_mut().poll_next(cx) {
+            Poll::Ready(None) => {
+                this.inner = None;
+                Poll::Ready(None)
+            }
+            other => other,
+        }
+    }
+}
+
+/// Resolves to the token usage of a streamed completion once its stream has
+/// ended or been dropped: the last `usage` block seen, or `None` if the
+/// server sent none.
+pub struct UsageHandle {
+    rx: oneshot::Receiver<Option<Usage>>,
+}
+
+impl Future for UsageHandle {
+    type Output = Option<Usage>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx)
+            .poll(cx)
+            .map(|result| result.ok().flatten())
+    }
+}
+
+/// Records usage as chunks pass and reports it when finished or dropped.
+struct UsageRecorder {
+    usage: Option<Usage>,
+    tx: Option<oneshot::Sender<Option<Usage>>>,
+}
+
+impl UsageRecorder {
+    fn finish(&mut self) {
+        if let Some(tx) = self.tx.take() {
+            let _ = tx.send(self.usage.take());
+        }
+    }
+}
+
+impl Drop for UsageRecorder {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Splits the usage reported by `stream` off into a [`UsageHandle`]. Chunks
+/// pass through unchanged.
+pub(crate) fn track_usage(
+    stream: BoxStream<ChatCompletionChunk>,
+) -> (BoxStream<ChatCompletionChunk>, UsageHandle) {
+    let (tx, rx) = oneshot::channel();
+    let recorder = Arc::new(Mutex::new(UsageRecorder {
+        usage: None,
+        tx: Some(tx),
+    }));
+
+    let on_chunk = recorder.clone();
+    let stream = stream
+        .inspect(move |item| {
+            if let Some(usage) = item.as_ref().ok().and_then(ChatCompletionChunk::usage) {
+                if let Ok(mut recorder) = on_chunk.lock() {
+                    recorder.usage = Some(usage);
+                }
+            }
+        })
+        .chain(
+            futures::stream::once(async move {
+                if let Ok(mut recorder) = recorder.lock() {
+                    recorder.finish();
+                }
+                None
+            })
+            .filter_map(futures::future::ready),
+        );
+
+    (Box::pin(stream), UsageHandle { rx })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chunks(values: Vec<serde_json::Value>) -> BoxStream<ChatCompletionChunk> {
+        Box::pin(futures::stream::iter(
+            values
+                .into_iter()
+                .map(|value| Ok(ChatCompletionChunk(value))),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_track_usage_resolves_after_stream_ends() {
+        let (stream, usage) = track_usage(chunks(vec![
+            json!({"choices": [{"index": 0, "delta": {"content": "hi"}}]}),
+            json!({"choices": [], "usage": {
+                "prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4
+            }}),
+        ]));
+
+        let passed: Vec<_> = stream.collect().await;
+        assert_eq!(passed.len(), 2);
+        assert_eq!(usage.await.unwrap().total_tokens, 4);
+    }
+
+    #[tokio::test]
+    async fn test_track_usage_without_usage_chunk_or_after_drop() {
+        let (stream, usage) = track_usage(chunks(vec![json!({"choices": []})]));
+        let _: Vec<_> = stream.collect().await;
+        assert!(usage.await.is_none());
+
+        let (mut stream, usage) = track_usage(chunks(vec![
+            json!({"choices": [], "usage": {
+                "prompt_tokens": 2, "completion_tokens": 0, "total_tokens": 2
+            }}),
+            json!({"choices": [{"index": 0, "delta": {"content": "unread"}}]}),
+        ]));
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+        assert_eq!(usage.await.unwrap().total_tokens, 2);
+    }
+}
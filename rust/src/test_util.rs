@@ -0,0 +1,141 @@
+//! Helpers for building servers and test doubles that speak the OpenSecret
+//! encrypted envelope format.
+//!
+//! Only compiled with the `test-util` feature. Nothing in here should be used
+//! by a production client.
+
+use crate::crypto::{self, PublicKey};
+use crate::error::{Error, Result};
+use crate::types::{EncryptedRequest, EncryptedResponse, KeyExchangeResponse};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use x25519_dalek::{SharedSecret, StaticSecret};
+
+/// Seals and opens payloads with a session key, exactly as the client and
+/// server do after a successful key exchange.
+#[derive(Clone)]
+pub struct SessionCipher {
+    session_key: [u8; 32],
+}
+
+impl SessionCipher {
+    pub fn new(session_key: [u8; 32]) -> Self {
+        Self { session_key }
+    }
+
+    pub fn session_key(&self) -> &[u8; 32] {
+        &self.session_key
+    }
+
+    pub fn seal_value(&self, value: &Value) -> Result<EncryptedRequest> {
+        self.seal(value)
+    }
+
+    pub fn open_value(&self, request: &EncryptedRequest) -> Result<Value> {
+        self.open(request)
+    }
+
+    /// Serializes and encrypts any payload into a request envelope.
+    pub fn seal<T: Serialize>(&self, payload: &T) -> Result<EncryptedRequest> {
+        let plaintext = serde_json::to_vec(payload)?;
+        let encrypted = crypto::encrypt_data(&self.session_key, &plaintext)?;
+        Ok(EncryptedRequest {
+            encrypted: BASE64.encode(encrypted),
+        })
+    }
+
+    /// Decrypts a request envelope and deserializes its payload.
+    pub fn open<T: DeserializeOwned>(&self, request: &EncryptedRequest) -> Result<T> {
+        let encrypted = BASE64.decode(&request.encrypted)?;
+        let plaintext = crypto::decrypt_data(&self.session_key, &encrypted)?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    /// Builds the response envelope a server returns for `payload`.
+    pub fn seal_response<T: Serialize>(&self, payload: &T) -> Result<EncryptedResponse<T>> {
+        let sealed = self.seal(payload)?;
+        Ok(EncryptedResponse::new(sealed.encrypted))
+    }
+
+    pub fn open_response<T: DeserializeOwned>(&self, response: &EncryptedResponse<T>) -> Result<T> {
+        self.open(&EncryptedRequest {
+            encrypted: response.encrypted.clone(),
+        })
+    }
+
+    /// Formats `payload` as a single encrypted SSE `data:` frame.
+    pub fn seal_sse_event<T: Serialize>(&self, payload: &T) -> Result<String> {
+        Ok(format!("data: {}\n\n", self.seal(payload)?.encrypted))
+    }
+}
+
+/// Encrypts a session key with the ECDH shared secret, the inverse of
+/// [`crypto::decrypt_session_key`].
+pub fn encrypt_session_key(shared_secret: &SharedSecret, session_key: &[u8; 32]) -> Result<String> {
+    let encrypted = crypto::encrypt_data(shared_secret.as_bytes(), session_key)?;
+    Ok(BASE64.encode(encrypted))
+}
+
+/// Performs the server half of `/key_exchange`: derives the shared secret from
+/// the client's base64 public key and wraps `session_key` with it.
+pub fn server_key_exchange(
+    server_secret: &StaticSecret,
+    client_public_key_b64: &str,
+    session_key: &[u8; 32],
+    session_id: Uuid,
+) -> Result<KeyExchangeResponse> {
+    let client_public_bytes = BASE64.decode(client_public_key_b64)?;
+    let client_public_key = PublicKey::from(
+        <[u8; 32]>::try_from(client_public_bytes.as_slice())
+            .map_err(|_| Error::KeyExchange("Invalid client public key length".to_string()))?,
+    );
+    let shared_secret = crypto::perform_static_key_exchange(server_secret, &client_public_key);
+
+    Ok(KeyExchangeResponse {
+        encrypted_session_key: encrypt_session_key(&shared_secret, session_key)?,
+        session_id: session_id.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_seal_and_open_value_round_trip() {
+        let cipher = SessionCipher::new([3u8; 32]);
+        let value = json!({ "hello": "world", "n": 1 });
+
+        let sealed = cipher.seal_value(&value).unwrap();
+        assert_eq!(cipher.open_value(&sealed).unwrap(), value);
+
+        let response = cipher.seal_response(&value).unwrap();
+        assert_eq!(cipher.open_response(&response).unwrap(), value);
+    }
+
+    #[test]
+    fn test_server_key_exchange_yields_session_key_client_can_decrypt() {
+        let (server_secret, server_public) = crypto::generate_static_keypair();
+        let (client_secret, client_public) = crypto::generate_static_keypair();
+        let session_key = [8u8; 32];
+        let session_id = Uuid::new_v4();
+
+        let response = server_key_exchange(
+            &server_secret,
+            &BASE64.encode(client_public.as_bytes()),
+            &session_key,
+            session_id,
+        )
+        .unwrap();
+
+        let shared_secret = crypto::perform_static_key_exchange(&client_secret, &server_public);
+        let decrypted =
+            crypto::decrypt_session_key(&shared_secret, &response.encrypted_session_key).unwrap();
+
+        assert_eq!(decrypted, session_key);
+        assert_eq!(response.session_id, session_id.to_string());
+    }
+}
@@ -0,0 +1,168 @@
+//! TLS public-key pinning for the transport to the enclave.
+//!
+//! Attestation already protects request contents, but a MITM holding a valid
+//! CA-issued certificate can still observe traffic metadata. Pinning requires
+//! the server's end-entity certificate to carry one of a known set of public
+//! keys, identified by the SHA-256 of their DER `SubjectPublicKeyInfo`.
+
+use crate::error::{Error, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// SHA-256 of the DER `SubjectPublicKeyInfo` of `cert_der`, the value to pass
+/// to `OpenSecretClientBuilder::pinned_tls_spki`.
+pub fn spki_sha256(cert_der: &[u8]) -> Result<[u8; 32]> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert_der)
+        .map_err(|e| Error::Configuration(format!("Invalid certificate: {}", e)))?;
+    Ok(Sha256::digest(cert.tbs_certificate.subject_pki.raw).into())
+}
+
+/// Runs the normal WebPKI chain and hostname checks, then requires the
+/// end-entity key to match a pin.
+#[derive(Debug)]
+pub(crate) struct PinnedSpkiVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl PinnedSpkiVerifier {
+    pub(crate) fn new(pins: Vec<Vec<u8>>) -> Result<Self> {
+        if pins.is_empty() {
+            return Err(Error::Configuration(
+                "At least one SPKI pin is required".to_string(),
+            ));
+        }
+        let pins = pins
+            .into_iter()
+            .map(|pin| {
+                <[u8; 32]>::try_from(pin.as_slice()).map_err(|_| {
+                    Error::Configuration(format!(
+                        "SPKI pins must be 32-byte SHA-256 hashes, got {} bytes",
+                        pin.len()
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let inner = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(rustls::crypto::ring::default_provider()),
+        )
+        .build()
+        .map_err(|e| Error::Configuration(format!("Failed to build TLS verifier: {}", e)))?;
+
+        Ok(Self { inner, pins })
+    }
+
+    fn check_pins(
+        &self,
+        end_entity: &CertificateDer<'_>,
+    ) -> std::result::Result<(), rustls::Error> {
+        let hash =
+            spki_sha256(end_entity.as_ref()).map_err(|e| rustls::Error::General(e.to_string()))?;
+        if self
+            .pins
+            .iter()
+            .any(|pin| crate::crypto::constant_time_eq(pin, &hash))
+        {
+            Ok(())
+        } else {
+            Err(rustls::Error::General(format!(
+                "TLS certificate public key {} does not match any pinned SPKI hash",
+                hex::encode(hash)
+            )))
+        }
+    }
+
+    /// A rustls client config that uses this verifier, for reqwest.
+    pub(crate) fn into_client_config(self) -> rustls::ClientConfig {
+        let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .expect("ring provider supports the default protocol versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(self))
+        .with_no_client_auth();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        config
+    }
+}
+
+impl ServerCertVerifier for PinnedSpkiVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        self.check_pins(end_entity)?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERT: &[u8] = include_bytes!("../assets/aws_nitro_root.der");
+
+    #[test]
+    fn test_pinned_verifier_accepts_only_pinned_keys() {
+        let pin = spki_sha256(CERT).unwrap();
+        let cert = CertificateDer::from(CERT);
+
+        let verifier = PinnedSpkiVerifier::new(vec![pin.to_vec()]).unwrap();
+        assert!(verifier.check_pins(&cert).is_ok());
+
+        let verifier = PinnedSpkiVerifier::new(vec![vec![0u8; 32]]).unwrap();
+        let error = verifier.check_pins(&cert).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("does not match any pinned SPKI hash"));
+    }
+
+    #[test]
+    fn test_pinned_verifier_rejects_malformed_pins() {
+        assert!(PinnedSpkiVerifier::new(vec![]).is_err());
+        assert!(PinnedSpkiVerifier::new(vec![vec![1u8; 20]]).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use uuid::Uuid;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 // Attestation & Key Exchange Types
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,19 +33,242 @@ pub struct EncryptedRequest {
     pub encrypted: String, // Base64-encoded (nonce + ciphertext)
 }
 
-#[derive(Debug, Clone)]
+/// The session key is wiped when the state is dropped, so fields cannot be
+/// moved out of it; copy or clone them instead.
+#[derive(Debug, Clone, Zeroize, ZeroizeOnDrop)]
 pub struct SessionState {
+    #[zeroize(skip)]
     pub session_id: Uuid,
     pub session_key: [u8; 32],
 }
 
+// Endpoint Discovery Types
+/// An enclave deployment listed by an endpoint directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointInfo {
+    pub region: String,
+    pub url: String,
+    /// Hex-encoded PCR values the operator publishes for this deployment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_pcrs: Option<HashMap<usize, String>>,
+}
+
+impl EndpointInfo {
+    /// Decodes `expected_pcrs` into the form taken by
+    /// `AttestationVerifier::with_expected_pcrs`.
+    pub fn expected_pcr_bytes(&self) -> crate::Result<Option<HashMap<usize, Vec<u8>>>> {
+        let Some(pcrs) = &self.expected_pcrs else {
+            return Ok(None);
+        };
+        pcrs.iter()
+            .map(|(index, value)| {
+                hex::decode(value)
+                    .map(|bytes| (*index, bytes))
+                    .map_err(|e| {
+                        crate::Error::InvalidResponse(format!(
+                            "Invalid PCR{} for region {}: {}",
+                            index, self.region, e
+                        ))
+                    })
+            })
+            .collect::<crate::Result<HashMap<_, _>>>()
+            .map(Some)
+    }
+}
+
+// Health Check Types
+/// Health-check payload, normalized across the formats deployments return:
+/// plain text (`OK`), JSON with `status`/`timestamp`, or a version blob.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthStatus {
+    /// Lowercased status, or `"unknown"` when the body was not recognized.
+    pub status: String,
+    pub version: Option<String>,
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Enclave details reported under `enclave`, when the deployment
+    /// includes them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enclave: Option<Value>,
+}
+
+impl HealthStatus {
+    pub const UNKNOWN: &'static str = "unknown";
+
+    /// Parses a health-check body. Never fails; unrecognized bodies yield a
+    /// status of [`HealthStatus::UNKNOWN`].
+    pub fn parse(body: &str) -> Self {
+        let body = body.trim();
+
+        if let Ok(status) = Self::parse_json(body) {
+            return status;
+        }
+
+        let is_word = !body.is_empty()
+            && body.len() <= 32
+            && body
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        Self {
+            status: if is_word {
+                body.to_lowercase()
+            } else {
+                Self::UNKNOWN.to_string()
+            },
+            version: None,
+            timestamp: None,
+            enclave: None,
+        }
+    }
+
+    /// Parses a JSON health-check body, failing with
+    /// [`crate::Error::InvalidResponse`] if it is not a JSON object.
+    pub fn parse_json(body: &str) -> crate::Result<Self> {
+        match serde_json::from_str::<Value>(body.trim()) {
+            Ok(Value::Object(fields)) => {
+                let text = |key: &str| match fields.get(key) {
+                    Some(Value::String(value)) => Some(value.trim().to_string()),
+                    Some(Value::Number(value)) => Some(value.to_string()),
+                    _ => None,
+                };
+                let version = text("version");
+                let status = text("status")
+                    .map(|status| status.to_lowercase())
+                    .or_else(|| version.as_ref().map(|_| "ok".to_string()))
+                    .unwrap_or_else(|| Self::UNKNOWN.to_string());
+                let timestamp = fields.get("timestamp").and_then(parse_health_timestamp);
+
+                Ok(Self {
+                    status,
+                    version,
+                    timestamp,
+                    enclave: fields.get("enclave").cloned(),
+                })
+            }
+            _ => Err(crate::Error::InvalidResponse(format!(
+                "health check did not return a JSON object: {}",
+                body.trim()
+            ))),
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        self.status == Self::UNKNOWN
+    }
+
+    /// Whether the status is one of the usual "up" spellings.
+    pub fn is_healthy(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "ok" | "healthy" | "up" | "pass" | "alive" | "ready"
+        )
+    }
+}
+
+/// Accepts RFC 3339 strings and Unix timestamps in seconds or milliseconds.
+fn parse_health_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|timestamp| timestamp.with_timezone(&Utc)),
+        Value::Number(number) => {
+            let raw = number.as_i64()?;
+            if raw > 100_000_000_000 {
+                DateTime::from_timestamp_millis(raw)
+            } else {
+                DateTime::from_timestamp(raw, 0)
+            }
+        }
+        _ => None,
+    }
+}
+
 // Token Management Types
-#[derive(Debug, Clone)]
+/// Both tokens are wiped when the pair is dropped, so they cannot be moved
+/// out of it; clone them instead.
+#[derive(Debug, Clone, Zeroize, ZeroizeOnDrop)]
 pub struct TokenPair {
     pub access_token: String,
     pub refresh_token: Option<String>,
 }
 
+/// Session key and tokens saved by `OpenSecretClient::export_session` so a
+/// restarted process can resume without a new handshake and login.
+///
+/// **This is secret material.** `session_key` decrypts all traffic of the
+/// session and the tokens act as the user. Keep it in encrypted storage such
+/// as the platform keychain, never in logs or plain files. `Debug` redacts
+/// everything but the session id, and the key and tokens are wiped when the
+/// value is dropped.
+#[derive(Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct SerializedSession {
+    #[zeroize(skip)]
+    pub session_id: Uuid,
+    pub session_key: [u8; 32],
+    pub access_token: Option<String>,
+    pub refresh_token: Option<String>,
+}
+
+impl std::fmt::Debug for SerializedSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |present: bool| if present { "Some(..)" } else { "None" };
+        f.debug_struct("SerializedSession")
+            .field("session_id", &self.session_id)
+            .field("session_key", &format_args!(".."))
+            .field(
+                "access_token",
+                &format_args!("{}", redacted(self.access_token.is_some())),
+            )
+            .field(
+                "refresh_token",
+                &format_args!("{}", redacted(self.refresh_token.is_some())),
+            )
+            .finish()
+    }
+}
+
+/// Credential to use for OpenAI-compatible calls instead of the client's
+/// default of "API key if set, otherwise JWT".
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credential {
+    /// The logged-in user's access token, even when an API key is set.
+    Jwt,
+    /// A specific API key, regardless of the one stored on the client.
+    ApiKey(String),
+}
+
+impl std::fmt::Debug for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Jwt => f.write_str("Jwt"),
+            Self::ApiKey(_) => f.write_str("ApiKey(..)"),
+        }
+    }
+}
+
+/// Retry policy for transient failures (502/503-style statuses, 429 and
+/// connection errors), set with `OpenSecretClient::with_retry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Retries after the first attempt.
+    pub max_retries: u32,
+    /// Delay before the first retry; it doubles with every further retry and
+    /// is jittered by up to half.
+    pub base_delay: std::time::Duration,
+    /// Upper bound on any single delay. A `Retry-After` asking for longer ends
+    /// the retries.
+    pub max_delay: std::time::Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: std::time::Duration::from_millis(200),
+            max_delay: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RefreshRequest {
     pub refresh_token: String,
@@ -106,6 +331,15 @@ pub struct EncryptedResponse<T> {
     _phantom: std::marker::PhantomData<T>,
 }
 
+impl<T> EncryptedResponse<T> {
+    pub fn new(encrypted: String) -> Self {
+        Self {
+            encrypted,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum NullableField<T> {
     #[default]
@@ -166,26 +400,39 @@ pub struct OAuthInitRequest {
     pub invite_code: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GithubAuthResponse {
-    pub auth_url: String,
-    #[serde(alias = "csrf_token")]
-    pub state: String,
+/// Identity providers supported for OAuth sign-in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OAuthProvider {
+    Github,
+    Google,
+    Apple,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GoogleAuthResponse {
-    pub auth_url: String,
-    #[serde(alias = "csrf_token")]
-    pub state: String,
+impl OAuthProvider {
+    /// Path segment of the provider's `/auth/{provider}` routes.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OAuthProvider::Github => "github",
+            OAuthProvider::Google => "google",
+            OAuthProvider::Apple => "apple",
+        }
+    }
 }
 
+/// Authorization URL to send the user to, and the state to check when the
+/// provider redirects back.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppleAuthResponse {
+pub struct OAuthInitResponse {
     pub auth_url: String,
+    #[serde(alias = "csrf_token")]
     pub state: String,
 }
 
+pub type GithubAuthResponse = OAuthInitResponse;
+pub type GoogleAuthResponse = OAuthInitResponse;
+pub type AppleAuthResponse = OAuthInitResponse;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OAuthCallbackRequest {
     pub code: String,
@@ -373,11 +620,120 @@ pub struct KeyOptions {
     pub seed_phrase_derivation_path: Option<String>,
 }
 
+impl KeyOptions {
+    /// Rejects derivation paths with more than `max_depth` components
+    /// (`m/44'/0'/0'/0/5` has depth 5).
+    pub fn validate_depth(&self, max_depth: usize) -> crate::Result<()> {
+        for path in [
+            &self.private_key_derivation_path,
+            &self.seed_phrase_derivation_path,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let depth = derivation_path_depth(path);
+            if depth > max_depth {
+                return Err(crate::Error::Configuration(format!(
+                    "Derivation path {} has depth {}, exceeding the maximum depth of {}",
+                    path, depth, max_depth
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn derivation_path_depth(path: &str) -> usize {
+    path.trim()
+        .trim_start_matches('m')
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .count()
+}
+
+/// Number of words in a BIP-85 derived child mnemonic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordCount {
+    Twelve,
+    Eighteen,
+    TwentyFour,
+}
+
+impl WordCount {
+    pub fn words(self) -> u32 {
+        match self {
+            Self::Twelve => 12,
+            Self::Eighteen => 18,
+            Self::TwentyFour => 24,
+        }
+    }
+
+    /// BIP-85 BIP39 application path (English) for this word count:
+    /// `m/83696968'/39'/0'/{words}'/{index}'`.
+    pub fn bip85_path(self, index: u32) -> crate::Result<String> {
+        if index >= 0x8000_0000 {
+            return Err(crate::Error::Configuration(format!(
+                "BIP-85 index {} is out of range for a hardened derivation",
+                index
+            )));
+        }
+        Ok(format!("m/83696968'/39'/0'/{}'/{}'", self.words(), index))
+    }
+}
+
+impl TryFrom<u32> for WordCount {
+    type Error = crate::Error;
+
+    fn try_from(words: u32) -> crate::Result<Self> {
+        match words {
+            12 => Ok(Self::Twelve),
+            18 => Ok(Self::Eighteen),
+            24 => Ok(Self::TwentyFour),
+            other => Err(crate::Error::Configuration(format!(
+                "Unsupported mnemonic word count {}: expected 12, 18, or 24",
+                other
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrivateKeyResponse {
     pub mnemonic: String,
 }
 
+impl PrivateKeyResponse {
+    pub fn words(&self) -> Vec<&str> {
+        self.mnemonic.split_whitespace().collect()
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.mnemonic.split_whitespace().count()
+    }
+
+    /// Checks that every word is in the BIP-39 English wordlist and that the
+    /// mnemonic's checksum is valid.
+    pub fn validate(&self) -> crate::Result<()> {
+        let language = bip39::Language::English;
+        if let Some((position, word)) = self
+            .words()
+            .into_iter()
+            .enumerate()
+            .find(|(_, word)| language.find_word(word).is_none())
+        {
+            return Err(crate::Error::Crypto(format!(
+                "Invalid mnemonic: word {} ({:?}) is not in the BIP-39 wordlist",
+                position + 1,
+                word
+            )));
+        }
+
+        bip39::Mnemonic::parse_in_normalized(language, &self.mnemonic)
+            .map(|_| ())
+            .map_err(|e| crate::Error::Crypto(format!("Invalid mnemonic: {}", e)))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrivateKeyBytesResponse {
     pub private_key: String, // Hex encoded (64 characters for 32 bytes)
@@ -397,6 +753,12 @@ pub struct SignMessageRequest {
     pub algorithm: SigningAlgorithm,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key_options: Option<SigningKeyOptions>,
+    /// When true, `message_base64` is a 32-byte digest that is signed as is
+    /// instead of being hashed by the server. Servers that do not know the
+    /// flag ignore it and hash the digest; `message_hash` in the response
+    /// shows which was signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_already_computed: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -469,6 +831,26 @@ pub struct ChangePasswordRequest {
     pub new_password: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeEmailRequest {
+    pub new_email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeEmailResponse {
+    #[serde(default)]
+    pub message: String,
+    /// Whether the new address must be verified with the code sent to it
+    /// before it is marked verified. Assumed when the server does not say.
+    #[serde(default = "default_verification_required")]
+    pub verification_required: bool,
+}
+
+fn default_verification_required() -> bool {
+    true
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PasswordResetRequest {
     pub email: String,
@@ -692,12 +1074,66 @@ pub struct Model {
     pub created: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub owned_by: Option<String>,
+    /// Backend-specific annotations such as pricing and limits.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
 }
 
 fn default_model_object() -> String {
     "model".to_string()
 }
 
+impl Model {
+    /// Price per 1K input tokens, from `input_price` (per 1K) or
+    /// `pricing.prompt` (per token). `None` when the backend doesn't say.
+    pub fn input_price(&self) -> Option<f64> {
+        self.price_field("input_price", "prompt")
+    }
+
+    /// Price per 1K output tokens, from `output_price` (per 1K) or
+    /// `pricing.completion` (per token).
+    pub fn output_price(&self) -> Option<f64> {
+        self.price_field("output_price", "completion")
+    }
+
+    /// Context or output token limit, from `max_tokens`, `max_model_len` or
+    /// `context_length`.
+    pub fn max_tokens(&self) -> Option<u64> {
+        ["max_tokens", "max_model_len", "context_length"]
+            .iter()
+            .find_map(|key| self.extra.get(*key).and_then(json_number))
+            .map(|value| value as u64)
+    }
+
+    /// Both prices, if the backend annotates the model with them.
+    pub fn price(&self) -> Option<ModelPrice> {
+        Some(ModelPrice {
+            input_per_1k: self.input_price()?,
+            output_per_1k: self.output_price()?,
+        })
+    }
+
+    fn price_field(&self, per_1k_key: &str, per_token_key: &str) -> Option<f64> {
+        if let Some(price) = self.extra.get(per_1k_key).and_then(json_number) {
+            return Some(price);
+        }
+        self.extra
+            .get("pricing")?
+            .get(per_token_key)
+            .and_then(json_number)
+            .map(|per_token| per_token * 1000.0)
+    }
+}
+
+/// Numbers are sometimes sent as strings to avoid float rounding.
+fn json_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelsResponse {
     pub object: String,
@@ -712,6 +1148,45 @@ pub struct Tool {
     pub function: Function,
 }
 
+impl Tool {
+    /// A function tool whose parameters are given as a JSON schema string. The
+    /// schema must be a JSON object with a string `type`, e.g.
+    /// `{"type": "object", "properties": {...}}`.
+    pub fn function(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        schema_json: &str,
+    ) -> crate::Result<Self> {
+        let name = name.into();
+        let parameters: Value = serde_json::from_str(schema_json).map_err(|e| {
+            crate::Error::Configuration(format!("Invalid schema for tool {}: {}", name, e))
+        })?;
+
+        let Some(schema) = parameters.as_object() else {
+            return Err(crate::Error::Configuration(format!(
+                "Schema for tool {} must be a JSON object",
+                name
+            )));
+        };
+        if !schema.get("type").is_some_and(Value::is_string) {
+            return Err(crate::Error::Configuration(format!(
+                "Schema for tool {} must have a string \"type\"",
+                name
+            )));
+        }
+
+        let description = description.into();
+        Ok(Self {
+            tool_type: "function".to_string(),
+            function: Function {
+                name,
+                description: (!description.is_empty()).then_some(description),
+                parameters,
+            },
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Function {
     pub name: String,
@@ -747,7 +1222,158 @@ pub struct ChatMessage {
     pub reasoning_content: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ChatMessage {
+    /// A plain-text message with the given role.
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: Value::String(content.into()),
+            tool_calls: None,
+            reasoning_content: None,
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new("system", content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new("user", content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new("assistant", content)
+    }
+
+    /// A user message with a single text part in array form, for appending
+    /// further parts (images, audio) to `content`.
+    pub fn user_text(text: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: Value::Array(vec![text_part(text.into())]),
+            tool_calls: None,
+            reasoning_content: None,
+        }
+    }
+
+    /// A user message asking about an image. `image_url` may be an
+    /// `https://` URL or a `data:` URL.
+    pub fn user_with_image(text: impl Into<String>, image_url: impl Into<String>) -> Self {
+        let mut message = Self::user_text(text);
+        if let Value::Array(parts) = &mut message.content {
+            parts.push(serde_json::json!({
+                "type": "image_url",
+                "image_url": { "url": image_url.into() },
+            }));
+        }
+        message
+    }
+
+    /// Like [`Self::user_with_image`] for raw image bytes, sent inline as a
+    /// base64 `data:` URL. `mime` is the image type, e.g. `image/png`.
+    pub fn user_with_image_bytes(text: impl Into<String>, bytes: &[u8], mime: &str) -> Self {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let data_url = format!("data:{};base64,{}", mime, STANDARD.encode(bytes));
+        Self::user_with_image(text, data_url)
+    }
+
+    /// Returns the text of the message whether `content` is a plain string or an
+    /// array of content parts. Text parts are concatenated in order; non-text
+    /// parts (images, audio) are skipped. Returns `None` when there is no text.
+    pub fn text_content(&self) -> Option<String> {
+        match &self.content {
+            Value::String(text) => Some(text.clone()),
+            Value::Array(parts) => {
+                let texts: Vec<&str> = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        Value::String(text) => Some(text.as_str()),
+                        Value::Object(_) => part.get("text").and_then(Value::as_str),
+                        _ => None,
+                    })
+                    .collect();
+                if texts.is_empty() {
+                    None
+                } else {
+                    Some(texts.concat())
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Rough token count of this message: about four characters per token
+    /// plus a fixed per-message overhead. Good enough for budgeting, not for
+    /// billing.
+    pub fn estimated_tokens(&self) -> usize {
+        let mut chars = match self.text_content() {
+            Some(text) => text.chars().count(),
+            None if self.content.is_null() => 0,
+            None => self.content.to_string().len(),
+        };
+        for call in self.tool_calls.iter().flatten() {
+            chars += call.function.name.len() + call.function.arguments.len();
+        }
+        MESSAGE_TOKEN_OVERHEAD + chars.div_ceil(4)
+    }
+}
+
+const MESSAGE_TOKEN_OVERHEAD: usize = 4;
+
+fn text_part(text: String) -> Value {
+    serde_json::json!({ "type": "text", "text": text })
+}
+
+/// Drops the oldest non-system messages until the estimated size of
+/// `messages` fits in `max_context_tokens - reserve_for_completion`.
+///
+/// System messages are always kept, and an assistant message is dropped
+/// together with the `tool` responses that follow it so no tool result is left
+/// without its call. The newest exchange is kept even if it alone exceeds the
+/// budget. Token counts come from [`ChatMessage::estimated_tokens`].
+pub fn truncate_to_fit(
+    messages: &[ChatMessage],
+    max_context_tokens: usize,
+    reserve_for_completion: usize,
+) -> Vec<ChatMessage> {
+    let budget = max_context_tokens.saturating_sub(reserve_for_completion);
+
+    // Group non-system messages so tool responses stay with the preceding message.
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        match message.role.as_str() {
+            "system" => {}
+            "tool" if !groups.is_empty() => groups.last_mut().unwrap().push(index),
+            _ => groups.push(vec![index]),
+        }
+    }
+
+    let tokens = |indices: &[usize]| -> usize {
+        indices
+            .iter()
+            .map(|&index| messages[index].estimated_tokens())
+            .sum()
+    };
+    let mut total: usize = messages.iter().map(ChatMessage::estimated_tokens).sum();
+
+    let mut first_kept = 0;
+    while total > budget && first_kept + 1 < groups.len() {
+        total -= tokens(&groups[first_kept]);
+        first_kept += 1;
+    }
+
+    let dropped: std::collections::HashSet<usize> =
+        groups[..first_kept].iter().flatten().copied().collect();
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !dropped.contains(index))
+        .map(|(_, message)| message.clone())
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ChatCompletionRequest {
     pub model: String,
     pub messages: Vec<ChatMessage>,
@@ -755,6 +1381,28 @@ pub struct ChatCompletionRequest {
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<i32>,
+    /// Number of choices to generate for each request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<StopSequence>,
+    /// Best-effort determinism: repeated requests with the same seed and
+    /// parameters should return the same result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// Return the log probability of each output token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+    /// Number of most likely alternatives to return per token (requires
+    /// `logprobs`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -763,6 +1411,60 @@ pub struct ChatCompletionRequest {
     pub tools: Option<Vec<Tool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// Vendor-specific parameters (`top_k`, `min_p`, `guided_json`, ...) merged
+    /// into the top level of the request body. Must not repeat a typed field.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<serde_json::Map<String, Value>>,
+}
+
+impl ChatCompletionRequest {
+    /// A request with only `model` and `messages` set.
+    pub fn new(model: impl Into<String>, messages: Vec<ChatMessage>) -> Self {
+        Self {
+            model: model.into(),
+            messages,
+            ..Default::default()
+        }
+    }
+
+    const FIELDS: &'static [&'static str] = &[
+        "model",
+        "messages",
+        "temperature",
+        "max_tokens",
+        "n",
+        "top_p",
+        "frequency_penalty",
+        "presence_penalty",
+        "stop",
+        "seed",
+        "logprobs",
+        "top_logprobs",
+        "stream",
+        "stream_options",
+        "tools",
+        "tool_choice",
+        "response_format",
+    ];
+
+    /// Rejects `extra` entries that would collide with a typed field.
+    pub fn validate_extra(&self) -> crate::Result<()> {
+        let Some(extra) = &self.extra else {
+            return Ok(());
+        };
+        match extra
+            .keys()
+            .find(|key| Self::FIELDS.contains(&key.as_str()))
+        {
+            Some(key) => Err(crate::Error::Configuration(format!(
+                "extra parameter '{}' collides with a ChatCompletionRequest field",
+                key
+            ))),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -770,6 +1472,68 @@ pub struct StreamOptions {
     pub include_usage: bool,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StopSequence {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl From<String> for StopSequence {
+    fn from(s: String) -> Self {
+        StopSequence::Single(s)
+    }
+}
+
+impl From<&str> for StopSequence {
+    fn from(s: &str) -> Self {
+        StopSequence::Single(s.to_string())
+    }
+}
+
+impl From<Vec<String>> for StopSequence {
+    fn from(v: Vec<String>) -> Self {
+        StopSequence::Multiple(v)
+    }
+}
+
+/// Output format requested from the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    /// Any valid JSON object.
+    JsonObject,
+    /// JSON conforming to a schema (structured outputs).
+    JsonSchema {
+        json_schema: JsonSchemaFormat,
+    },
+}
+
+impl ResponseFormat {
+    /// Structured output named `name`, constrained to `schema`.
+    pub fn json_schema(name: impl Into<String>, schema: Value) -> Self {
+        ResponseFormat::JsonSchema {
+            json_schema: JsonSchemaFormat {
+                name: name.into(),
+                description: None,
+                schema,
+                strict: None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatCompletionResponse {
     pub id: String,
@@ -781,11 +1545,104 @@ pub struct ChatCompletionResponse {
     pub usage: Option<Usage>,
 }
 
+impl ChatCompletionResponse {
+    /// Parses the first choice's content as JSON and checks it against
+    /// `schema`, returning the parsed value. `schema` may be the bare JSON
+    /// schema or the `{"type": "json_schema", "json_schema": {"schema": ...}}`
+    /// response format it was requested with. A surrounding Markdown code
+    /// fence is tolerated.
+    ///
+    /// Fails with [`crate::Error::InvalidResponse`] listing every violation
+    /// when the output does not conform.
+    #[cfg(feature = "jsonschema")]
+    pub fn validate_against_schema(&self, schema: &Value) -> crate::Result<Value> {
+        let schema = schema.pointer("/json_schema/schema").unwrap_or(schema);
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|e| crate::Error::Configuration(format!("Invalid JSON schema: {}", e)))?;
+
+        let content = self
+            .choices
+            .first()
+            .and_then(|choice| choice.message.text_content())
+            .ok_or_else(|| {
+                crate::Error::InvalidResponse("Completion has no text content".to_string())
+            })?;
+        let value: Value = serde_json::from_str(strip_code_fence(&content)).map_err(|e| {
+            crate::Error::InvalidResponse(format!("Completion content is not JSON: {}", e))
+        })?;
+
+        let violations: Vec<String> = validator
+            .iter_errors(&value)
+            .map(|error| format!("{}: {}", error.instance_path, error))
+            .collect();
+        if !violations.is_empty() {
+            return Err(crate::Error::InvalidResponse(format!(
+                "Completion does not match schema: {}",
+                violations.join("; ")
+            )));
+        }
+
+        Ok(value)
+    }
+
+    /// Cost of this completion, or `None` when the response carries no usage or
+    /// its model is missing from `prices`.
+    pub fn cost(&self, prices: &PriceTable) -> Option<f64> {
+        estimate_cost(&self.model, self.usage.as_ref()?, prices)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatChoice {
     pub index: i32,
     pub message: ChatMessage,
     pub finish_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<ChoiceLogprobs>,
+}
+
+/// Per-token log probabilities of a choice, returned when the request set
+/// `logprobs`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChoiceLogprobs {
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogprob>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<Vec<TokenLogprob>>,
+}
+
+impl ChoiceLogprobs {
+    /// Appends the tokens of a later fragment, as streamed chunks carry only
+    /// the logprobs of their own delta.
+    pub fn extend(&mut self, other: ChoiceLogprobs) {
+        for (ours, theirs) in [
+            (&mut self.content, other.content),
+            (&mut self.refusal, other.refusal),
+        ] {
+            if let Some(theirs) = theirs {
+                ours.get_or_insert_with(Vec::new).extend(theirs);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    /// UTF-8 bytes of the token, for tokens that split a character.
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -795,15 +1652,364 @@ pub struct Usage {
     pub total_tokens: i32,
 }
 
-// Streaming types - transparent Value wrapper for full passthrough of any backend JSON.
-// This avoids deserialization failures when LLMs send null fields in streaming tool_call
-// deltas or introduce new fields the SDK doesn't know about yet.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Per-1K-token prices for one model, in whatever currency the caller uses.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_1k: f64,
+    pub output_per_1k: f64,
+}
+
+/// Caller-supplied prices keyed by model id. The SDK ships no pricing data.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(transparent)]
-pub struct ChatCompletionChunk(pub Value);
+pub struct PriceTable {
+    pub models: HashMap<String, ModelPrice>,
+}
 
-// Embeddings Types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl PriceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_model(
+        mut self,
+        model: impl Into<String>,
+        input_per_1k: f64,
+        output_per_1k: f64,
+    ) -> Self {
+        self.models.insert(
+            model.into(),
+            ModelPrice {
+                input_per_1k,
+                output_per_1k,
+            },
+        );
+        self
+    }
+
+    /// Prices advertised by the models endpoint. Models without pricing
+    /// annotations are left out.
+    pub fn from_models(models: &[Model]) -> Self {
+        Self {
+            models: models
+                .iter()
+                .filter_map(|model| Some((model.id.clone(), model.price()?)))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, model: &str) -> Option<&ModelPrice> {
+        self.models.get(model)
+    }
+}
+
+/// Strips a Markdown code fence (```json ... ```) around `text`, if present.
+#[cfg(feature = "jsonschema")]
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    let Some(inner) = text.strip_prefix("```") else {
+        return text;
+    };
+    let inner = inner.strip_suffix("```").unwrap_or(inner);
+    // Drop the info string (e.g. `json`) on the opening line.
+    match inner.split_once('\n') {
+        Some((info, body)) if !info.trim_start().starts_with(['{', '[']) => body.trim(),
+        _ => inner.trim(),
+    }
+}
+
+/// Cost of `usage` for `model`, or `None` if the model has no entry in `prices`.
+pub fn estimate_cost(model: &str, usage: &Usage, prices: &PriceTable) -> Option<f64> {
+    let price = prices.get(model)?;
+    let input = f64::from(usage.prompt_tokens) / 1000.0 * price.input_per_1k;
+    let output = f64::from(usage.completion_tokens) / 1000.0 * price.output_per_1k;
+    Some(input + output)
+}
+
+// Streaming types - transparent Value wrapper for full passthrough of any backend JSON.
+// This avoids deserialization failures when LLMs send null fields in streaming tool_call
+// deltas or introduce new fields the SDK doesn't know about yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ChatCompletionChunk(pub Value);
+
+/// What a streaming call does after an event fails to decrypt or parse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StreamErrorPolicy {
+    /// Yield the error, then end the stream. A decryption failure usually means
+    /// the session is broken, so later events cannot be trusted either.
+    #[default]
+    AbortOnError,
+    /// Log the error and continue with the next event.
+    SkipErrors,
+}
+
+impl ChatCompletionChunk {
+    /// Text delta of the first choice, if this chunk carries one.
+    pub fn delta_content(&self) -> Option<&str> {
+        self.0
+            .get("choices")?
+            .get(0)?
+            .get("delta")?
+            .get("content")?
+            .as_str()
+    }
+
+    /// Log probabilities of the first choice's delta, when requested.
+    pub fn logprobs(&self) -> Option<ChoiceLogprobs> {
+        self.0
+            .get("choices")?
+            .get(0)?
+            .get("logprobs")
+            .filter(|logprobs| !logprobs.is_null())
+            .and_then(|logprobs| serde_json::from_value(logprobs.clone()).ok())
+    }
+
+    /// Parsed `usage` block, present on the final chunk when usage reporting is on.
+    pub fn usage(&self) -> Option<Usage> {
+        self.0
+            .get("usage")
+            .filter(|usage| !usage.is_null())
+            .and_then(|usage| serde_json::from_value(usage.clone()).ok())
+    }
+
+    /// True for chunks that report usage but carry no choices, such as the
+    /// trailing usage chunk some backends send after the last delta.
+    pub fn is_usage_only(&self) -> bool {
+        let no_choices = self
+            .0
+            .get("choices")
+            .and_then(Value::as_array)
+            .is_none_or(|choices| choices.is_empty());
+        no_choices && self.usage().is_some()
+    }
+}
+
+/// Merges the tool-call deltas of a chat completion stream into complete
+/// [`ToolCall`]s. Feed it every chunk of the stream; deltas are keyed by their
+/// `index`, so several calls may be interleaved.
+///
+/// ```ignore
+/// let mut tool_calls = ToolCallAccumulator::new();
+/// while let Some(chunk) = stream.next().await {
+///     tool_calls.push(&chunk?);
+/// }
+/// for call in tool_calls.completed_calls() { /* ... */ }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallAccumulator {
+    calls: BTreeMap<i64, ToolCall>,
+    finish_reason: Option<String>,
+}
+
+impl ToolCallAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges the tool-call deltas and finish reason of the first choice.
+    pub fn push(&mut self, chunk: &ChatCompletionChunk) {
+        let Some(choice) = chunk.0.get("choices").and_then(|choices| choices.get(0)) else {
+            return;
+        };
+
+        if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+            self.finish_reason = Some(reason.to_string());
+        }
+
+        if let Some(delta) = choice.get("delta") {
+            self.push_delta(delta);
+        }
+    }
+
+    /// Merges the `tool_calls` fragments of one choice's `delta` object, for
+    /// callers that pick choices out of the chunks themselves. Argument
+    /// strings are appended per `index`; ids, types and names are kept from
+    /// whichever fragment carries them.
+    pub fn push_delta(&mut self, delta: &Value) {
+        let deltas = delta.get("tool_calls").and_then(Value::as_array);
+        for delta in deltas.into_iter().flatten() {
+            let index = delta.get("index").and_then(Value::as_i64).unwrap_or(0);
+            let call = self.calls.entry(index).or_insert_with(|| ToolCall {
+                id: String::new(),
+                tool_type: "function".to_string(),
+                function: FunctionCall {
+                    name: String::new(),
+                    arguments: String::new(),
+                },
+                index: Some(index as i32),
+            });
+
+            if let Some(id) = delta.get("id").and_then(Value::as_str) {
+                if !id.is_empty() {
+                    call.id = id.to_string();
+                }
+            }
+            if let Some(tool_type) = delta.get("type").and_then(Value::as_str) {
+                call.tool_type = tool_type.to_string();
+            }
+            if let Some(function) = delta.get("function") {
+                // Some backends repeat the full name on every delta.
+                if let Some(name) = function.get("name").and_then(Value::as_str) {
+                    if call.function.name != name {
+                        call.function.name.push_str(name);
+                    }
+                }
+                if let Some(arguments) = function.get("arguments").and_then(Value::as_str) {
+                    call.function.arguments.push_str(arguments);
+                }
+            }
+        }
+    }
+
+    /// Last finish reason seen on the stream.
+    pub fn finish_reason(&self) -> Option<&str> {
+        self.finish_reason.as_deref()
+    }
+
+    /// True once the stream finished with `finish_reason == "tool_calls"`.
+    pub fn is_complete(&self) -> bool {
+        self.finish_reason() == Some("tool_calls")
+    }
+
+    /// The merged calls in index order, or an empty list until the stream has
+    /// finished with `tool_calls`.
+    pub fn completed_calls(&self) -> Vec<ToolCall> {
+        if !self.is_complete() {
+            return Vec::new();
+        }
+        self.calls.values().cloned().collect()
+    }
+
+    /// Every call merged so far in index order, whatever the finish reason.
+    pub fn finish(self) -> Vec<ToolCall> {
+        self.calls.into_values().collect()
+    }
+}
+
+/// Folds the chunks of a streamed chat completion into the
+/// [`ChatCompletionResponse`] a non-streaming request would have returned:
+/// deltas are grouped by choice index, content and reasoning concatenated,
+/// tool calls merged with [`ToolCallAccumulator`], and the last reported
+/// usage kept.
+#[derive(Debug, Clone, Default)]
+pub struct ChatCompletionCollector {
+    id: String,
+    created: i64,
+    model: String,
+    choices: BTreeMap<i64, ChoiceCollector>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ChoiceCollector {
+    role: Option<String>,
+    content: String,
+    reasoning_content: String,
+    tool_calls: ToolCallAccumulator,
+    finish_reason: Option<String>,
+    logprobs: Option<ChoiceLogprobs>,
+}
+
+impl ChoiceCollector {
+    fn push(&mut self, choice: &Value) {
+        if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+            self.finish_reason = Some(reason.to_string());
+        }
+        let logprobs = choice
+            .get("logprobs")
+            .filter(|logprobs| !logprobs.is_null())
+            .and_then(|logprobs| serde_json::from_value::<ChoiceLogprobs>(logprobs.clone()).ok());
+        if let Some(logprobs) = logprobs {
+            self.logprobs
+                .get_or_insert_with(Default::default)
+                .extend(logprobs);
+        }
+        let Some(delta) = choice.get("delta") else {
+            return;
+        };
+        self.tool_calls.push_delta(delta);
+        if let Some(role) = delta.get("role").and_then(Value::as_str) {
+            self.role = Some(role.to_string());
+        }
+        if let Some(content) = delta.get("content").and_then(Value::as_str) {
+            self.content.push_str(content);
+        }
+        if let Some(reasoning) = delta.get("reasoning_content").and_then(Value::as_str) {
+            self.reasoning_content.push_str(reasoning);
+        }
+    }
+
+    fn finish(self, index: i64) -> ChatChoice {
+        let tool_calls = self.tool_calls.finish();
+        let message = ChatMessage {
+            role: self.role.unwrap_or_else(|| "assistant".to_string()),
+            content: Value::String(self.content),
+            tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+            reasoning_content: (!self.reasoning_content.is_empty())
+                .then_some(self.reasoning_content),
+        };
+        ChatChoice {
+            index: index as i32,
+            message,
+            finish_reason: self.finish_reason,
+            logprobs: self.logprobs,
+        }
+    }
+}
+
+impl ChatCompletionCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chunk: &ChatCompletionChunk) {
+        let text = |key: &str| chunk.0.get(key).and_then(Value::as_str);
+        if let Some(id) = text("id").filter(|id| !id.is_empty()) {
+            self.id = id.to_string();
+        }
+        if let Some(model) = text("model").filter(|model| !model.is_empty()) {
+            self.model = model.to_string();
+        }
+        if let Some(created) = chunk.0.get("created").and_then(Value::as_i64) {
+            self.created = created;
+        }
+        if let Some(usage) = chunk.usage() {
+            self.usage = Some(usage);
+        }
+
+        let choices = chunk.0.get("choices").and_then(Value::as_array);
+        for choice in choices.into_iter().flatten() {
+            let index = choice.get("index").and_then(Value::as_i64).unwrap_or(0);
+            self.choices.entry(index).or_default().push(choice);
+        }
+    }
+
+    /// The aggregated response, with one choice per index seen (at least one,
+    /// at index 0, even if no choice arrived) in index order.
+    pub fn finish(mut self) -> ChatCompletionResponse {
+        if self.choices.is_empty() {
+            self.choices.insert(0, ChoiceCollector::default());
+        }
+        let choices = self
+            .choices
+            .into_iter()
+            .map(|(index, choice)| choice.finish(index))
+            .collect();
+
+        ChatCompletionResponse {
+            id: self.id,
+            object: "chat.completion".to_string(),
+            created: self.created,
+            model: self.model,
+            choices,
+            usage: self.usage,
+        }
+    }
+}
+
+// Embeddings Types
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingRequest {
     pub input: EmbeddingInput,
     #[serde(default = "default_embedding_model")]
@@ -857,15 +2063,225 @@ pub struct EmbeddingResponse {
 pub struct EmbeddingData {
     pub object: String,
     pub index: i32,
+    /// Accepts both a float array and, for `encoding_format: "base64"`, a
+    /// base64 string of little-endian `f32`s.
+    #[serde(deserialize_with = "deserialize_embedding")]
     pub embedding: Vec<f64>,
 }
 
+fn deserialize_embedding<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::de::Error as _;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Float(Vec<f64>),
+        Base64(String),
+    }
+
+    match Encoded::deserialize(deserializer)? {
+        Encoded::Float(values) => Ok(values),
+        Encoded::Base64(encoded) => {
+            let bytes = STANDARD.decode(encoded).map_err(D::Error::custom)?;
+            if bytes.len() % 4 != 0 {
+                return Err(D::Error::custom(format!(
+                    "base64 embedding has {} bytes, not a multiple of 4",
+                    bytes.len()
+                )));
+            }
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|chunk| {
+                    f64::from(f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                })
+                .collect())
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingUsage {
     pub prompt_tokens: i32,
     pub total_tokens: i32,
 }
 
+// Speech Types
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeechRequest {
+    pub model: String,
+    pub input: String,
+    pub voice: String,
+    /// Audio encoding such as `mp3`, `opus`, `aac`, `flac`, `wav` or `pcm`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<String>,
+    /// Playback speed, from 0.25 to 4.0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+}
+
+impl SpeechRequest {
+    pub fn new(
+        model: impl Into<String>,
+        input: impl Into<String>,
+        voice: impl Into<String>,
+    ) -> Self {
+        Self {
+            model: model.into(),
+            input: input.into(),
+            voice: voice.into(),
+            response_format: None,
+            speed: None,
+        }
+    }
+}
+
+// Transcription Types
+/// Body of `/v1/audio/transcriptions`. The encrypted channel carries JSON, so
+/// the audio travels base64-encoded in `file` rather than as multipart form
+/// data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptionRequest {
+    /// Base64-encoded audio.
+    pub file: String,
+    pub filename: String,
+    pub content_type: String,
+    pub model: String,
+    /// ISO-639-1 code of the spoken language, e.g. `en`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// `json` (the default), `verbose_json`, `text`, `srt` or `vtt`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptionResponse {
+    /// The transcript, or the whole subtitle file for the `srt` and `vtt`
+    /// formats.
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Audio length in seconds (`verbose_json` only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+}
+
+impl TranscriptionResponse {
+    /// Parses a decrypted response in any of the response formats: a JSON
+    /// object, a JSON string, or plain text.
+    pub(crate) fn from_body(body: &[u8]) -> crate::Result<Self> {
+        if let Ok(response) = serde_json::from_slice::<Self>(body) {
+            return Ok(response);
+        }
+        let text = match serde_json::from_slice::<String>(body) {
+            Ok(text) => text,
+            Err(_) => String::from_utf8(body.to_vec())?,
+        };
+        Ok(Self {
+            text,
+            language: None,
+            duration: None,
+        })
+    }
+}
+
+// Image Types
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageGenerationRequest {
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    /// Dimensions such as `1024x1024`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// `url` or `b64_json`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<String>,
+}
+
+impl ImageGenerationRequest {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            model: None,
+            n: None,
+            size: None,
+            response_format: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageGenerationResponse {
+    pub created: i64,
+    pub data: Vec<ImageData>,
+}
+
+/// One generated image. Exactly one of `b64_json` and `url` is set,
+/// depending on the requested `response_format`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b64_json: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revised_prompt: Option<String>,
+}
+
+// Moderation Types
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationRequest {
+    pub input: EmbeddingInput,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationResponse {
+    pub id: String,
+    pub model: String,
+    /// One result per input, in input order.
+    pub results: Vec<ModerationResult>,
+}
+
+impl ModerationResponse {
+    /// Whether any input was flagged.
+    pub fn flagged(&self) -> bool {
+        self.results.iter().any(|result| result.flagged)
+    }
+}
+
+/// Categories are keyed by their OpenAI names, e.g. `hate`,
+/// `self-harm/intent` or `sexual/minors`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    pub categories: BTreeMap<String, bool>,
+    pub category_scores: BTreeMap<String, f64>,
+}
+
+impl ModerationResult {
+    /// Names of the categories this input was flagged for.
+    pub fn flagged_categories(&self) -> Vec<&str> {
+        self.categories
+            .iter()
+            .filter(|(_, flagged)| **flagged)
+            .map(|(category, _)| category.as_str())
+            .collect()
+    }
+
+    pub fn score(&self, category: &str) -> Option<f64> {
+        self.category_scores.get(category).copied()
+    }
+}
+
 // Agent API Types
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1083,6 +2499,103 @@ pub enum AgentSseEvent {
     Error(AgentErrorEvent),
 }
 
+// Diagnostics Types
+/// Outcome of a single step of a diagnostic run.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticStep {
+    pub name: String,
+    pub passed: bool,
+    pub duration_ms: u64,
+    /// Short result on success, the error message on failure.
+    pub detail: String,
+}
+
+/// What `OpenSecretClient::perform_handshake_detailed` verified and
+/// negotiated. The client stores the same session and document.
+#[derive(Debug, Clone)]
+pub struct HandshakeOutcome {
+    pub attestation_document: crate::attestation::AttestationDocument,
+    pub session_id: Uuid,
+    /// Whether the document was accepted as a mock rather than verified
+    /// against the AWS Nitro root (local servers only).
+    pub mock_attestation: bool,
+    /// Whether certificate-chain verification was skipped because the
+    /// document was signed like one in an
+    /// [`AttestationCache`](crate::attestation::AttestationCache).
+    pub cached_attestation: bool,
+}
+
+/// Report produced by `OpenSecretClient::diagnose`. Serializes to JSON and
+/// prints as one line per step, ready to paste into a support ticket.
+#[derive(Debug, Serialize)]
+pub struct Diagnostics {
+    pub base_url: String,
+    pub mock_attestation: bool,
+    pub module_id: Option<String>,
+    pub steps: Vec<DiagnosticStep>,
+    /// The error that stopped the run, if any.
+    #[serde(skip)]
+    pub first_error: Option<crate::Error>,
+}
+
+impl Diagnostics {
+    pub fn passed(&self) -> bool {
+        self.first_error.is_none()
+    }
+
+    /// Records a step and returns whether the run should continue.
+    pub(crate) fn record(
+        &mut self,
+        name: &str,
+        started: std::time::Instant,
+        result: crate::Result<String>,
+    ) -> bool {
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(e) => {
+                let detail = e.to_string();
+                self.first_error = Some(e);
+                (false, detail)
+            }
+        };
+        self.steps.push(DiagnosticStep {
+            name: name.to_string(),
+            passed,
+            duration_ms,
+            detail,
+        });
+        passed
+    }
+}
+
+impl std::fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.mock_attestation {
+            writeln!(
+                f,
+                "WARNING: attestation is mocked; the server's identity is NOT verified"
+            )?;
+        }
+        writeln!(f, "base_url: {}", self.base_url)?;
+        writeln!(f, "mock_attestation: {}", self.mock_attestation)?;
+        if let Some(module_id) = &self.module_id {
+            writeln!(f, "module_id: {}", module_id)?;
+        }
+        for step in &self.steps {
+            writeln!(
+                f,
+                "[{}] {} ({} ms): {}",
+                if step.passed { "ok" } else { "FAIL" },
+                step.name,
+                step.duration_ms,
+                step.detail
+            )?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1134,6 +2647,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn word_count_builds_bip85_paths_and_rejects_unsupported_counts() {
+        assert_eq!(
+            WordCount::try_from(24).unwrap().bip85_path(0).unwrap(),
+            "m/83696968'/39'/0'/24'/0'"
+        );
+        assert_eq!(
+            WordCount::Eighteen.bip85_path(3).unwrap(),
+            "m/83696968'/39'/0'/18'/3'"
+        );
+        assert!(matches!(
+            WordCount::try_from(15),
+            Err(crate::Error::Configuration(_))
+        ));
+        assert!(WordCount::Twelve.bip85_path(0x8000_0000).is_err());
+    }
+
     #[test]
     fn credential_update_response_tolerates_missing_message() {
         let response: CredentialUpdateResponse =
@@ -1143,4 +2673,730 @@ mod tests {
         assert_eq!(response.access_token.as_deref(), Some("new-access"));
         assert_eq!(response.refresh_token, None);
     }
+
+    #[test]
+    fn chat_message_text_content_handles_string_and_part_arrays() {
+        let message: ChatMessage = serde_json::from_value(json!({
+            "role": "assistant",
+            "content": "hello"
+        }))
+        .unwrap();
+        assert_eq!(message.text_content().as_deref(), Some("hello"));
+
+        let message: ChatMessage = serde_json::from_value(json!({
+            "role": "assistant",
+            "content": [
+                { "type": "text", "text": "hello " },
+                { "type": "image_url", "image_url": { "url": "https://example.com/a.png" } },
+                { "type": "text", "text": "world" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(message.text_content().as_deref(), Some("hello world"));
+
+        let message: ChatMessage = serde_json::from_value(json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": []
+        }))
+        .unwrap();
+        assert_eq!(message.text_content(), None);
+    }
+
+    #[test]
+    fn price_table_estimates_completion_cost() {
+        let prices = PriceTable::new().with_model("llama-3.3-70b", 0.5, 1.5);
+        let usage = Usage {
+            prompt_tokens: 2000,
+            completion_tokens: 500,
+            total_tokens: 2500,
+        };
+
+        assert_eq!(estimate_cost("llama-3.3-70b", &usage, &prices), Some(1.75));
+        assert_eq!(estimate_cost("unknown-model", &usage, &prices), None);
+
+        let response: ChatCompletionResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "llama-3.3-70b",
+            "choices": [],
+            "usage": { "prompt_tokens": 2000, "completion_tokens": 500, "total_tokens": 2500 }
+        }))
+        .unwrap();
+        assert_eq!(response.cost(&prices), Some(1.75));
+    }
+
+    #[test]
+    fn chat_completion_chunk_detects_usage_only_chunks() {
+        let usage_only = ChatCompletionChunk(json!({
+            "id": "chatcmpl-1",
+            "choices": [],
+            "usage": { "prompt_tokens": 3, "completion_tokens": 4, "total_tokens": 7 }
+        }));
+        assert!(usage_only.is_usage_only());
+        assert_eq!(usage_only.usage().unwrap().total_tokens, 7);
+
+        let heartbeat = ChatCompletionChunk(json!({ "id": "chatcmpl-1", "choices": [] }));
+        assert!(!heartbeat.is_usage_only());
+        assert!(heartbeat.usage().is_none());
+
+        let delta = ChatCompletionChunk(json!({
+            "choices": [{ "index": 0, "delta": { "content": "hi" } }],
+            "usage": null
+        }));
+        assert!(!delta.is_usage_only());
+    }
+
+    #[test]
+    fn chat_completion_request_flattens_extra_and_rejects_collisions() {
+        let mut request = ChatCompletionRequest {
+            model: "llama-3.3-70b".to_string(),
+            messages: vec![],
+            extra: Some(
+                json!({ "top_k": 40, "repetition_penalty": 1.1 })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ),
+            ..Default::default()
+        };
+
+        assert!(request.validate_extra().is_ok());
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({
+                "model": "llama-3.3-70b",
+                "messages": [],
+                "top_k": 40,
+                "repetition_penalty": 1.1
+            })
+        );
+
+        request
+            .extra
+            .as_mut()
+            .unwrap()
+            .insert("temperature".to_string(), json!(2));
+        assert!(matches!(
+            request.validate_extra(),
+            Err(crate::Error::Configuration(message)) if message.contains("temperature")
+        ));
+    }
+
+    #[test]
+    fn private_key_response_splits_and_validates_words() {
+        let response = PrivateKeyResponse {
+            mnemonic: "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about".to_string(),
+        };
+        assert_eq!(response.word_count(), 12);
+        assert_eq!(response.words()[11], "about");
+        assert!(response.validate().is_ok());
+
+        let bad_checksum = PrivateKeyResponse {
+            mnemonic: ["abandon"; 12].join(" "),
+        };
+        assert!(bad_checksum.validate().is_err());
+
+        let unknown_word = PrivateKeyResponse {
+            mnemonic: "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon bitcoin".to_string(),
+        };
+        assert!(matches!(
+            unknown_word.validate(),
+            Err(crate::Error::Crypto(message)) if message.contains("word 12")
+        ));
+    }
+
+    #[test]
+    fn key_options_validate_depth_counts_path_components() {
+        let options = KeyOptions {
+            private_key_derivation_path: Some("m/44'/0'/0'/0/5".to_string()),
+            seed_phrase_derivation_path: Some("m/83696968'/39'/0'/12'/0'".to_string()),
+        };
+        assert!(options.validate_depth(5).is_ok());
+        assert!(matches!(
+            options.validate_depth(4),
+            Err(crate::Error::Configuration(_))
+        ));
+        assert_eq!(derivation_path_depth("m"), 0);
+        assert_eq!(derivation_path_depth("m/0'/1'/2'"), 3);
+    }
+
+    #[test]
+    fn health_status_parses_known_payload_shapes() {
+        let plain = HealthStatus::parse("OK\n");
+        assert_eq!(plain.status, "ok");
+        assert!(plain.is_healthy());
+
+        let json_status =
+            HealthStatus::parse(r#"{"status":"healthy","timestamp":"2024-01-01T00:00:00Z"}"#);
+        assert!(json_status.is_healthy());
+        assert_eq!(
+            json_status.timestamp.unwrap().to_rfc3339(),
+            "2024-01-01T00:00:00+00:00"
+        );
+
+        let version_blob = HealthStatus::parse(r#"{"version":"3.2.0","timestamp":1704067200}"#);
+        assert_eq!(version_blob.status, "ok");
+        assert_eq!(version_blob.version.as_deref(), Some("3.2.0"));
+        assert_eq!(version_blob.timestamp.unwrap().timestamp(), 1_704_067_200);
+
+        let unknown = HealthStatus::parse("<html><body>502 Bad Gateway</body></html>");
+        assert!(unknown.is_unknown());
+        assert!(!unknown.is_healthy());
+
+        let with_enclave = HealthStatus::parse_json(
+            r#"{"status":"ok","enclave":{"platform":"aws-nitro","pcr0":"abc"}}"#,
+        )
+        .unwrap();
+        assert_eq!(with_enclave.enclave.unwrap()["platform"], "aws-nitro");
+        assert!(matches!(
+            HealthStatus::parse_json("OK"),
+            Err(crate::Error::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_tool_call_accumulator_merges_interleaved_calls() {
+        let chunks = [
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "id": "call_a", "type": "function",
+                 "function": {"name": "get_weather", "arguments": ""}}
+            ]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 1, "id": "call_b", "type": "function",
+                 "function": {"name": "get_time", "arguments": "{\"tz\":"}}
+            ]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "id": null, "function": {"arguments": "{\"city\":"}}
+            ]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 1, "function": {"name": null, "arguments": "\"UTC\"}"}},
+                {"index": 0, "function": {"arguments": "\"Paris\"}"}}
+            ]}}]}),
+            json!({"choices": [{"index": 0, "delta": {}, "finish_reason": null}]}),
+        ];
+
+        let mut accumulator = ToolCallAccumulator::new();
+        for chunk in &chunks {
+            accumulator.push(&ChatCompletionChunk(chunk.clone()));
+        }
+        assert!(!accumulator.is_complete());
+        assert!(accumulator.completed_calls().is_empty());
+
+        accumulator.push(&ChatCompletionChunk(json!({
+            "choices": [{"index": 0, "delta": {}, "finish_reason": "tool_calls"}]
+        })));
+
+        let calls = accumulator.completed_calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_a");
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, "{\"city\":\"Paris\"}");
+        assert_eq!(calls[1].id, "call_b");
+        assert_eq!(calls[1].function.name, "get_time");
+        assert_eq!(calls[1].function.arguments, "{\"tz\":\"UTC\"}");
+    }
+
+    #[test]
+    fn test_tool_call_accumulator_push_delta() {
+        // Deltas as they arrive from a streamed completion: the first fragment
+        // of each call carries its id and name, later ones only arguments.
+        let deltas = [
+            json!({"role": "assistant", "content": null, "tool_calls": [
+                {"index": 0, "id": "call_9xT", "type": "function",
+                 "function": {"name": "search_flights", "arguments": ""}}
+            ]}),
+            json!({"tool_calls": [{"index": 0, "function": {"arguments": "{\"from\""}}]}),
+            json!({"tool_calls": [{"index": 0, "function": {"arguments": ": \"SFO\", "}}]}),
+            json!({"tool_calls": [{"index": 0, "function": {"arguments": "\"to\": \"JFK\"}"}}]}),
+            json!({"tool_calls": [
+                {"index": 1, "id": "call_4kQ", "type": "function",
+                 "function": {"name": "get_weather", "arguments": "{\"city\""}}
+            ]}),
+            json!({"tool_calls": [{"index": 1, "function": {"arguments": ": \"New York\"}"}}]}),
+            json!({}),
+        ];
+
+        let mut accumulator = ToolCallAccumulator::new();
+        for delta in &deltas {
+            accumulator.push_delta(delta);
+        }
+
+        let calls = accumulator.finish();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_9xT");
+        assert_eq!(calls[0].tool_type, "function");
+        assert_eq!(calls[0].function.name, "search_flights");
+        assert_eq!(
+            calls[0].function.arguments,
+            "{\"from\": \"SFO\", \"to\": \"JFK\"}"
+        );
+        assert_eq!(calls[1].id, "call_4kQ");
+        assert_eq!(calls[1].function.name, "get_weather");
+        assert_eq!(calls[1].function.arguments, "{\"city\": \"New York\"}");
+    }
+
+    #[test]
+    fn test_image_generation_round_trip() {
+        let mut request = ImageGenerationRequest::new("a lighthouse at dusk");
+        request.n = Some(2);
+        request.size = Some("512x512".to_string());
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"prompt": "a lighthouse at dusk", "n": 2, "size": "512x512"})
+        );
+
+        let body = json!({
+            "created": 1_700_000_000,
+            "data": [
+                {"b64_json": "iVBORw0KGgo=", "revised_prompt": "a lighthouse at dusk, oil painting"},
+                {"url": "https://example.com/image.png"}
+            ]
+        });
+        let response: ImageGenerationResponse = serde_json::from_value(body.clone()).unwrap();
+        assert_eq!(response.data[0].b64_json.as_deref(), Some("iVBORw0KGgo="));
+        assert!(response.data[0].url.is_none());
+        assert_eq!(
+            response.data[1].url.as_deref(),
+            Some("https://example.com/image.png")
+        );
+        assert_eq!(serde_json::to_value(&response).unwrap(), body);
+    }
+
+    #[test]
+    fn test_moderation_response_deserializes() {
+        let response: ModerationResponse = serde_json::from_value(json!({
+            "id": "modr-5MWoLO",
+            "model": "omni-moderation-latest",
+            "results": [
+                {
+                    "flagged": true,
+                    "categories": {
+                        "harassment": false,
+                        "hate": false,
+                        "self-harm/intent": true,
+                        "violence": true
+                    },
+                    "category_scores": {
+                        "harassment": 0.0012,
+                        "hate": 0.0003,
+                        "self-harm/intent": 0.91,
+                        "violence": 0.67
+                    }
+                },
+                {
+                    "flagged": false,
+                    "categories": {"harassment": false, "hate": false},
+                    "category_scores": {"harassment": 0.0001, "hate": 0.0}
+                }
+            ]
+        }))
+        .unwrap();
+
+        assert!(response.flagged());
+        assert_eq!(response.results.len(), 2);
+        let first = &response.results[0];
+        assert!(first.flagged);
+        assert_eq!(first.flagged_categories(), ["self-harm/intent", "violence"]);
+        assert_eq!(first.score("self-harm/intent"), Some(0.91));
+        assert_eq!(first.score("sexual"), None);
+        assert!(!response.results[1].flagged);
+        assert!(response.results[1].flagged_categories().is_empty());
+
+        let request = ModerationRequest {
+            input: vec!["one".to_string(), "two".to_string()].into(),
+            model: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"input": ["one", "two"]})
+        );
+    }
+
+    #[test]
+    fn test_response_format_serialization() {
+        let mut request = ChatCompletionRequest::new("m", vec![]);
+        assert!(serde_json::to_value(&request)
+            .unwrap()
+            .get("response_format")
+            .is_none());
+
+        request.response_format = Some(ResponseFormat::Text);
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["response_format"],
+            json!({"type": "text"})
+        );
+
+        request.response_format = Some(ResponseFormat::JsonObject);
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["response_format"],
+            json!({"type": "json_object"})
+        );
+
+        let schema = json!({"type": "object", "properties": {"ok": {"type": "boolean"}}});
+        let mut format = ResponseFormat::json_schema("result", schema.clone());
+        if let ResponseFormat::JsonSchema { json_schema } = &mut format {
+            json_schema.strict = Some(true);
+        }
+        request.response_format = Some(format.clone());
+        let value = serde_json::to_value(&request).unwrap()["response_format"].clone();
+        assert_eq!(
+            value,
+            json!({
+                "type": "json_schema",
+                "json_schema": {"name": "result", "schema": schema, "strict": true}
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<ResponseFormat>(value).unwrap(),
+            format
+        );
+    }
+
+    #[test]
+    fn test_sampling_parameters_round_trip() {
+        let request = ChatCompletionRequest::new("m", vec![]);
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"model": "m", "messages": []})
+        );
+
+        let mut request = ChatCompletionRequest::new("m", vec![]);
+        request.top_p = Some(0.5);
+        request.frequency_penalty = Some(0.25);
+        request.presence_penalty = Some(-1.0);
+        request.stop = Some(vec!["\n\n".to_string(), "END".to_string()].into());
+        request.seed = Some(42);
+        let value = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "model": "m",
+                "messages": [],
+                "top_p": 0.5,
+                "frequency_penalty": 0.25,
+                "presence_penalty": -1.0,
+                "stop": ["\n\n", "END"],
+                "seed": 42
+            })
+        );
+
+        let parsed: ChatCompletionRequest = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.top_p, Some(0.5));
+        assert_eq!(parsed.frequency_penalty, Some(0.25));
+        assert_eq!(parsed.presence_penalty, Some(-1.0));
+        assert_eq!(
+            parsed.stop,
+            Some(StopSequence::Multiple(vec![
+                "\n\n".to_string(),
+                "END".to_string()
+            ]))
+        );
+        assert_eq!(parsed.seed, Some(42));
+        assert!(parsed.extra.unwrap_or_default().is_empty());
+
+        request.stop = Some("STOP".into());
+        let value = serde_json::to_value(&request).unwrap();
+        assert_eq!(value["stop"], json!("STOP"));
+        let parsed: ChatCompletionRequest = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.stop, Some(StopSequence::Single("STOP".to_string())));
+    }
+
+    #[test]
+    fn test_collector_groups_choices_by_index() {
+        let chunks = [
+            json!({"id": "chatcmpl-n2", "created": 7, "model": "m", "choices": [
+                {"index": 0, "delta": {"role": "assistant", "content": ""}},
+                {"index": 1, "delta": {"role": "assistant", "content": ""}}
+            ]}),
+            json!({"id": "chatcmpl-n2", "choices": [
+                {"index": 1, "delta": {"content": "Bonjour"}},
+                {"index": 0, "delta": {"content": "Hello"}}
+            ]}),
+            json!({"id": "chatcmpl-n2", "choices": [
+                {"index": 0, "delta": {"content": ", world"}, "finish_reason": "stop"}
+            ]}),
+            json!({"id": "chatcmpl-n2", "choices": [
+                {"index": 1, "delta": {"content": " le monde"}},
+            ]}),
+            json!({"id": "chatcmpl-n2", "choices": [
+                {"index": 1, "delta": {}, "finish_reason": "length"}
+            ]}),
+            json!({"id": "chatcmpl-n2", "choices": [],
+                   "usage": {"prompt_tokens": 5, "completion_tokens": 6, "total_tokens": 11}}),
+        ];
+
+        let mut collector = ChatCompletionCollector::new();
+        for chunk in &chunks {
+            collector.push(&ChatCompletionChunk(chunk.clone()));
+        }
+        let response = collector.finish();
+
+        assert_eq!(response.id, "chatcmpl-n2");
+        assert_eq!(response.choices.len(), 2);
+        assert_eq!(response.choices[0].index, 0);
+        assert_eq!(response.choices[0].message.content, json!("Hello, world"));
+        assert_eq!(response.choices[0].finish_reason.as_deref(), Some("stop"));
+        assert_eq!(response.choices[1].index, 1);
+        assert_eq!(
+            response.choices[1].message.content,
+            json!("Bonjour le monde")
+        );
+        assert_eq!(response.choices[1].finish_reason.as_deref(), Some("length"));
+        assert_eq!(response.usage.unwrap().total_tokens, 11);
+    }
+
+    #[test]
+    fn test_logprobs_parse_and_collect() {
+        let request = ChatCompletionRequest::new("m", vec![]);
+        assert!(serde_json::to_value(&request)
+            .unwrap()
+            .get("logprobs")
+            .is_none());
+
+        let choice: ChatChoice = serde_json::from_value(json!({
+            "index": 0,
+            "message": {"role": "assistant", "content": "Hi"},
+            "finish_reason": "stop"
+        }))
+        .unwrap();
+        assert!(choice.logprobs.is_none());
+        assert!(serde_json::to_value(&choice)
+            .unwrap()
+            .get("logprobs")
+            .is_none());
+
+        let token = |token: &str, logprob: f64| {
+            json!({
+                "token": token,
+                "logprob": logprob,
+                "bytes": token.as_bytes(),
+                "top_logprobs": [{"token": token, "logprob": logprob, "bytes": null}]
+            })
+        };
+        let chunks = [
+            json!({"choices": [{"index": 0, "delta": {"content": "Hel"},
+                                "logprobs": {"content": [token("Hel", -0.1)], "refusal": null}}]}),
+            json!({"choices": [{"index": 0, "delta": {"content": "lo"},
+                                "logprobs": {"content": [token("lo", -0.25)]}}]}),
+            json!({"choices": [{"index": 0, "delta": {}, "logprobs": null,
+                                "finish_reason": "stop"}]}),
+        ];
+
+        let first = ChatCompletionChunk(chunks[0].clone()).logprobs().unwrap();
+        let tokens = first.content.unwrap();
+        assert_eq!(tokens[0].token, "Hel");
+        assert_eq!(tokens[0].bytes.as_deref(), Some(&b"Hel"[..]));
+        assert_eq!(tokens[0].top_logprobs[0].logprob, -0.1);
+        assert!(first.refusal.is_none());
+
+        let mut collector = ChatCompletionCollector::new();
+        for chunk in &chunks {
+            collector.push(&ChatCompletionChunk(chunk.clone()));
+        }
+        let response = collector.finish();
+        let content = response.choices[0]
+            .logprobs
+            .as_ref()
+            .and_then(|logprobs| logprobs.content.as_ref())
+            .unwrap();
+        let tokens: Vec<&str> = content.iter().map(|t| t.token.as_str()).collect();
+        assert_eq!(tokens, ["Hel", "lo"]);
+        assert_eq!(content[1].logprob, -0.25);
+    }
+
+    #[test]
+    fn test_multimodal_message_builders() {
+        let message = ChatMessage::user_text("Describe this");
+        assert_eq!(message.role, "user");
+        assert_eq!(
+            message.content,
+            json!([{"type": "text", "text": "Describe this"}])
+        );
+
+        let message = ChatMessage::user_with_image("What is it?", "https://example.com/cat.jpg");
+        assert_eq!(
+            message.content,
+            json!([
+                {"type": "text", "text": "What is it?"},
+                {"type": "image_url", "image_url": {"url": "https://example.com/cat.jpg"}}
+            ])
+        );
+        assert_eq!(message.text_content().as_deref(), Some("What is it?"));
+
+        let png_header = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        let message = ChatMessage::user_with_image_bytes("And this?", &png_header, "image/png");
+        assert_eq!(
+            message.content[1]["image_url"]["url"],
+            json!("data:image/png;base64,iVBORw0KGgo=")
+        );
+    }
+
+    #[test]
+    fn test_embedding_accepts_float_and_base64() {
+        let floats: EmbeddingData = serde_json::from_value(json!({
+            "object": "embedding", "index": 0, "embedding": [0.5, -1.25, 2.0]
+        }))
+        .unwrap();
+        assert_eq!(floats.embedding, [0.5, -1.25, 2.0]);
+
+        // [0.5, -1.25, 2.0] as little-endian f32s.
+        let encoded: EmbeddingData = serde_json::from_value(json!({
+            "object": "embedding", "index": 0, "embedding": "AAAAPwAAoL8AAABA"
+        }))
+        .unwrap();
+        assert_eq!(encoded.embedding, floats.embedding);
+
+        assert!(serde_json::from_value::<EmbeddingData>(json!({
+            "object": "embedding", "index": 0, "embedding": "AAAAPwA="
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_model_pricing_annotations() {
+        let response: ModelsResponse = serde_json::from_value(json!({
+            "object": "list",
+            "data": [
+                {"id": "a", "input_price": 0.5, "output_price": "1.5", "max_tokens": 8192},
+                {"id": "b", "pricing": {"prompt": "0.000002", "completion": "0.000004"},
+                 "context_length": 128000},
+                {"id": "c", "owned_by": "someone"}
+            ]
+        }))
+        .unwrap();
+
+        let a = &response.data[0];
+        assert_eq!(a.input_price(), Some(0.5));
+        assert_eq!(a.output_price(), Some(1.5));
+        assert_eq!(a.max_tokens(), Some(8192));
+
+        let b = &response.data[1];
+        assert!((b.input_price().unwrap() - 0.002).abs() < 1e-12);
+        assert_eq!(b.max_tokens(), Some(128000));
+
+        let c = &response.data[2];
+        assert!(c.price().is_none());
+        assert!(c.max_tokens().is_none());
+
+        let prices = PriceTable::from_models(&response.data);
+        assert_eq!(prices.models.len(), 2);
+        assert!(prices.get("c").is_none());
+
+        let serialized = serde_json::to_value(a).unwrap();
+        assert_eq!(serialized["input_price"], 0.5);
+    }
+
+    #[test]
+    fn test_tool_function_from_schema_string() {
+        let tool = Tool::function(
+            "get_weather",
+            "Look up the weather",
+            r#"{"type": "object", "properties": {"city": {"type": "string"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(tool.tool_type, "function");
+        assert_eq!(tool.function.name, "get_weather");
+        assert_eq!(
+            tool.function.description.as_deref(),
+            Some("Look up the weather")
+        );
+        assert_eq!(
+            tool.function.parameters["properties"]["city"]["type"],
+            "string"
+        );
+
+        for schema in [
+            r#"{"type": "object""#,
+            r#"["object"]"#,
+            r#"{"properties": {}}"#,
+        ] {
+            let error = Tool::function("bad", "", schema).unwrap_err();
+            assert!(matches!(error, crate::Error::Configuration(_)));
+        }
+    }
+
+    #[test]
+    fn test_truncate_to_fit_keeps_system_and_tool_pairs() {
+        let tool_call = ChatMessage {
+            role: "assistant".to_string(),
+            content: Value::Null,
+            tool_calls: Some(vec![ToolCall {
+                id: "call_1".to_string(),
+                tool_type: "function".to_string(),
+                function: FunctionCall {
+                    name: "lookup".to_string(),
+                    arguments: "{}".to_string(),
+                },
+                index: None,
+            }]),
+            reasoning_content: None,
+        };
+        let messages = vec![
+            ChatMessage::system("You are terse."),
+            ChatMessage::user("x".repeat(400)),
+            tool_call,
+            ChatMessage::new("tool", "y".repeat(400)),
+            ChatMessage::user("What now?"),
+        ];
+
+        let everything: usize = messages.iter().map(ChatMessage::estimated_tokens).sum();
+        assert_eq!(truncate_to_fit(&messages, everything, 0).len(), 5);
+
+        // Dropping the first user message is not enough; the tool pair goes too.
+        let kept = truncate_to_fit(&messages, everything - 120, 0);
+        let roles: Vec<&str> = kept.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user"]);
+        assert_eq!(kept[1].text_content().as_deref(), Some("What now?"));
+
+        // The newest exchange survives even when nothing fits.
+        assert_eq!(truncate_to_fit(&messages, 10, 100).len(), 2);
+    }
+
+    #[cfg(feature = "jsonschema")]
+    #[test]
+    fn test_validate_against_schema() {
+        let response = |content: &str| ChatCompletionResponse {
+            id: "chatcmpl-test".to_string(),
+            object: "chat.completion".to_string(),
+            created: 1,
+            model: "test".to_string(),
+            choices: vec![ChatChoice {
+                index: 0,
+                message: ChatMessage::assistant(content),
+                finish_reason: Some("stop".to_string()),
+                logprobs: None,
+            }],
+            usage: None,
+        };
+        let schema = json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": "person",
+                "schema": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string" }, "age": { "type": "integer" } },
+                    "required": ["name", "age"]
+                }
+            }
+        });
+
+        let value = response(r#"{"name": "Ada", "age": 36}"#)
+            .validate_against_schema(&schema)
+            .unwrap();
+        assert_eq!(value["name"], "Ada");
+
+        let fenced = response("```json\n{\"name\": \"Ada\", \"age\": 36}\n```");
+        assert!(fenced.validate_against_schema(&schema).is_ok());
+
+        let error = response(r#"{"name": "Ada", "age": "old"}"#)
+            .validate_against_schema(&schema)
+            .unwrap_err();
+        assert!(error.to_string().contains("/age"), "{}", error);
+
+        assert!(response("not json")
+            .validate_against_schema(&schema)
+            .is_err());
+    }
 }
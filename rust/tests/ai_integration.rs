@@ -1,7 +1,7 @@
 use futures::StreamExt;
 use opensecret::{
-    ChatCompletionRequest, ChatMessage, EmbeddingInput, EmbeddingRequest, Error, Function,
-    OpenSecretClient, Result, Tool,
+    ChatCompletionRequest, ChatMessage, ConversationCreateRequest, ConversationsListParams,
+    EmbeddingInput, EmbeddingRequest, Error, Function, OpenSecretClient, Result, Tool,
 };
 use std::env;
 use uuid::Uuid;
@@ -143,9 +143,7 @@ async fn test_chat_completion_streaming() {
         temperature: Some(0.0),
         max_tokens: Some(10),
         stream: Some(true),
-        stream_options: None,
-        tools: None,
-        tool_choice: None,
+        ..Default::default()
     };
 
     let mut stream = match client.create_chat_completion_stream(request).await {
@@ -217,9 +215,7 @@ async fn test_reasoning_content_with_kimi_k2() {
         temperature: Some(0.0),
         max_tokens: Some(100),
         stream: Some(true),
-        stream_options: None,
-        tools: None,
-        tool_choice: None,
+        ..Default::default()
     };
 
     let mut stream = match client.create_chat_completion_stream(request).await {
@@ -276,9 +272,7 @@ async fn test_chat_completion_with_system_message() {
         temperature: Some(0.0),
         max_tokens: Some(10),
         stream: Some(true), // Server only supports streaming
-        stream_options: None,
-        tools: None,
-        tool_choice: None,
+        ..Default::default()
     };
 
     let mut stream = match client.create_chat_completion_stream(request).await {
@@ -313,21 +307,34 @@ async fn test_delete_conversations() {
         .await
         .expect("Failed to setup client");
 
-    // 1. Create a couple of conversations first
-    // Note: Currently the SDK doesn't have create_conversation exposed directly on client struct
-    // but create_chat_completion implicitly creates or uses conversation if we could access it.
-    // However, based on the TS SDK, there's explicit conversation management.
-    // The Rust SDK seems to be catching up.
-    // For now, we'll just call delete_conversations and ensure it returns success,
-    // which covers the API contract even if list is empty.
-
-    // Ideally we would create conversations here, but the Rust SDK client wrapper
-    // doesn't seem to expose explicit conversation creation methods yet based on my earlier grep.
-    // It only has what I added: delete_conversations.
-    // The TS SDK had `createConversation`.
+    let first = client
+        .create_conversation(ConversationCreateRequest {
+            metadata: Some(serde_json::json!({"title": "delete test 1"})),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create conversation");
+    let second = client
+        .create_conversation(ConversationCreateRequest::default())
+        .await
+        .expect("Failed to create conversation");
 
-    // Let's verify what methods OpenSecretClient actually has.
-    // I only added `delete_conversations`.
+    let fetched = client
+        .get_conversation(first.id)
+        .await
+        .expect("Failed to get conversation");
+    assert_eq!(fetched.id, first.id);
+
+    let listed = client
+        .list_conversations(Some(ConversationsListParams {
+            limit: Some(100),
+            ..Default::default()
+        }))
+        .await
+        .expect("Failed to list conversations");
+    let ids: Vec<Uuid> = listed.data.iter().map(|c| c.id).collect();
+    assert!(ids.contains(&first.id));
+    assert!(ids.contains(&second.id));
 
     let result = client
         .delete_conversations()
@@ -336,6 +343,11 @@ async fn test_delete_conversations() {
 
     assert_eq!(result.object, "list.deleted");
     assert!(result.deleted);
+    let remaining = client
+        .list_conversations(None)
+        .await
+        .expect("Failed to list conversations");
+    assert!(remaining.data.is_empty());
 }
 
 #[tokio::test]
@@ -385,12 +397,8 @@ async fn test_guest_user_cannot_use_ai() {
             tool_calls: None,
             reasoning_content: None,
         }],
-        temperature: None,
-        max_tokens: None,
         stream: Some(true), // Server only supports streaming
-        stream_options: None,
-        tools: None,
-        tool_choice: None,
+        ..Default::default()
     };
 
     let completion_result = client.create_chat_completion(request).await;
@@ -564,9 +572,8 @@ async fn test_streaming_multi_tool_calls() {
         temperature: Some(0.0),
         max_tokens: Some(512),
         stream: Some(true),
-        stream_options: None,
         tools: Some(tools),
-        tool_choice: None,
+        ..Default::default()
     };
 
     let mut stream = client
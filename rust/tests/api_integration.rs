@@ -235,7 +235,7 @@ async fn test_private_key_generation() -> Result<()> {
     // Test private key generation without options
     let key_response = client.get_private_key(None).await?;
     assert!(!key_response.mnemonic.is_empty());
-    let word_count = key_response.mnemonic.split_whitespace().count();
+    let word_count = key_response.word_count();
     assert!(word_count == 12 || word_count == 18 || word_count == 24);
     println!(
         "✓ Private key generated successfully ({} words)",
@@ -249,7 +249,7 @@ async fn test_private_key_generation() -> Result<()> {
     };
     let key_with_path = client.get_private_key(Some(options)).await?;
     assert!(!key_with_path.mnemonic.is_empty());
-    assert_eq!(key_with_path.mnemonic.split_whitespace().count(), 24);
+    assert_eq!(key_with_path.word_count(), 24);
     println!("✓ Private key with BIP-85 derivation generated (24 words)");
 
     // Test private key bytes
@@ -158,9 +158,7 @@ async fn test_streaming_chat_with_api_key() -> Result<()> {
         temperature: Some(0.1),
         max_tokens: Some(10),
         stream: Some(true),
-        stream_options: None,
-        tools: None,
-        tool_choice: None,
+        ..Default::default()
     };
 
     let mut stream = match api_client.create_chat_completion_stream(request).await {
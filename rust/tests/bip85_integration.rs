@@ -31,7 +31,7 @@ async fn test_bip85_derive_child_mnemonic() -> Result<()> {
     // Get master mnemonic
     let master_mnemonic = client.get_private_key(None).await?;
     assert!(!master_mnemonic.mnemonic.is_empty());
-    let master_word_count = master_mnemonic.mnemonic.split_whitespace().count();
+    let master_word_count = master_mnemonic.word_count();
     assert!(master_word_count >= 12);
     println!("✓ Master mnemonic has {} words", master_word_count);
 
@@ -42,7 +42,7 @@ async fn test_bip85_derive_child_mnemonic() -> Result<()> {
     };
     let child_mnemonic = client.get_private_key(Some(child_options)).await?;
     assert!(!child_mnemonic.mnemonic.is_empty());
-    assert_eq!(child_mnemonic.mnemonic.split_whitespace().count(), 12);
+    assert_eq!(child_mnemonic.word_count(), 12);
     println!("✓ BIP-85 child mnemonic generated (12 words)");
 
     // Child mnemonic should be different from master
@@ -34,6 +34,28 @@ async fn test_health_check_mock() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_health_status_mock() -> Result<()> {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/health-check"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "status": "healthy",
+            "timestamp": "2024-01-01T00:00:00Z"
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let client = OpenSecretClient::new(mock_server.uri())?;
+    let health = client.health_status().await?;
+
+    assert!(health.is_healthy());
+    assert!(health.timestamp.is_some());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_full_flow_with_real_server() -> Result<()> {
     // Load .env.local from OpenSecret-SDK directory